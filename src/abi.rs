@@ -0,0 +1,210 @@
+use std::io::{Seek, Write};
+
+use crate::bitfield::{Bitfield, BitfieldRepr};
+use crate::deferred::Deferred;
+use crate::endian::{ByteOrder, Endian};
+use crate::fixed::{Fixed, FixedRepr};
+use crate::float::{Bf16, F16};
+use crate::pack::{CodePoint, EndianOverride, FixedLenPack, FixedStr, Guid, IntoPack, Pack, Uuid};
+use crate::{Result, Writer};
+
+/// The alignment rules of a C ABI, for use with
+/// [`Writer::c_struct`](crate::Writer::c_struct).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Abi {
+    /// The x86-64 System V ABI, where each scalar is aligned to its own
+    /// size. This also matches the layout of `#[repr(C)]` structs on
+    /// 64-bit Windows and on AArch64 for the types supported here.
+    SystemV64,
+
+    /// The i386 System V ABI, which is the same as
+    /// [`SystemV64`](Self::SystemV64) except that eight-byte scalars are
+    /// aligned to only four bytes.
+    SystemVI386,
+
+    /// No alignment at all, as with `#[repr(C, packed)]` or
+    /// `__attribute__((packed))`.
+    Packed,
+}
+
+impl Abi {
+    /// Returns the alignment of a scalar (integer or floating point) type
+    /// of the given size under this ABI.
+    pub fn scalar_align(self, size: usize) -> usize {
+        match self {
+            Abi::Packed => 1,
+            Abi::SystemVI386 if size == 8 => 4,
+            _ => size.next_power_of_two().max(1),
+        }
+    }
+}
+
+/// Trait implemented by types that can be written as fields of a C struct
+/// using [`CStruct::field`](CStruct::field), giving their alignment.
+///
+/// Implement this for other types whose packed form matches a C type, such
+/// as a nested struct, so that they can be written as fields too.
+pub trait CLayout: Pack {
+    /// Returns the alignment of the type under the given ABI.
+    fn c_align(abi: Abi) -> usize;
+}
+
+/// A writer for the fields of a C struct, passed to the function given to
+/// [`Writer::c_struct`](crate::Writer::c_struct).
+///
+/// Each field is preceded by whatever padding the selected ABI requires to
+/// align it relative to the start of the struct.
+pub struct CStruct<'w, 'a, W, E>
+where
+    W: Write + Seek,
+    E: ByteOrder,
+{
+    w: &'w mut Writer<'a, W, E>,
+    abi: Abi,
+    start: u64,
+    align: usize,
+}
+
+impl<'w, 'a, W, E> CStruct<'w, 'a, W, E>
+where
+    W: Write + Seek,
+    E: ByteOrder,
+{
+    pub(crate) fn new(w: &'w mut Writer<'a, W, E>, abi: Abi) -> Result<Self> {
+        let start = w.position()?;
+        Ok(Self {
+            w,
+            abi,
+            start,
+            align: 1,
+        })
+    }
+
+    /// Writes the given value as the next field, after any padding needed to
+    /// align it. Returns the number of bytes written for the value itself.
+    pub fn field<V>(&mut self, v: V) -> Result<usize>
+    where
+        V: IntoPack,
+        V::PackType: CLayout,
+    {
+        self.align_field::<V::PackType>()?;
+        self.w.write(v)
+    }
+
+    /// Writes a placeholder for a deferred value as the next field, after
+    /// any padding needed to align it, as with
+    /// [`Writer::write_deferred`](crate::Writer::write_deferred).
+    pub fn field_deferred<T>(&mut self, initial: T) -> Result<Deferred<'a, T>>
+    where
+        T: IntoPack + Clone + 'static,
+        T::PackType: FixedLenPack + CLayout,
+    {
+        self.align_field::<T::PackType>()?;
+        self.w.write_deferred(initial)
+    }
+
+    /// Returns the alignment of the struct so far, which is the largest
+    /// alignment of any of its fields.
+    pub fn alignment(&self) -> usize {
+        self.align
+    }
+
+    /// Returns the underlying writer, for writing data that isn't subject
+    /// to automatic padding.
+    pub fn writer(&mut self) -> &mut Writer<'a, W, E> {
+        self.w
+    }
+
+    fn align_field<T: CLayout>(&mut self) -> Result<()> {
+        let n = T::c_align(self.abi);
+        self.align = self.align.max(n);
+        self.w.align_from(self.start, n)?;
+        Ok(())
+    }
+
+    /// Writes the trailing padding needed to make the size of the struct a
+    /// multiple of its alignment.
+    pub(crate) fn finish(self) -> Result<usize> {
+        self.w.align_from(self.start, self.align)
+    }
+}
+
+macro_rules! scalar_layout {
+    ($($t:ty),+) => {
+        $(
+            impl CLayout for $t {
+                fn c_align(abi: Abi) -> usize {
+                    abi.scalar_align(std::mem::size_of::<$t>())
+                }
+            }
+        )+
+    };
+}
+
+scalar_layout!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f32, f64, bool);
+
+impl CLayout for F16 {
+    fn c_align(abi: Abi) -> usize {
+        abi.scalar_align(2)
+    }
+}
+
+impl CLayout for Bf16 {
+    fn c_align(abi: Abi) -> usize {
+        abi.scalar_align(2)
+    }
+}
+
+/// Code points have the alignment of `char32_t`.
+impl CLayout for CodePoint {
+    fn c_align(abi: Abi) -> usize {
+        abi.scalar_align(4)
+    }
+}
+
+/// UUIDs have the alignment of a sixteen-byte array.
+impl CLayout for Uuid {
+    fn c_align(_: Abi) -> usize {
+        1
+    }
+}
+
+/// GUIDs have the alignment of the Windows `GUID` struct, whose first
+/// field is a 32-bit integer.
+impl CLayout for Guid {
+    fn c_align(abi: Abi) -> usize {
+        abi.scalar_align(4)
+    }
+}
+
+/// Fixed-length strings have the alignment of a `char` array.
+impl<const N: usize> CLayout for FixedStr<N> {
+    fn c_align(_: Abi) -> usize {
+        1
+    }
+}
+
+/// Arrays have the alignment of their element type.
+impl<T: CLayout, const N: usize> CLayout for [T; N] {
+    fn c_align(abi: Abi) -> usize {
+        T::c_align(abi)
+    }
+}
+
+impl<T: CLayout, E: Endian> CLayout for EndianOverride<T, E> {
+    fn c_align(abi: Abi) -> usize {
+        T::c_align(abi)
+    }
+}
+
+impl<T: FixedRepr + CLayout, const FRAC: u32> CLayout for Fixed<T, FRAC> {
+    fn c_align(abi: Abi) -> usize {
+        T::c_align(abi)
+    }
+}
+
+impl<T: BitfieldRepr + CLayout> CLayout for Bitfield<T> {
+    fn c_align(abi: Abi) -> usize {
+        T::c_align(abi)
+    }
+}
@@ -0,0 +1,138 @@
+use std::convert::TryFrom;
+use std::num::TryFromIntError;
+
+use crate::endian::Endian;
+use crate::pack::{out_of_range_error, FixedLenPack, Pack};
+use crate::unpack::Unpack;
+
+/// Packs an unsigned integer as packed binary-coded decimal in `N` bytes,
+/// with two decimal digits per byte.
+///
+/// The digits are packed most significant first, with the more significant
+/// digit of each pair in the upper four bits, regardless of the writer's
+/// endianness. Unused leading digits are zero.
+///
+/// Use the [`TryFrom<u64>`](std::convert::TryFrom) implementation to
+/// construct a `Bcd`, which returns an error if the value has more than
+/// `2 * N` digits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bcd<const N: usize>(u64);
+
+/// Packs an unsigned integer as unpacked binary-coded decimal in `N` bytes,
+/// with one decimal digit per byte.
+///
+/// The digits are packed most significant first, regardless of the
+/// writer's endianness. Unused leading digits are zero.
+///
+/// Use the [`TryFrom<u64>`](std::convert::TryFrom) implementation to
+/// construct an `UnpackedBcd`, which returns an error if the value has more
+/// than `N` digits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnpackedBcd<const N: usize>(u64);
+
+impl<const N: usize> Bcd<N> {
+    /// Returns the value being encoded.
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl<const N: usize> UnpackedBcd<N> {
+    /// Returns the value being encoded.
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+/// Returns an error if `v` has more than `digits` decimal digits.
+fn check_digits(v: u64, digits: usize) -> Result<u64, TryFromIntError> {
+    match u32::try_from(digits)
+        .ok()
+        .and_then(|d| 10_u64.checked_pow(d))
+    {
+        Some(limit) if v >= limit => Err(out_of_range_error()),
+        _ => Ok(v),
+    }
+}
+
+impl<const N: usize> TryFrom<u64> for Bcd<N> {
+    type Error = TryFromIntError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        Ok(Self(check_digits(v, N * 2)?))
+    }
+}
+
+impl<const N: usize> TryFrom<u64> for UnpackedBcd<N> {
+    type Error = TryFromIntError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        Ok(Self(check_digits(v, N)?))
+    }
+}
+
+impl<const N: usize> Pack for Bcd<N> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let mut v = self.0;
+        for b in buf[..N].iter_mut().rev() {
+            *b = (v % 10) as u8 | ((v / 10 % 10) as u8) << 4;
+            v /= 100;
+        }
+    }
+}
+
+impl<const N: usize> FixedLenPack for Bcd<N> {
+    const PACK_LEN: usize = N;
+}
+
+impl<const N: usize> Unpack for Bcd<N> {
+    const UNPACK_LEN: usize = N;
+
+    /// Unpacks packed binary-coded decimal. Nibbles greater than nine are
+    /// treated as if they were nine, and values too large for a `u64`
+    /// saturate.
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let v = buf[..N].iter().fold(0_u64, |v, b| {
+            let hi = (b >> 4).min(9) as u64;
+            let lo = (b & 0xf).min(9) as u64;
+            v.saturating_mul(100).saturating_add(hi * 10 + lo)
+        });
+        Self(v)
+    }
+}
+
+impl<const N: usize> Pack for UnpackedBcd<N> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let mut v = self.0;
+        for b in buf[..N].iter_mut().rev() {
+            *b = (v % 10) as u8;
+            v /= 10;
+        }
+    }
+}
+
+impl<const N: usize> FixedLenPack for UnpackedBcd<N> {
+    const PACK_LEN: usize = N;
+}
+
+impl<const N: usize> Unpack for UnpackedBcd<N> {
+    const UNPACK_LEN: usize = N;
+
+    /// Unpacks unpacked binary-coded decimal. Bytes greater than nine are
+    /// treated as if they were nine, and values too large for a `u64`
+    /// saturate.
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let v = buf[..N].iter().fold(0_u64, |v, b| {
+            v.saturating_mul(10).saturating_add((*b).min(9) as u64)
+        });
+        Self(v)
+    }
+}
@@ -0,0 +1,144 @@
+use std::ops::Range;
+
+use crate::endian::Endian;
+use crate::pack::{FixedLenPack, Pack};
+
+/// Packs a set of bit fields as an unsigned integer of type `T`, using the
+/// writer's endianness.
+///
+/// Bits are numbered from zero at the least significant bit. Each field is
+/// set using [`set`](Self::set) or [`set_flag`](Self::set_flag), which
+/// return an error if the field doesn't fit in the integer or the value
+/// doesn't fit in the field, and bits that aren't set are zero.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// use binbin::bitfield::Bitfield;
+///
+/// let mut buf = Vec::<u8>::new();
+/// binbin::write_vec_be(&mut buf, |w| {
+///     w.write(Bitfield::<u16>::new().set(0..4, 3)?.set_flag(4, true)?)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(buf, [0x00, 0x13]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bitfield<T> {
+    bits: u64,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+/// A sealed trait implemented by the unsigned integer types that can hold
+/// a [`Bitfield`](Bitfield).
+pub trait BitfieldRepr: FixedLenPack + Copy + private::Sealed {}
+
+impl<T: BitfieldRepr> Bitfield<T> {
+    /// Returns a bitfield with all bits zero.
+    pub fn new() -> Self {
+        Self {
+            bits: 0,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the field occupying the given range of bits to the given value,
+    /// replacing any value previously set for those bits.
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the range is
+    /// empty or extends beyond the width of `T`, or if the value is too large
+    /// for the field.
+    pub fn set(self, bits: Range<u32>, v: u64) -> std::io::Result<Self> {
+        if bits.start >= bits.end || bits.end > T::BITS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "bits {}..{} are not a valid field of a {}-bit integer",
+                    bits.start,
+                    bits.end,
+                    T::BITS
+                ),
+            ));
+        }
+        let width = bits.end - bits.start;
+        let mask = u64::MAX >> (64 - width);
+        if v & !mask != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "value {} does not fit in the {}-bit field at bits {}..{}",
+                    v, width, bits.start, bits.end
+                ),
+            ));
+        }
+        Ok(Self {
+            bits: (self.bits & !(mask << bits.start)) | (v << bits.start),
+            ..self
+        })
+    }
+
+    /// Sets or clears the single bit with the given number.
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the bit is
+    /// beyond the width of `T`.
+    pub fn set_flag(self, bit: u32, v: bool) -> std::io::Result<Self> {
+        self.set(bit..bit.saturating_add(1), v as u64)
+    }
+
+    /// Returns the value of the field occupying the given range of bits, or
+    /// `None` if the range isn't a valid field.
+    pub fn get(&self, bits: Range<u32>) -> Option<u64> {
+        if bits.start >= bits.end || bits.end > T::BITS {
+            return None;
+        }
+        let mask = u64::MAX >> (64 - (bits.end - bits.start));
+        Some((self.bits >> bits.start) & mask)
+    }
+
+    /// Returns the packed integer.
+    pub fn value(&self) -> T {
+        T::from_bits(self.bits)
+    }
+}
+
+impl<T: BitfieldRepr> Pack for Bitfield<T> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.value().pack_into_slice::<E>(buf)
+    }
+}
+
+impl<T: BitfieldRepr> FixedLenPack for Bitfield<T> {
+    const PACK_LEN: usize = T::PACK_LEN;
+}
+
+macro_rules! bitfield_repr {
+    ($($t:ty),+) => {
+        $(
+            impl BitfieldRepr for $t {}
+            impl private::Sealed for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn from_bits(bits: u64) -> Self {
+                    bits as $t
+                }
+            }
+        )+
+    };
+}
+
+bitfield_repr!(u8, u16, u32, u64);
+
+mod private {
+    pub trait Sealed {
+        const BITS: u32;
+        fn from_bits(bits: u64) -> Self;
+    }
+}
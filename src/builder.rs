@@ -0,0 +1,321 @@
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+
+use crate::endian::{ByteOrder, DynEndian, Endianness};
+use crate::layout::LayoutMap;
+use crate::trace::Tracer;
+use crate::Writer;
+
+/// Options for creating a [`Writer`](Writer), for situations where the
+/// defaults used by [`write_le`](crate::write_le) and similar are not
+/// appropriate.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// use binbin::endian::LittleEndian;
+/// use binbin::Builder;
+///
+/// let mut buf = Vec::<u8>::new();
+/// Builder::<LittleEndian>::new()
+///     .padding(0xff)
+///     .default_alignment(4)
+///     .write_vec(&mut buf, |w| {
+///         w.write(1_u8)?;
+///         w.align_default()?;
+///         Ok(())
+///     })?;
+/// assert_eq!(buf, vec![0x01, 0xff, 0xff, 0xff]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Builder<E: ByteOrder> {
+    endianness: Endianness,
+    padding: u8,
+    default_alignment: usize,
+    strict: bool,
+    buffer_capacity: usize,
+    unresolved: UnresolvedPolicy,
+    batch_patches: bool,
+    write_buffer: usize,
+    trace: Option<Tracer>,
+    max_len: Option<u64>,
+    _phantom: std::marker::PhantomData<E>,
+}
+
+/// What to do about deferred slots that are still unresolved when a
+/// writer is finalized.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UnresolvedPolicy {
+    /// Finalization fails unless each unresolved slot was marked using
+    /// [`Writer::allow_unresolved`](Writer::allow_unresolved). This is the
+    /// default.
+    #[default]
+    Error,
+
+    /// Any unresolved slots keep their initial values, as if each one had
+    /// been marked using [`Writer::allow_unresolved`](Writer::allow_unresolved).
+    KeepInitial,
+}
+
+impl<E: ByteOrder> Builder<E> {
+    /// Creates a builder with the default options, writing with the byte
+    /// order selected by `E`.
+    ///
+    /// If `E` is [`DynEndian`](DynEndian) then the byte order defaults to
+    /// little-endian; use [`endianness`](Builder::endianness) to select it.
+    pub fn new() -> Self {
+        Self {
+            endianness: Endianness::select::<E>(Endianness::Little),
+            padding: 0,
+            default_alignment: 1,
+            strict: false,
+            buffer_capacity: 0,
+            unresolved: UnresolvedPolicy::Error,
+            batch_patches: false,
+            write_buffer: 0,
+            trace: None,
+            max_len: None,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Selects the initial padding byte, as with
+    /// [`Writer::set_padding`](Writer::set_padding).
+    pub fn padding(mut self, v: u8) -> Self {
+        self.padding = v;
+        self
+    }
+
+    /// Selects the alignment used by
+    /// [`Writer::align_default`](Writer::align_default).
+    pub fn default_alignment(mut self, n: usize) -> Self {
+        self.default_alignment = n;
+        self
+    }
+
+    /// Selects whether the writer starts in strict mode, as with
+    /// [`Writer::set_strict`](Writer::set_strict).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Selects the number of bytes to preallocate for in-memory buffers,
+    /// such as the output of [`write_vec`](Builder::write_vec) and regions
+    /// produced by [`Writer::buffered`](Writer::buffered).
+    pub fn buffer_capacity(mut self, n: usize) -> Self {
+        self.buffer_capacity = n;
+        self
+    }
+
+    /// Selects what to do about deferred slots that are still unresolved
+    /// when the writer is finalized.
+    pub fn unresolved(mut self, policy: UnresolvedPolicy) -> Self {
+        self.unresolved = policy;
+        self
+    }
+
+    /// Selects the number of bytes of writes to collect in memory before
+    /// writing them to the underlying stream, or zero to disable buffering,
+    /// which is the default.
+    ///
+    /// Unlike wrapping the stream in a [`BufWriter`](std::io::BufWriter),
+    /// this buffering understands seeking: updates to placeholders that are
+    /// still in the buffer, such as those made by
+    /// [`Writer::resolve`](Writer::resolve), just change the buffered data
+    /// without seeking the underlying stream at all. The buffer is flushed
+    /// whenever the writer needs to use the underlying stream directly, such
+    /// as to read back data for [`Writer::derive`](Writer::derive), and when
+    /// the writer is finalized.
+    pub fn write_buffer(mut self, n: usize) -> Self {
+        self.write_buffer = n;
+        self
+    }
+
+    /// Selects whether to postpone writing resolved values over their
+    /// placeholders, so that all of the patches can be written in a single
+    /// pass in order of increasing offset.
+    ///
+    /// By default each call to [`Writer::resolve`](Writer::resolve) seeks
+    /// back to update each placeholder immediately. For outputs with many
+    /// placeholders on storage where seeking is expensive, batching the
+    /// patches avoids repeatedly seeking back and forth. The batched
+    /// patches are written when the writer is finalized, or earlier if the
+    /// writer needs to read back or revisit already-written data, such as
+    /// for [`Writer::derive`](Writer::derive) or [`Writer::at`](Writer::at).
+    ///
+    /// While batching, placeholders still contain their initial values in
+    /// the underlying stream, so avoid writing over placeholders other than
+    /// by using the writer's own methods.
+    pub fn batch_patches(mut self, batch: bool) -> Self {
+        self.batch_patches = batch;
+        self
+    }
+
+    /// Selects the maximum length of the output, so that any write that
+    /// would extend beyond that length fails.
+    ///
+    /// This is for formats whose offsets and sizes have a fixed width, such
+    /// as the many formats limited to 4 GiB by 32-bit offsets: with
+    /// `max_len(1 << 32)` the first write that would place data beyond
+    /// the range of a 32-bit offset fails with a clear error, rather than
+    /// some offset later being truncated. Data written into
+    /// [`Writer::buffered`](Writer::buffered) regions is checked when the
+    /// region is emitted.
+    pub fn max_len(mut self, n: u64) -> Self {
+        self.max_len = Some(n);
+        self
+    }
+
+    /// Selects a destination for a trace of everything the writer does,
+    /// for debugging.
+    ///
+    /// Each write is logged as a hexdump of the bytes written along with
+    /// their position and, where known, the type of the value packed.
+    /// Events such as resolving deferred slots are logged too. Positions
+    /// inside [`Writer::buffered`](Writer::buffered) regions are relative
+    /// to the start of the region. The trace is written as the writer
+    /// makes each write, so it's in the order that things were written
+    /// even if the underlying stream is buffered.
+    ///
+    /// The destination is shared by all writers created by this builder
+    /// and its clones.
+    pub fn trace<T: Write + 'static>(mut self, sink: T) -> Self {
+        self.trace = Some(Tracer::new(sink));
+        self
+    }
+
+    /// Writes arbitrary binary data to the given writer `w` using the given
+    /// function `f`, with the options from this builder.
+    ///
+    /// This is otherwise the same as [`write`](crate::write).
+    pub fn write<W, F, R>(&self, w: &mut W, f: F) -> Result<R>
+    where
+        W: Write + Seek,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
+    {
+        let mut wr = self.build(w)?;
+        let ret = f(&mut wr)?;
+        wr.finalize()?;
+        Ok(ret)
+    }
+
+    /// Like [`write`](Builder::write), but also returns a description of
+    /// the layout of the complete output, including the regions named using
+    /// [`Writer::annotate`](Writer::annotate).
+    pub fn write_with_layout<W, F, R>(&self, w: &mut W, f: F) -> Result<(R, LayoutMap)>
+    where
+        W: Write + Seek,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
+    {
+        let mut wr = self.build(w)?;
+        let ret = f(&mut wr)?;
+        wr.complete()?;
+        Ok((ret, wr.layout_map()))
+    }
+
+    /// Appends arbitrary binary data to the end of the given stream using
+    /// the given function `f`, with the options from this builder.
+    ///
+    /// This is otherwise the same as [`append`](crate::append).
+    pub fn append<W, F, R>(&self, w: &mut W, f: F) -> Result<R>
+    where
+        W: Write + Seek,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
+    {
+        w.seek(SeekFrom::End(0))?;
+        self.write(w, f)
+    }
+
+    /// Edits existing data in the given stream using the given function
+    /// `f`, with the options from this builder.
+    ///
+    /// This is otherwise the same as [`edit`](crate::edit).
+    pub fn edit<RW, F, R>(&self, rw: &mut RW, f: F) -> Result<R>
+    where
+        RW: Read + Write + Seek,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut RW, E>) -> Result<R>,
+    {
+        rw.seek(SeekFrom::Start(0))?;
+        self.write_readable(rw, f)
+    }
+
+    /// Like [`write`](Self::write), but for streams that are known to be
+    /// readable, so that derivations postponed inside nested writers can
+    /// run during finalization.
+    fn write_readable<W, F, R>(&self, w: &mut W, f: F) -> Result<R>
+    where
+        W: Read + Write + Seek,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
+    {
+        let mut wr = self.build(w)?;
+        wr.set_readable();
+        let ret = f(&mut wr)?;
+        wr.finalize()?;
+        Ok(ret)
+    }
+
+    /// Creates a writer over the given stream with the options from this
+    /// builder, for use without a closure.
+    ///
+    /// This is otherwise the same as [`Writer::new_le`](Writer::new_le).
+    /// Call [`Writer::finish`](Writer::finish) once writing is complete.
+    pub fn build<'w, W: Write + Seek>(&self, w: W) -> Result<Writer<'w, W, E>> {
+        let mut wr = Writer::new_with_endianness(w, self.endianness);
+        wr.set_padding(self.padding);
+        wr.set_strict(self.strict);
+        wr.state.default_alignment = self.default_alignment;
+        wr.state.buffer_capacity = self.buffer_capacity;
+        wr.state.unresolved = self.unresolved;
+        wr.w.set_capacity(self.write_buffer)?;
+        wr.w.set_trace(self.trace.clone());
+        wr.w.set_max_len(self.max_len);
+        if self.batch_patches {
+            wr.state.journal = Some(Vec::new());
+        }
+        wr.start = wr.position()?;
+        Ok(wr)
+    }
+
+    /// Writes arbitrary binary data into a byte vector using the given
+    /// function `f`, with the options from this builder.
+    ///
+    /// This is otherwise the same as [`write_vec`](crate::write_vec).
+    pub fn write_vec<F, R>(&self, into: &mut Vec<u8>, f: F) -> Result<R>
+    where
+        for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>) -> Result<R>,
+    {
+        into.reserve(self.buffer_capacity);
+        let mut cursor = std::io::Cursor::new(into);
+        self.write_readable(&mut cursor, f)
+    }
+
+    /// Appends arbitrary binary data to the given byte vector using the
+    /// given function `f`, with the options from this builder.
+    ///
+    /// This is otherwise the same as [`append_vec`](crate::append_vec).
+    pub fn append_vec<'v, F, R>(&self, into: &'v mut Vec<u8>, f: F) -> Result<R>
+    where
+        for<'w> F: FnOnce(&mut Writer<'w, &mut crate::vecbuf::VecBuf<'v>, E>) -> Result<R>,
+    {
+        into.reserve(self.buffer_capacity);
+        let mut vb = crate::vecbuf::VecBuf::new(into);
+        self.write_readable(&mut vb, f)
+    }
+}
+
+impl Builder<DynEndian> {
+    /// Selects the byte order for a builder whose byte order is chosen at
+    /// runtime.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+}
+
+impl<E: ByteOrder> Default for Builder<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,461 @@
+use std::io::Write;
+
+/// The parameters of a CRC algorithm in the "Rocksoft" model, which can
+/// describe most of the CRC variants used in practice.
+///
+/// The associated constants describe some commonly-used variants. See the
+/// [CRC RevEng catalogue](https://reveng.sourceforge.io/crc-catalogue/) for
+/// the parameters of many others.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CrcParams {
+    /// The number of bits in the result, which must be between 8 and 64
+    /// inclusive.
+    pub width: u32,
+
+    /// The generator polynomial, in non-reflected form and without its
+    /// implicit most significant bit.
+    pub poly: u64,
+
+    /// The initial value of the register, in non-reflected form.
+    pub init: u64,
+
+    /// Whether each input byte is processed least significant bit first.
+    pub reflect_in: bool,
+
+    /// Whether the final register value is reflected before `xor_out` is
+    /// applied.
+    pub reflect_out: bool,
+
+    /// A value to combine with the final register value using exclusive OR.
+    pub xor_out: u64,
+}
+
+impl CrcParams {
+    /// CRC-8/SMBUS, with polynomial `0x07`.
+    pub const CRC_8: Self = Self {
+        width: 8,
+        poly: 0x07,
+        init: 0x00,
+        reflect_in: false,
+        reflect_out: false,
+        xor_out: 0x00,
+    };
+
+    /// CRC-16/ARC, as used by LHA and many other archive formats.
+    pub const CRC_16_ARC: Self = Self {
+        width: 16,
+        poly: 0x8005,
+        init: 0x0000,
+        reflect_in: true,
+        reflect_out: true,
+        xor_out: 0x0000,
+    };
+
+    /// CRC-16/IBM-3740, often misleadingly called "CRC-16-CCITT".
+    pub const CRC_16_IBM_3740: Self = Self {
+        width: 16,
+        poly: 0x1021,
+        init: 0xffff,
+        reflect_in: false,
+        reflect_out: false,
+        xor_out: 0x0000,
+    };
+
+    /// CRC-32/ISO-HDLC, as used by PNG, ZIP, GPT, Ethernet, and many others.
+    pub const CRC_32: Self = Self {
+        width: 32,
+        poly: 0x04c11db7,
+        init: 0xffffffff,
+        reflect_in: true,
+        reflect_out: true,
+        xor_out: 0xffffffff,
+    };
+
+    /// CRC-32/ISCSI, also known as CRC-32C, as used by iSCSI, ext4, and
+    /// Btrfs.
+    pub const CRC_32C: Self = Self {
+        width: 32,
+        poly: 0x1edc6f41,
+        init: 0xffffffff,
+        reflect_in: true,
+        reflect_out: true,
+        xor_out: 0xffffffff,
+    };
+
+    /// CRC-64/XZ, as used by the XZ compression format.
+    pub const CRC_64_XZ: Self = Self {
+        width: 64,
+        poly: 0x42f0e1eba9ea3693,
+        init: 0xffffffffffffffff,
+        reflect_in: true,
+        reflect_out: true,
+        xor_out: 0xffffffffffffffff,
+    };
+
+    /// CRC-64/ECMA-182.
+    pub const CRC_64_ECMA_182: Self = Self {
+        width: 64,
+        poly: 0x42f0e1eba9ea3693,
+        init: 0x0000000000000000,
+        reflect_in: false,
+        reflect_out: false,
+        xor_out: 0x0000000000000000,
+    };
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+}
+
+/// Calculates a CRC over data given in one or more chunks.
+///
+/// `Crc` also implements [`Write`](std::io::Write), so it can be the target
+/// of [`std::io::copy`](std::io::copy) from the reader given to
+/// [`Writer::derive`](crate::Writer::derive) or
+/// [`Writer::derive_deferred`](crate::Writer::derive_deferred).
+///
+/// ```
+/// use binbin::checksum::{Crc, CrcParams};
+///
+/// let mut crc = Crc::new(CrcParams::CRC_32);
+/// crc.update(b"12345");
+/// crc.update(b"6789");
+/// assert_eq!(crc.finish(), 0xcbf43926);
+/// ```
+#[derive(Clone)]
+pub struct Crc {
+    params: CrcParams,
+    table: [u64; 256],
+    reg: u64,
+}
+
+impl Crc {
+    /// Creates a new CRC calculation with the given parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `params.width` is not between 8 and 64 inclusive.
+    pub fn new(params: CrcParams) -> Self {
+        assert!(
+            (8..=64).contains(&params.width),
+            "CRC width must be between 8 and 64 bits"
+        );
+        let mask = params.mask();
+        let mut table = [0_u64; 256];
+        if params.reflect_in {
+            let poly = reflect(params.poly, params.width);
+            for (i, v) in table.iter_mut().enumerate() {
+                let mut r = i as u64;
+                for _ in 0..8 {
+                    r = if r & 1 != 0 { (r >> 1) ^ poly } else { r >> 1 };
+                }
+                *v = r;
+            }
+        } else {
+            let top = 1 << (params.width - 1);
+            for (i, v) in table.iter_mut().enumerate() {
+                let mut r = (i as u64) << (params.width - 8);
+                for _ in 0..8 {
+                    r = if r & top != 0 {
+                        (r << 1) ^ params.poly
+                    } else {
+                        r << 1
+                    };
+                }
+                *v = r & mask;
+            }
+        }
+        let mut ret = Self {
+            params,
+            table,
+            reg: 0,
+        };
+        ret.reset();
+        ret
+    }
+
+    /// Calculates the CRC of the given data in a single step.
+    pub fn checksum(params: CrcParams, data: &[u8]) -> u64 {
+        let mut crc = Self::new(params);
+        crc.update(data);
+        crc.finish()
+    }
+
+    /// Discards all of the data given so far, to begin a new calculation
+    /// with the same parameters.
+    pub fn reset(&mut self) {
+        self.reg = if self.params.reflect_in {
+            reflect(self.params.init, self.params.width)
+        } else {
+            self.params.init & self.params.mask()
+        };
+    }
+
+    /// Adds more data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        let mut reg = self.reg;
+        if self.params.reflect_in {
+            for b in data {
+                reg = (reg >> 8) ^ self.table[((reg ^ *b as u64) & 0xff) as usize];
+            }
+        } else {
+            let shift = self.params.width - 8;
+            for b in data {
+                let idx = ((reg >> shift) ^ *b as u64) & 0xff;
+                reg = (reg << 8) ^ self.table[idx as usize];
+            }
+            reg &= self.params.mask();
+        }
+        self.reg = reg;
+    }
+
+    /// Returns the CRC of all of the data given so far.
+    ///
+    /// The result is in the least significant
+    /// [`width`](CrcParams::width) bits of the returned value.
+    pub fn finish(&self) -> u64 {
+        let reg = if self.params.reflect_in != self.params.reflect_out {
+            reflect(self.reg, self.params.width)
+        } else {
+            self.reg
+        };
+        (reg ^ self.params.xor_out) & self.params.mask()
+    }
+}
+
+/// Reverses the order of the least significant `width` bits of `v`.
+fn reflect(v: u64, width: u32) -> u64 {
+    v.reverse_bits() >> (64 - width)
+}
+
+/// Calculates the one's-complement "Internet checksum" described in
+/// RFC 1071, as used in IPv4, UDP, TCP, and ICMP headers.
+///
+/// The data is summed as a sequence of big-endian 16-bit words, with an odd
+/// final byte padded with zero. The given chunks need not have even length.
+///
+/// ```
+/// use binbin::checksum::InternetChecksum;
+///
+/// let mut sum = InternetChecksum::new();
+/// sum.update(&[0x00, 0x01, 0xf2]);
+/// sum.update(&[0x03, 0xf4, 0xf5, 0xf6, 0xf7]);
+/// assert_eq!(sum.finish(), 0x220d);
+/// ```
+#[derive(Clone, Default)]
+pub struct InternetChecksum {
+    sum: u32,
+    odd: Option<u8>,
+}
+
+impl InternetChecksum {
+    /// Creates a new checksum calculation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds more data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        let mut data = data;
+        if let Some(hi) = self.odd.take() {
+            match data.split_first() {
+                Some((lo, rest)) => {
+                    self.add_word(u16::from_be_bytes([hi, *lo]));
+                    data = rest;
+                }
+                None => {
+                    self.odd = Some(hi);
+                    return;
+                }
+            }
+        }
+        let mut words = data.chunks_exact(2);
+        for word in &mut words {
+            self.add_word(u16::from_be_bytes([word[0], word[1]]));
+        }
+        self.odd = words.remainder().first().copied();
+    }
+
+    /// Returns the checksum of all of the data given so far.
+    pub fn finish(&self) -> u16 {
+        let mut sum = self.sum;
+        if let Some(hi) = self.odd {
+            sum += (hi as u32) << 8;
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    fn add_word(&mut self, v: u16) {
+        self.sum += v as u32;
+        // Fold the carry back in eagerly so the sum can't overflow.
+        if self.sum > 0xffff {
+            self.sum = (self.sum & 0xffff) + (self.sum >> 16);
+        }
+    }
+}
+
+/// Calculates the Fletcher-16 checksum, which sums individual bytes.
+///
+/// ```
+/// use binbin::checksum::Fletcher16;
+///
+/// let mut sum = Fletcher16::new();
+/// sum.update(b"abcde");
+/// assert_eq!(sum.finish(), 0xc8f0);
+/// ```
+#[derive(Clone, Default)]
+pub struct Fletcher16 {
+    sum1: u16,
+    sum2: u16,
+}
+
+impl Fletcher16 {
+    /// Creates a new checksum calculation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds more data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.sum1 = (self.sum1 + *b as u16) % 255;
+            self.sum2 = (self.sum2 + self.sum1) % 255;
+        }
+    }
+
+    /// Returns the checksum of all of the data given so far.
+    pub fn finish(&self) -> u16 {
+        (self.sum2 << 8) | self.sum1
+    }
+}
+
+/// Calculates the Fletcher-32 checksum, which sums little-endian 16-bit
+/// words.
+///
+/// An odd final byte is padded with zero. The given chunks need not have
+/// even length.
+///
+/// ```
+/// use binbin::checksum::Fletcher32;
+///
+/// let mut sum = Fletcher32::new();
+/// sum.update(b"abcde");
+/// assert_eq!(sum.finish(), 0xf04fc729);
+/// ```
+#[derive(Clone, Default)]
+pub struct Fletcher32 {
+    sum1: u32,
+    sum2: u32,
+    odd: Option<u8>,
+}
+
+impl Fletcher32 {
+    /// Creates a new checksum calculation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds more data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        let mut data = data;
+        if let Some(lo) = self.odd.take() {
+            match data.split_first() {
+                Some((hi, rest)) => {
+                    self.add_word(u16::from_le_bytes([lo, *hi]));
+                    data = rest;
+                }
+                None => {
+                    self.odd = Some(lo);
+                    return;
+                }
+            }
+        }
+        let mut words = data.chunks_exact(2);
+        for word in &mut words {
+            self.add_word(u16::from_le_bytes([word[0], word[1]]));
+        }
+        self.odd = words.remainder().first().copied();
+    }
+
+    /// Returns the checksum of all of the data given so far.
+    pub fn finish(&self) -> u32 {
+        let mut sum = self.clone();
+        if let Some(lo) = sum.odd.take() {
+            sum.add_word(lo as u16);
+        }
+        (sum.sum2 << 16) | sum.sum1
+    }
+
+    fn add_word(&mut self, v: u16) {
+        self.sum1 = (self.sum1 + v as u32) % 65535;
+        self.sum2 = (self.sum2 + self.sum1) % 65535;
+    }
+}
+
+/// Calculates the Adler-32 checksum, as used by zlib.
+///
+/// ```
+/// use binbin::checksum::Adler32;
+///
+/// let mut sum = Adler32::new();
+/// sum.update(b"Wikipedia");
+/// assert_eq!(sum.finish(), 0x11e60398);
+/// ```
+#[derive(Clone)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    /// Creates a new checksum calculation.
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    /// Adds more data to the calculation.
+    pub fn update(&mut self, data: &[u8]) {
+        for v in data {
+            self.a = (self.a + *v as u32) % 65521;
+            self.b = (self.b + self.a) % 65521;
+        }
+    }
+
+    /// Returns the checksum of all of the data given so far.
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements [`Write`](std::io::Write) for a checksum type by passing all
+/// written bytes to its `update` method, so that it can be the target of
+/// [`std::io::copy`](std::io::copy).
+macro_rules! checksum_write {
+    ($t:ty) => {
+        impl Write for $t {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.update(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+checksum_write!(Crc);
+checksum_write!(InternetChecksum);
+checksum_write!(Fletcher16);
+checksum_write!(Fletcher32);
+checksum_write!(Adler32);
@@ -0,0 +1,54 @@
+use std::io::{Result, Write};
+
+/// Trait implemented by compression algorithms for use with
+/// [`Writer::compressed_subregion`](crate::Writer::compressed_subregion).
+///
+/// `binbin` doesn't include any compression algorithms itself, but this
+/// trait is implemented for any function with a suitable signature, so it's
+/// straightforward to adapt an encoder from another crate. For example,
+/// using the `flate2` crate's raw deflate encoder:
+///
+/// ```ignore
+/// let deflate = |data: &[u8], out: &mut dyn std::io::Write| {
+///     let mut enc = flate2::write::DeflateEncoder::new(out, flate2::Compression::default());
+///     enc.write_all(data)?;
+///     enc.finish()?;
+///     Ok(())
+/// };
+/// let (compressed, ()) = w.compressed_subregion(deflate, |w| {
+///     w.write(&b"hello hello hello"[..])?;
+///     Ok(())
+/// })?;
+/// ```
+pub trait Compressor {
+    /// Writes a compressed representation of all of `data` to `out`.
+    fn compress(&mut self, data: &[u8], out: &mut dyn Write) -> Result<()>;
+}
+
+impl<F> Compressor for F
+where
+    F: FnMut(&[u8], &mut dyn Write) -> Result<()>,
+{
+    fn compress(&mut self, data: &[u8], out: &mut dyn Write) -> Result<()> {
+        self(data, out)
+    }
+}
+
+/// Describes a region written by
+/// [`Writer::compressed_subregion`](crate::Writer::compressed_subregion).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Compressed {
+    /// The start and end positions of the compressed data in the underlying
+    /// stream.
+    pub range: std::ops::Range<u64>,
+
+    /// The length of the data before compression.
+    pub uncompressed_len: u64,
+}
+
+impl Compressed {
+    /// Returns the length of the compressed data.
+    pub fn compressed_len(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+}
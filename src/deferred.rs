@@ -1,22 +1,31 @@
 /// A placeholder for a value that we'll learn only later in our process of
 /// writing out data.
-#[derive(Copy, Clone)]
+///
+/// A `Deferred` is just a handle for a slot owned by the
+/// [`Writer`](crate::Writer) that created it, so it can be copied freely
+/// even if `T` itself is not `Copy`.
 pub struct Deferred<'a, T> {
     pub(crate) idx: usize,
-    pub(crate) initial: T,
-    _phantom: std::marker::PhantomData<&'a T>,
+    _phantom: std::marker::PhantomData<&'a fn() -> T>,
 }
 
 impl<'a, T> Deferred<'a, T> {
-    pub(crate) fn new(idx: usize, initial: T) -> Self {
+    pub(crate) fn new(idx: usize) -> Self {
         Self {
-            idx: idx,
-            initial: initial,
+            idx,
             _phantom: std::marker::PhantomData,
         }
     }
 }
 
+impl<'a, T> Clone for Deferred<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for Deferred<'a, T> {}
+
 impl<'a, T> Deferred<'a, T>
 where
     T: crate::pack::IntoPack,
@@ -32,3 +41,286 @@ where
         Self::PACK_LEN
     }
 }
+
+impl<'a, T> Deferred<'a, T>
+where
+    T: crate::pack::IntoPack + Clone + std::convert::TryFrom<u64> + 'static,
+    <T as crate::pack::IntoPack>::PackType: crate::pack::FixedLenPack,
+{
+    /// Resolves the slot using the given writer with a `u64` value, such as
+    /// a position, returning an error if the value doesn't fit in `T`.
+    ///
+    /// This is a shorthand for
+    /// [`Writer::resolve_checked`](crate::Writer::resolve_checked).
+    pub fn resolve_from_u64<W, E>(
+        self,
+        w: &mut crate::Writer<'a, W, E>,
+        v: u64,
+    ) -> std::io::Result<T>
+    where
+        W: std::io::Write + std::io::Seek,
+        E: crate::endian::ByteOrder,
+    {
+        w.resolve_checked(self, v)
+    }
+}
+
+/// Trait implemented by the primitive number types that can be used with
+/// [`Writer::deferred_add`](crate::Writer::deferred_add) and similar.
+///
+/// For integer types the derived value is checked for overflow, so a
+/// derivation that would overflow fails instead of wrapping.
+pub trait DeferredArith: Copy + private::Sealed {}
+
+macro_rules! deferred_arith_int {
+    ($($t:ty),+) => {
+        $(
+            impl DeferredArith for $t {}
+            impl private::Sealed for $t {
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_add(self, rhs)
+                }
+
+                fn checked_sub(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, rhs)
+                }
+
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    <$t>::wrapping_add(self, rhs)
+                }
+
+                fn wrapping_sub(self, rhs: Self) -> Self {
+                    <$t>::wrapping_sub(self, rhs)
+                }
+            }
+        )+
+    };
+}
+
+deferred_arith_int!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128);
+
+macro_rules! deferred_arith_float {
+    ($($t:ty),+) => {
+        $(
+            impl DeferredArith for $t {}
+            impl private::Sealed for $t {
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    Some(self + rhs)
+                }
+
+                fn checked_sub(self, rhs: Self) -> Option<Self> {
+                    Some(self - rhs)
+                }
+
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    self + rhs
+                }
+
+                fn wrapping_sub(self, rhs: Self) -> Self {
+                    self - rhs
+                }
+            }
+        )+
+    };
+}
+
+deferred_arith_float!(f32, f64);
+
+mod private {
+    pub trait Sealed: Sized {
+        fn checked_add(self, rhs: Self) -> Option<Self>;
+        fn checked_sub(self, rhs: Self) -> Option<Self>;
+        fn wrapping_add(self, rhs: Self) -> Self;
+        fn wrapping_sub(self, rhs: Self) -> Self;
+    }
+}
+
+/// A deferred slot that counts occurrences of something, such as records
+/// written in a loop, and is resolved with the final count automatically
+/// when the writer is finalized.
+///
+/// Create a counter using [`Writer::counter`](crate::Writer::counter),
+/// write placeholders for it using its [`deferred`](Self::deferred) slot,
+/// and call [`increment`](Self::increment) for each occurrence. Counters
+/// can be cloned, with all clones sharing the same count.
+#[derive(Clone)]
+pub struct Counter<'a, T> {
+    pub(crate) deferred: Deferred<'a, T>,
+    pub(crate) count: std::rc::Rc<std::cell::Cell<u64>>,
+}
+
+impl<'a, T> Counter<'a, T> {
+    /// Adds one to the count.
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    /// Adds the given number to the count.
+    pub fn add(&self, n: u64) {
+        self.count.set(self.count.get() + n);
+    }
+
+    /// Returns the count so far.
+    pub fn get(&self) -> u64 {
+        self.count.get()
+    }
+
+    /// Returns the deferred slot that will be resolved with the final
+    /// count, for use with [`Writer::write_placeholder`](crate::Writer::write_placeholder)
+    /// and similar.
+    pub fn deferred(&self) -> Deferred<'a, T> {
+        self.deferred
+    }
+}
+
+/// A deferred slot for an offset relative to some base position other
+/// than the start of the output, such as the start of an enclosing chunk.
+///
+/// Create one using
+/// [`Writer::deferred_offset`](crate::Writer::deferred_offset), write
+/// placeholders for it using its [`deferred`](Self::deferred) slot, and
+/// then call [`Writer::resolve_offset`](crate::Writer::resolve_offset) at
+/// the position that the offset refers to. The offset is negative if that
+/// position is before the base, which requires a signed `T`.
+pub struct DeferredOffset<'a, T> {
+    pub(crate) deferred: Deferred<'a, T>,
+    pub(crate) base: u64,
+}
+
+impl<'a, T> DeferredOffset<'a, T> {
+    /// Returns the deferred slot that will be resolved with the offset, for
+    /// use with [`Writer::write_placeholder`](crate::Writer::write_placeholder)
+    /// and similar.
+    pub fn deferred(&self) -> Deferred<'a, T> {
+        self.deferred
+    }
+
+    /// Returns the position that the offset is relative to.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+}
+
+impl<'a, T> Clone for DeferredOffset<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for DeferredOffset<'a, T> {}
+
+/// A raw area of the output, of a fixed number of bytes, whose content
+/// will be provided later.
+///
+/// Create a reservation using [`Writer::reserve`](crate::Writer::reserve)
+/// and provide its content using
+/// [`Writer::fill_reservation`](crate::Writer::fill_reservation). This is
+/// for fields that don't fit the typed [`Deferred`](Deferred) model, such
+/// as those whose length is chosen at runtime. As with deferred slots,
+/// finalization fails if a reservation is never filled.
+#[derive(Copy, Clone)]
+pub struct Reservation<'a> {
+    pub(crate) idx: usize,
+    pub(crate) len: usize,
+    _phantom: std::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> Reservation<'a> {
+    pub(crate) fn new(idx: usize, len: usize) -> Self {
+        Self {
+            idx,
+            len,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns the length of the reserved area, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the reserved area is zero bytes long.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A marker for a position in the output that will be determined later,
+/// whose offset can be referenced before the position is known.
+///
+/// Create a label using [`Writer::label`](crate::Writer::label), write
+/// references to it using
+/// [`Writer::write_offset_of`](crate::Writer::write_offset_of), and then
+/// bind it to a position using [`Writer::place`](crate::Writer::place).
+/// All references are updated with the final offset when the writer is
+/// finalized.
+#[derive(Copy, Clone)]
+pub struct Label<'a> {
+    pub(crate) idx: usize,
+    _phantom: std::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> Label<'a> {
+    pub(crate) fn new(idx: usize) -> Self {
+        Self {
+            idx,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+/// Tracks the position of a label, if known, and the positions, sizes, and
+/// byte orders of all of the references to it that need updating once it's
+/// known.
+pub(crate) struct LabelState {
+    pub(crate) pos: Option<Location>,
+    pub(crate) refs: Vec<(Location, usize, crate::endian::Endianness, RefKind)>,
+}
+
+/// What is written for a reference to a label.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum RefKind {
+    /// The label's position in the output.
+    Offset,
+
+    /// The virtual address of the label's position, as mapped using
+    /// [`Writer::set_vaddr_base`](crate::Writer::set_vaddr_base).
+    Vaddr,
+
+    /// The label's position plus the addend if the label is placed, or
+    /// otherwise a relocation to be resolved by some later process, as
+    /// written by [`Writer::write_reloc`](crate::Writer::write_reloc).
+    Reloc { kind: u32, addend: i64 },
+}
+
+/// A handle for a region written into memory by
+/// [`Writer::buffered`](crate::Writer::buffered), which can be written to
+/// the output later using [`Writer::emit`](crate::Writer::emit).
+pub struct Buffered<'a> {
+    pub(crate) idx: usize,
+    _phantom: std::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> Buffered<'a> {
+    pub(crate) fn new(idx: usize) -> Self {
+        Self {
+            idx,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+/// A position in either the underlying stream, if `buffer` is `None`, or
+/// in the data of one of the writer's buffered regions.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Location {
+    pub(crate) buffer: Option<usize>,
+    pub(crate) pos: u64,
+}
+
+/// Tracks the data of a buffered region once complete, and where it was
+/// emitted once emitted.
+pub(crate) struct BufferState {
+    pub(crate) data: Option<Vec<u8>>,
+    pub(crate) emitted_at: Option<Location>,
+}
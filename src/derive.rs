@@ -1,28 +1,151 @@
-use std::io::{Read, Take};
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
 /// The [`Read`](Read) implementation used with
-/// [`Writer::derive`](crate::Writer::derive).
+/// [`Writer::derive`](crate::Writer::derive) and
+/// [`Writer::derive_deferred`](crate::Writer::derive_deferred).
+///
+/// Positions are relative to the start of the region being derived from,
+/// and the reader reports end of file at the end of that region. It also
+/// implements [`Seek`](Seek), with positions clamped to the region, and
+/// [`BufRead`](BufRead), so that it can be passed directly to functions
+/// that require either.
 pub struct DeriveRead<'a, R>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
-    r: Take<&'a mut R>,
+    r: &'a mut R,
+    len: u64,
+
+    /// The position within the region of the next byte to return.
+    pos: u64,
+
+    /// Data already read from `r` for [`BufRead`](BufRead), of which the
+    /// bytes from `consumed` up to `filled` are not yet returned.
+    buf: Vec<u8>,
+    consumed: usize,
+    filled: usize,
 }
 
+/// A trait for streams that implement both [`Read`](Read) and
+/// [`Seek`](Seek), so that a [`DeriveRead`](DeriveRead) can be used over
+/// a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+const BUF_LEN: usize = 8192;
+
 impl<'a, R> DeriveRead<'a, R>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
     pub(crate) fn new(r: &'a mut R, limit: u64) -> Self {
-        Self { r: r.take(limit) }
+        Self {
+            r,
+            len: limit,
+            pos: 0,
+            buf: Vec::new(),
+            consumed: 0,
+            filled: 0,
+        }
+    }
+
+    /// Returns the length of the region being read.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the region being read is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes remaining before the end of the region.
+    fn remain(&self) -> usize {
+        (self.len - self.pos).min(usize::MAX as u64) as usize
     }
 }
 
 impl<'a, R> Read for DeriveRead<'a, R>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
     fn read(&mut self, buf: &mut [u8]) -> std::result::Result<usize, std::io::Error> {
-        self.r.read(buf)
+        if self.consumed == self.filled && buf.len() >= BUF_LEN {
+            // There's nothing buffered, and buffering wouldn't save us any
+            // reads, so we'll read directly into the caller's buffer.
+            let max = buf.len().min(self.remain());
+            let n = self.r.read(&mut buf[..max])?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let n = {
+            let avail = self.fill_buf()?;
+            let n = avail.len().min(buf.len());
+            buf[..n].copy_from_slice(&avail[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a, R> BufRead for DeriveRead<'a, R>
+where
+    R: Read + ?Sized,
+{
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.consumed == self.filled {
+            self.buf.resize(BUF_LEN, 0);
+            let max = BUF_LEN.min(self.remain());
+            self.filled = self.r.read(&mut self.buf[..max])?;
+            self.consumed = 0;
+        }
+        Ok(&self.buf[self.consumed..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.filled - self.consumed);
+        self.consumed += amt;
+        self.pos += amt as u64;
+    }
+}
+
+impl<'a, R> Seek for DeriveRead<'a, R>
+where
+    R: Read + Seek + ?Sized,
+{
+    /// Moves to a new position relative to the start of the region.
+    /// Positions after the end of the region are clamped to the end, and
+    /// positions before the start return an error.
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match to {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(delta) => (self.len, delta),
+            SeekFrom::Current(delta) => (self.pos, delta),
+        };
+        let target = base
+            .checked_add_signed(delta)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "cannot seek before the start of the region",
+                )
+            })?
+            .min(self.len);
+
+        // The underlying stream is ahead of our position by however many
+        // bytes are still buffered.
+        let actual = self.pos + (self.filled - self.consumed) as u64;
+        self.r
+            .seek(SeekFrom::Current(target as i64 - actual as i64))?;
+        self.pos = target;
+        self.consumed = 0;
+        self.filled = 0;
+        Ok(target)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
     }
 }
@@ -1,9 +1,30 @@
-/// A sealed trait that has only [`LittleEndian`](LittleEndian) and
-/// [`BigEndian`](BigEndian) as its implementations.
-pub trait Endian: private::Sealed {
+/// A sealed trait for the type parameters of [`Writer`](crate::Writer) and
+/// [`Reader`](crate::Reader) that select a byte order, which has
+/// [`LittleEndian`](LittleEndian), [`BigEndian`](BigEndian),
+/// [`MiddleEndian`](MiddleEndian), and [`DynEndian`](DynEndian) as its
+/// implementations.
+pub trait ByteOrder: private::Sealed {
+    /// The byte order this type selects, or `None` if the byte order is
+    /// instead selected at runtime.
+    const FIXED: Option<Endianness>;
+}
+
+/// A sealed trait that has only [`LittleEndian`](LittleEndian),
+/// [`BigEndian`](BigEndian), and [`MiddleEndian`](MiddleEndian) as its
+/// implementations, selecting a byte order at compile time.
+///
+/// [`DynEndian`](DynEndian) doesn't implement this trait because it has no
+/// byte order of its own, so it can't be used to pack or unpack values
+/// directly.
+pub trait Endian: ByteOrder {
     /// Writes the least significant `into.len()` bytes from `v` into the
-    /// buffer that `into` refers to.
-    fn write_integer(v: u64, into: &mut [u8]);
+    /// buffer that `into` refers to. If `into` is longer than sixteen bytes
+    /// then the extra, most significant, bytes are zero.
+    fn write_integer(v: u128, into: &mut [u8]);
+
+    /// Reads an unsigned integer from all of the bytes in `from`, which must
+    /// be no longer than sixteen bytes.
+    fn read_integer(from: &[u8]) -> u128;
 }
 
 /// Selects little-endian encoding in type parameters that represent selectable
@@ -12,13 +33,23 @@ pub trait Endian: private::Sealed {
 /// There are no values of this type.
 pub enum LittleEndian {}
 
+impl ByteOrder for LittleEndian {
+    const FIXED: Option<Endianness> = Some(Endianness::Little);
+}
+
 impl Endian for LittleEndian {
-    fn write_integer(v: u64, into: &mut [u8]) {
+    fn write_integer(v: u128, into: &mut [u8]) {
         let l = into.len();
         for i in 0..l {
-            into[i] = (v >> (8 * i)) as u8;
+            into[i] = v.checked_shr(8 * i as u32).unwrap_or(0) as u8;
         }
     }
+
+    fn read_integer(from: &[u8]) -> u128 {
+        from.iter()
+            .enumerate()
+            .fold(0, |v, (i, b)| v | ((*b as u128) << (8 * i)))
+    }
 }
 
 /// Selects big-endian encoding in type parameters that represent selectable
@@ -27,12 +58,171 @@ impl Endian for LittleEndian {
 /// There are no values of this type.
 pub enum BigEndian {}
 
+impl ByteOrder for BigEndian {
+    const FIXED: Option<Endianness> = Some(Endianness::Big);
+}
+
 impl Endian for BigEndian {
-    fn write_integer(v: u64, into: &mut [u8]) {
+    fn write_integer(v: u128, into: &mut [u8]) {
         let l = into.len();
         for i in 0..l {
             let shift = 8 * (l - i - 1);
-            into[i] = (v >> shift) as u8;
+            into[i] = v.checked_shr(shift as u32).unwrap_or(0) as u8;
+        }
+    }
+
+    fn read_integer(from: &[u8]) -> u128 {
+        from.iter().fold(0, |v, b| (v << 8) | (*b as u128))
+    }
+}
+
+/// Selects the "middle-endian" byte order used by the PDP-11 for 32-bit
+/// values, in type parameters that represent selectable endianness.
+///
+/// Values are split into 16-bit words that are written most significant
+/// word first, but the bytes within each word are little-endian, so
+/// `0x0A0B0C0D_u32` is written as `0B 0A 0D 0C`. Sixteen-bit values are
+/// therefore the same as in [`LittleEndian`](LittleEndian). For values
+/// with an odd number of bytes, the final byte is the least significant.
+///
+/// There are no values of this type.
+pub enum MiddleEndian {}
+
+impl ByteOrder for MiddleEndian {
+    const FIXED: Option<Endianness> = Some(Endianness::Middle);
+}
+
+impl Endian for MiddleEndian {
+    fn write_integer(v: u128, into: &mut [u8]) {
+        BigEndian::write_integer(v, into);
+        for word in into.chunks_exact_mut(2) {
+            word.swap(0, 1);
+        }
+    }
+
+    fn read_integer(from: &[u8]) -> u128 {
+        let mut buf = [0_u8; 16];
+        let buf = &mut buf[..from.len()];
+        buf.copy_from_slice(from);
+        for word in buf.chunks_exact_mut(2) {
+            word.swap(0, 1);
+        }
+        BigEndian::read_integer(buf)
+    }
+}
+
+/// Selects the byte order of the target platform, which is an alias for
+/// either [`LittleEndian`](LittleEndian) or [`BigEndian`](BigEndian).
+#[cfg(target_endian = "little")]
+pub type NativeEndian = LittleEndian;
+
+/// Selects the byte order of the target platform, which is an alias for
+/// either [`LittleEndian`](LittleEndian) or [`BigEndian`](BigEndian).
+#[cfg(target_endian = "big")]
+pub type NativeEndian = BigEndian;
+
+/// Selects an endianness chosen at runtime, rather than at compile time,
+/// in type parameters that represent selectable endianness.
+///
+/// This is for formats like TIFF where the byte order is recorded in the
+/// file itself. A [`Writer`](crate::Writer) or [`Reader`](crate::Reader)
+/// using `DynEndian` carries an [`Endianness`](Endianness) value that it
+/// consults for each value it encodes or decodes. Use
+/// [`write_dyn`](crate::write_dyn) or [`read_dyn`](crate::read_dyn) to
+/// create one.
+///
+/// `DynEndian` implements [`ByteOrder`](ByteOrder) but not
+/// [`Endian`](Endian), so it can't be used to pack or unpack values
+/// directly. Use the methods of [`Endianness`](Endianness) for that
+/// instead:
+///
+/// ```compile_fail
+/// use binbin::endian::DynEndian;
+/// use binbin::pack::Pack;
+///
+/// let mut buf = [0_u8; 4];
+/// 1_u32.pack_into_slice::<DynEndian>(&mut buf);
+/// ```
+///
+/// There are no values of this type.
+pub enum DynEndian {}
+
+impl ByteOrder for DynEndian {
+    const FIXED: Option<Endianness> = None;
+}
+
+/// A byte order selected at runtime.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    /// Little-endian, equivalent to [`LittleEndian`](LittleEndian).
+    Little,
+
+    /// Big-endian, equivalent to [`BigEndian`](BigEndian).
+    Big,
+
+    /// PDP-11 middle-endian, equivalent to [`MiddleEndian`](MiddleEndian).
+    Middle,
+}
+
+impl Endianness {
+    /// Returns the endianness selected by type `E`, or `runtime` if `E`
+    /// defers the choice until runtime.
+    pub fn select<E: ByteOrder>(runtime: Endianness) -> Endianness {
+        match E::FIXED {
+            Some(e) => e,
+            None => runtime,
+        }
+    }
+
+    /// Writes the least significant `into.len()` bytes from `v` into the
+    /// buffer that `into` refers to, using this byte order.
+    pub fn write_integer(self, v: u128, into: &mut [u8]) {
+        match self {
+            Endianness::Little => LittleEndian::write_integer(v, into),
+            Endianness::Big => BigEndian::write_integer(v, into),
+            Endianness::Middle => MiddleEndian::write_integer(v, into),
+        }
+    }
+
+    /// Reads an unsigned integer from all of the bytes in `from` using this
+    /// byte order.
+    pub fn read_integer(self, from: &[u8]) -> u128 {
+        match self {
+            Endianness::Little => LittleEndian::read_integer(from),
+            Endianness::Big => BigEndian::read_integer(from),
+            Endianness::Middle => MiddleEndian::read_integer(from),
+        }
+    }
+
+    /// Packs the given value into the given slice using this byte order.
+    pub fn pack_into_slice<P: crate::pack::Pack + ?Sized>(self, v: &P, into: &mut [u8]) {
+        match self {
+            Endianness::Little => v.pack_into_slice::<LittleEndian>(into),
+            Endianness::Big => v.pack_into_slice::<BigEndian>(into),
+            Endianness::Middle => v.pack_into_slice::<MiddleEndian>(into),
+        }
+    }
+
+    /// Packs the given value using this byte order and writes it to the
+    /// given writer, returning the number of bytes written.
+    pub fn pack_to_writer<P, W>(self, v: &P, w: &mut W) -> std::io::Result<usize>
+    where
+        P: crate::pack::Pack + ?Sized,
+        W: std::io::Write + ?Sized,
+    {
+        match self {
+            Endianness::Little => v.pack_to_writer::<LittleEndian, W>(w),
+            Endianness::Big => v.pack_to_writer::<BigEndian, W>(w),
+            Endianness::Middle => v.pack_to_writer::<MiddleEndian, W>(w),
+        }
+    }
+
+    /// Unpacks a value from the given slice using this byte order.
+    pub fn unpack_from_slice<T: crate::unpack::Unpack>(self, from: &[u8]) -> T {
+        match self {
+            Endianness::Little => T::unpack_from_slice::<LittleEndian>(from),
+            Endianness::Big => T::unpack_from_slice::<BigEndian>(from),
+            Endianness::Middle => T::unpack_from_slice::<MiddleEndian>(from),
         }
     }
 }
@@ -42,4 +232,6 @@ mod private {
 
     impl Sealed for super::BigEndian {}
     impl Sealed for super::LittleEndian {}
+    impl Sealed for super::MiddleEndian {}
+    impl Sealed for super::DynEndian {}
 }
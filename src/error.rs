@@ -0,0 +1,130 @@
+/// An error from a [`Writer`](crate::Writer) operation, along with some
+/// context about where in the output it occurred.
+///
+/// The methods of [`Writer`](crate::Writer) return
+/// [`std::io::Error`](std::io::Error) for compatibility with other code
+/// that works with I/O streams, but when context is available the
+/// `io::Error` wraps a value of this type. Use
+/// [`Error::from_io`](Error::from_io) to recover it, or just format the
+/// `io::Error` for display, which includes the context.
+#[derive(Debug)]
+pub struct Error {
+    source: std::io::Error,
+    position: Option<u64>,
+    context: Vec<String>,
+    slot: Option<usize>,
+}
+
+impl Error {
+    pub(crate) fn new(
+        source: std::io::Error,
+        position: Option<u64>,
+        context: Vec<String>,
+        slot: Option<usize>,
+    ) -> Self {
+        Self {
+            source,
+            position,
+            context,
+            slot,
+        }
+    }
+
+    /// Returns the context wrapped in the given `io::Error`, if any.
+    pub fn from_io(err: &std::io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref::<Self>()
+    }
+
+    /// Returns the underlying I/O error.
+    pub fn io_error(&self) -> &std::io::Error {
+        &self.source
+    }
+
+    /// Returns the position in the current stream when the error occurred,
+    /// if known.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Returns the names of the [`context`](crate::Writer::context)
+    /// regions that were active when the error occurred, outermost first.
+    pub fn context(&self) -> &[String] {
+        &self.context
+    }
+
+    /// Returns the index of the deferred slot involved in the error, if
+    /// the error occurred while resolving one.
+    pub fn slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    /// Consumes the error, returning the underlying I/O error.
+    pub fn into_io_error(self) -> std::io::Error {
+        self.source
+    }
+
+    /// Returns true if the given error, or the error it wraps, is a
+    /// [`Cancelled`](Cancelled) error.
+    pub fn is_cancelled(err: &std::io::Error) -> bool {
+        let err = match Self::from_io(err) {
+            Some(wrapped) => &wrapped.source,
+            None => err,
+        };
+        err.get_ref()
+            .is_some_and(|inner| inner.downcast_ref::<Cancelled>().is_some())
+    }
+}
+
+/// The error wrapped in the `io::Error` returned when writing is cancelled
+/// using the token given to
+/// [`Writer::set_cancel`](crate::Writer::set_cancel).
+///
+/// The `io::Error` has kind [`Other`](std::io::ErrorKind::Other) rather
+/// than [`Interrupted`](std::io::ErrorKind::Interrupted), because standard
+/// library functions such as `write_all` retry interrupted writes. Use
+/// [`Error::is_cancelled`](Error::is_cancelled) to recognize it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("writing was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl From<Cancelled> for std::io::Error {
+    fn from(err: Cancelled) -> Self {
+        std::io::Error::other(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.context.is_empty() {
+            write!(f, "in {}", self.context.join(" > "))?;
+        } else {
+            write!(f, "in output")?;
+        }
+        if let Some(pos) = self.position {
+            write!(f, " at {:#x}", pos)?;
+        }
+        if let Some(slot) = self.slot {
+            write!(f, " resolving deferred slot {}", slot)?;
+        }
+        write!(f, ": {}", self.source)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        std::io::Error::new(err.source.kind(), err)
+    }
+}
@@ -0,0 +1,120 @@
+use crate::endian::Endian;
+use crate::pack::{FixedLenPack, Pack};
+use crate::unpack::Unpack;
+
+/// Packs a fixed-point number as an integer of type `T`, where the least
+/// significant `FRAC` bits are the fractional part.
+///
+/// The integer type determines both the size of the packed value and
+/// whether it's signed, and the remaining bits are the integer part. For
+/// example, `Fixed<i16, 14>` is the "F2Dot14" format used in TrueType and
+/// OpenType fonts, with a two-bit signed integer part, and
+/// `Fixed<u32, 16>` is an unsigned Q16.16 number. The wrapped integer is the
+/// raw packed value.
+///
+/// Conversions from floating point values round to the nearest
+/// representable value, and saturate at the minimum or maximum value of the
+/// type for values outside of its range.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed<T, const FRAC: u32>(pub T);
+
+/// The signed 2.14 format used for font outline data, as in TrueType and
+/// OpenType.
+pub type F2Dot14 = Fixed<i16, 14>;
+
+/// The signed 16.16 format used as `Fixed` in TrueType and OpenType, and
+/// widely elsewhere.
+pub type Fixed16Dot16 = Fixed<i32, 16>;
+
+/// A sealed trait implemented by the integer types that can represent a
+/// [`Fixed`](Fixed) value.
+pub trait FixedRepr: FixedLenPack + Unpack + Copy + private::Sealed {}
+
+impl<T: FixedRepr, const FRAC: u32> Fixed<T, FRAC> {
+    /// Returns the fixed-point number nearest to the given value.
+    pub fn from_f64(v: f64) -> Self {
+        Self(T::from_f64((v * scale(FRAC)).round()))
+    }
+
+    /// Returns the fixed-point number nearest to the given value.
+    pub fn from_f32(v: f32) -> Self {
+        Self::from_f64(v as f64)
+    }
+
+    /// Returns the value of the fixed-point number, which may be rounded if
+    /// the integer type is wider than the mantissa of an `f64`.
+    pub fn to_f64(self) -> f64 {
+        self.0.to_f64() / scale(FRAC)
+    }
+
+    /// Returns the value of the fixed-point number, rounded to the nearest
+    /// `f32`.
+    pub fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+}
+
+fn scale(frac: u32) -> f64 {
+    2_f64.powi(frac as i32)
+}
+
+impl<T: FixedRepr, const FRAC: u32> From<f64> for Fixed<T, FRAC> {
+    fn from(v: f64) -> Self {
+        Self::from_f64(v)
+    }
+}
+
+impl<T: FixedRepr, const FRAC: u32> From<f32> for Fixed<T, FRAC> {
+    fn from(v: f32) -> Self {
+        Self::from_f32(v)
+    }
+}
+
+impl<T: FixedRepr, const FRAC: u32> Pack for Fixed<T, FRAC> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl<T: FixedRepr, const FRAC: u32> FixedLenPack for Fixed<T, FRAC> {
+    const PACK_LEN: usize = T::PACK_LEN;
+}
+
+impl<T: FixedRepr, const FRAC: u32> Unpack for Fixed<T, FRAC> {
+    const UNPACK_LEN: usize = T::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(T::unpack_from_slice::<E>(buf))
+    }
+}
+
+macro_rules! fixed_repr {
+    ($($t:ty),+) => {
+        $(
+            impl FixedRepr for $t {}
+            impl private::Sealed for $t {
+                fn from_f64(v: f64) -> Self {
+                    // Float to integer casts saturate, and produce zero for NaN.
+                    v as $t
+                }
+
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )+
+    };
+}
+
+fixed_repr!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+mod private {
+    pub trait Sealed {
+        fn from_f64(v: f64) -> Self;
+        fn to_f64(self) -> f64;
+    }
+}
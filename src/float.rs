@@ -0,0 +1,182 @@
+use crate::endian::Endian;
+use crate::pack::{FixedLenPack, Pack};
+use crate::unpack::Unpack;
+
+/// Packs an IEEE 754 binary16 "half precision" floating point number.
+///
+/// The wrapped integer is the raw bit pattern. Use [`from_f32`](Self::from_f32)
+/// to convert from a single precision number, which rounds to the nearest
+/// representable value, with ties to even.
+///
+/// Slices of `F16` can be written using
+/// [`Writer::write_slice`](crate::Writer::write_slice), and
+/// [`Writer::write_slice_as`](crate::Writer::write_slice_as) can convert a
+/// slice of `f32` while writing it.
+///
+/// This crate doesn't depend on the `half` crate, so its `f16` and `bf16`
+/// types don't implement [`Pack`](Pack) directly. Convert them using
+/// `F16(h.to_bits())` or `Bf16(h.to_bits())`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct F16(pub u16);
+
+/// Packs a "bfloat16" floating point number, which is the most significant
+/// half of an IEEE 754 single precision number.
+///
+/// The wrapped integer is the raw bit pattern. Use [`from_f32`](Self::from_f32)
+/// to convert from a single precision number, which rounds to the nearest
+/// representable value, with ties to even.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Bf16(pub u16);
+
+impl F16 {
+    /// Returns the half precision number nearest to the given value.
+    pub fn from_f32(v: f32) -> Self {
+        let x = v.to_bits();
+        let sign = ((x >> 16) & 0x8000) as u16;
+        let exp = ((x >> 23) & 0xff) as i32;
+        let man = x & 0x7f_ffff;
+        if exp == 0xff {
+            // Infinities stay infinite, and NaNs must keep at least one
+            // mantissa bit set.
+            let nan = if man != 0 {
+                0x200 | (man >> 13) as u16
+            } else {
+                0
+            };
+            return Self(sign | 0x7c00 | nan);
+        }
+        let exp = exp - 127 + 15;
+        if exp >= 0x1f {
+            return Self(sign | 0x7c00);
+        }
+        if exp <= 0 {
+            // The result is subnormal, or too small to represent at all.
+            if exp < -10 {
+                return Self(sign);
+            }
+            let man = man | 0x80_0000;
+            let shift = (14 - exp) as u32;
+            return Self(sign | round_shr(man, shift) as u16);
+        }
+        // Rounding up may carry into the exponent, which is correct even
+        // when it produces infinity.
+        Self(sign | round_shr((exp as u32) << 23 | man, 13) as u16)
+    }
+
+    /// Returns the value of the half precision number, which is always
+    /// exactly representable as an `f32`.
+    pub fn to_f32(self) -> f32 {
+        let h = self.0 as u32;
+        let sign = (h & 0x8000) << 16;
+        let exp = (h >> 10) & 0x1f;
+        let man = h & 0x3ff;
+        match exp {
+            0 => {
+                let v = man as f32 * 2_f32.powi(-24);
+                f32::from_bits(v.to_bits() | sign)
+            }
+            0x1f => f32::from_bits(sign | 0x7f80_0000 | man << 13),
+            _ => f32::from_bits(sign | (exp + 112) << 23 | man << 13),
+        }
+    }
+}
+
+impl Bf16 {
+    /// Returns the bfloat16 number nearest to the given value.
+    pub fn from_f32(v: f32) -> Self {
+        let x = v.to_bits();
+        if v.is_nan() {
+            return Self((x >> 16) as u16 | 0x40);
+        }
+        Self(round_shr(x, 16) as u16)
+    }
+
+    /// Returns the value of the bfloat16 number, which is always exactly
+    /// representable as an `f32`.
+    pub fn to_f32(self) -> f32 {
+        f32::from_bits((self.0 as u32) << 16)
+    }
+}
+
+/// Shifts `v` right by `shift` bits, rounding to the nearest result with
+/// ties to even.
+fn round_shr(v: u32, shift: u32) -> u32 {
+    let half = 1 << (shift - 1);
+    let rem = v & ((1 << shift) - 1);
+    let ret = v >> shift;
+    if rem > half || (rem == half && ret & 1 == 1) {
+        ret + 1
+    } else {
+        ret
+    }
+}
+
+impl From<f32> for F16 {
+    fn from(v: f32) -> Self {
+        Self::from_f32(v)
+    }
+}
+
+impl From<F16> for f32 {
+    fn from(v: F16) -> Self {
+        v.to_f32()
+    }
+}
+
+impl From<f32> for Bf16 {
+    fn from(v: f32) -> Self {
+        Self::from_f32(v)
+    }
+}
+
+impl From<Bf16> for f32 {
+    fn from(v: Bf16) -> Self {
+        v.to_f32()
+    }
+}
+
+impl Pack for F16 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for F16 {
+    const PACK_LEN: usize = <u16 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for F16 {
+    const UNPACK_LEN: usize = <u16 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(u16::unpack_from_slice::<E>(buf))
+    }
+}
+
+impl Pack for Bf16 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for Bf16 {
+    const PACK_LEN: usize = <u16 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for Bf16 {
+    const UNPACK_LEN: usize = <u16 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(u16::unpack_from_slice::<E>(buf))
+    }
+}
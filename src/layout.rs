@@ -0,0 +1,229 @@
+use std::io::Write;
+use std::ops::Range;
+
+use crate::endian::Endianness;
+
+/// A description of the layout of some output, listing the regions named
+/// using [`Writer::annotate`](crate::Writer::annotate) and the positions
+/// of the placeholders for each deferred slot.
+///
+/// Use [`Builder::write_with_layout`](crate::Builder::write_with_layout)
+/// to retrieve the layout of a complete output, or
+/// [`Writer::layout_map`](crate::Writer::layout_map) for the layout so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayoutMap {
+    pub(crate) regions: Vec<Region>,
+    pub(crate) slots: Vec<SlotLayout>,
+}
+
+impl LayoutMap {
+    /// Returns the named regions, in the order they were started, so that
+    /// each region appears before any regions nested inside it.
+    ///
+    /// Regions written inside a [`buffered`](crate::Writer::buffered) region
+    /// that was never emitted are not included.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the placeholders of each deferred slot, in the order the
+    /// slots were created.
+    pub fn slots(&self) -> &[SlotLayout] {
+        &self.slots
+    }
+
+    /// Writes a machine-readable description of the layout as JSON, for
+    /// use with hex editors and other tools that can import field
+    /// descriptions.
+    ///
+    /// The result is an object with a `regions` array, where each region
+    /// has its `name`, the `path` of names from
+    /// [`Region::path`](Region::path), its `offset` and `size` in bytes,
+    /// and its `endianness`, and a `slots` array, where each slot has its
+    /// `index`, whether it was `resolved`, and its `placeholders`, each
+    /// with an `offset`, `size`, and `endianness`.
+    pub fn write_json<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"regions\": [")?;
+        for (i, region) in self.regions.iter().enumerate() {
+            let path: Vec<_> = region.path.iter().map(|name| json_str(name)).collect();
+            write!(
+                w,
+                "    {{\"name\": {}, \"path\": [{}], \"offset\": {}, \"size\": {}, \"endianness\": \"{}\"}}",
+                json_str(region.name()),
+                path.join(", "),
+                region.range.start,
+                region.len(),
+                endianness_name(region.endianness),
+            )?;
+            writeln!(w, "{}", if i + 1 < self.regions.len() { "," } else { "" })?;
+        }
+        writeln!(w, "  ],")?;
+        writeln!(w, "  \"slots\": [")?;
+        for (i, slot) in self.slots.iter().enumerate() {
+            let placeholders: Vec<_> = slot
+                .placeholders
+                .iter()
+                .zip(slot.endianness.iter())
+                .map(|(rng, endianness)| {
+                    format!(
+                        "{{\"offset\": {}, \"size\": {}, \"endianness\": \"{}\"}}",
+                        rng.start,
+                        rng.end - rng.start,
+                        endianness_name(*endianness),
+                    )
+                })
+                .collect();
+            write!(
+                w,
+                "    {{\"index\": {}, \"resolved\": {}, \"placeholders\": [{}]}}",
+                slot.index,
+                slot.resolved,
+                placeholders.join(", "),
+            )?;
+            writeln!(w, "{}", if i + 1 < self.slots.len() { "," } else { "" })?;
+        }
+        writeln!(w, "  ]")?;
+        writeln!(w, "}}")
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn endianness_name(endianness: Endianness) -> &'static str {
+    match endianness {
+        Endianness::Little => "little",
+        Endianness::Big => "big",
+        Endianness::Middle => "middle",
+    }
+}
+
+impl std::fmt::Display for LayoutMap {
+    /// Formats the map as a human-readable table, with nested regions
+    /// indented beneath the regions that contain them.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for region in &self.regions {
+            writeln!(
+                f,
+                "{:#010x}..{:#010x} {:>8} {:indent$}{}",
+                region.range.start,
+                region.range.end,
+                region.len(),
+                "",
+                region.name(),
+                indent = (region.path.len() - 1) * 2,
+            )?;
+        }
+        for slot in &self.slots {
+            let positions: Vec<_> = slot
+                .placeholders
+                .iter()
+                .map(|rng| format!("{:#x}", rng.start))
+                .collect();
+            writeln!(
+                f,
+                "slot {}{}: {}",
+                slot.index,
+                if slot.resolved { "" } else { " (unresolved)" },
+                positions.join(", "),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A region of the output named using
+/// [`Writer::annotate`](crate::Writer::annotate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub(crate) path: Vec<String>,
+    pub(crate) range: Range<u64>,
+    pub(crate) endianness: Endianness,
+}
+
+impl Region {
+    /// Returns the region's own name.
+    pub fn name(&self) -> &str {
+        // Annotated regions always add at least their own name.
+        self.path.last().unwrap()
+    }
+
+    /// Returns the names of the regions and error contexts enclosing this
+    /// one, outermost first, followed by the region's own name.
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// Returns the range of positions the region occupies.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Returns the length of the region, in bytes.
+    pub fn len(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    /// Returns true if the region is zero bytes long.
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    /// Returns the byte order the writer was using when the region began.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Returns the largest power of two that the region's start position
+    /// is a multiple of, up to 2^63.
+    pub fn alignment(&self) -> u64 {
+        1 << self.range.start.trailing_zeros().min(63)
+    }
+}
+
+/// The placeholders of a deferred slot, as recorded in a
+/// [`LayoutMap`](LayoutMap).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotLayout {
+    pub(crate) index: usize,
+    pub(crate) placeholders: Vec<Range<u64>>,
+    pub(crate) endianness: Vec<Endianness>,
+    pub(crate) resolved: bool,
+}
+
+impl SlotLayout {
+    /// Returns the slot's index, as used in error messages.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the range of positions of each of the slot's placeholders.
+    pub fn placeholders(&self) -> &[Range<u64>] {
+        &self.placeholders
+    }
+
+    /// Returns the byte order of each of the slot's placeholders, in the
+    /// same order as [`placeholders`](Self::placeholders).
+    pub fn endianness(&self) -> &[Endianness] {
+        &self.endianness
+    }
+
+    /// Returns true if the slot has been resolved.
+    pub fn resolved(&self) -> bool {
+        self.resolved
+    }
+}
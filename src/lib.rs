@@ -35,8 +35,10 @@
 //!     w.write(0 as u32)?; // number of program header entries (none)
 //!     let section_header_size = w.write_deferred(0 as u32)?;
 //!     let section_header_count = w.write_deferred(0 as u32)?;
+//!     w.allow_unresolved(section_header_size);
+//!     w.allow_unresolved(section_header_count);
 //!     let header_end = w.position()? as u32;
-//!     w.resolve(header_len, header_end - header_start);
+//!     w.resolve(header_len, header_end - header_start)?;
 //!     w.write(0 as u32)?; // no string table
 //!
 //!     w.align(4)?;
@@ -68,7 +70,8 @@
 //!     0x00, 0x00, 0x00, 0x00, // number of program header entries
 //!
 //!     // Section header entries were deferred but never resolved,
-//!     // so they retain their placeholder values.
+//!     // (which we explicitly allowed) so they retain their placeholder
+//!     // values.
 //!     0x00, 0x00, 0x00, 0x00, // size of section header entry
 //!     0x00, 0x00, 0x00, 0x00, // number of section header entries
 //!
@@ -83,6 +86,15 @@ use std::io::{Read, Result, Seek, Write};
 /// Representation of values to be determined later.
 pub mod deferred;
 
+/// Errors with context about where in the output they occurred.
+pub mod error;
+
+/// Options for creating a [`Writer`](Writer).
+pub mod builder;
+
+/// Descriptions of the layout of written output, for debugging.
+pub mod layout;
+
 /// Types for representing endianness.
 pub mod endian;
 
@@ -92,11 +104,117 @@ pub mod pack;
 /// Types used with [`Writer::derive`](Writer::derive).
 pub mod derive;
 
+/// Traits for deserializing data for [`Reader::read`](Reader::read).
+pub mod unpack;
+
+/// The reading counterpart of [`Writer`](Writer).
+pub mod reader;
+
+/// Variable-length integer encodings.
+pub mod varint;
+
+/// Packing bit fields into integers.
+pub mod bitfield;
+
+/// Binary-coded decimal encodings.
+pub mod bcd;
+
+/// Reduced-precision floating point encodings.
+pub mod float;
+
+/// Fixed-point number encodings.
+pub mod fixed;
+
+/// Timestamp encodings used in file formats.
+pub mod timestamp;
+
+/// Writing structs laid out as a C compiler would, for use with
+/// [`Writer::c_struct`](Writer::c_struct).
+pub mod abi;
+
+/// String tables for use with
+/// [`Writer::emit_string_table`](Writer::emit_string_table).
+pub mod strtab;
+
+/// Observing the bytes written to a stream.
+pub mod tee;
+
+/// Measuring output without producing it.
+pub mod measure;
+
+mod stream;
+
+mod trace;
+
+mod progress;
+
+/// Writing directly into byte vectors, for use with
+/// [`append_vec_le`](append_vec_le) and similar.
+pub mod vecbuf;
+
+/// Writing directly into fixed-size byte slices, such as memory-mapped
+/// files.
+pub mod slicebuf;
+
+/// Positioned storage backends, adapted as streams for use with
+/// [`Writer`](Writer).
+pub mod storage;
+
+/// Saving the offsets of named symbols between sessions, for use with
+/// [`append_le`](append_le) and similar.
+pub mod registry;
+
+/// Splitting output across multiple volumes of limited size.
+pub mod volume;
+
+/// Size limits for subregions, for use with
+/// [`Writer::subregion_limited`](Writer::subregion_limited).
+pub mod limit;
+
+/// Planning the layout of interdependent sections, for use with
+/// [`Writer::write_planned`](Writer::write_planned).
+pub mod plan;
+
+/// Relocations for references to labels that aren't placed, for use with
+/// [`Writer::write_reloc`](Writer::write_reloc).
+pub mod reloc;
+
+/// Sections built independently and then emitted with
+/// [`Writer::emit_section`](Writer::emit_section).
+pub mod section;
+
+/// Compression of subregions, for use with
+/// [`Writer::compressed_subregion`](Writer::compressed_subregion).
+#[cfg(feature = "compress")]
+pub mod compress;
+
+/// Offset tables for use with [`Writer::emit_table`](Writer::emit_table).
+pub mod offtab;
+
+/// Checksum algorithms, for use with [`Writer::derive`](Writer::derive)
+/// and similar.
+#[cfg(feature = "checksum")]
+pub mod checksum;
+
+/// Helpers for testing code that writes binary formats.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 #[cfg(test)]
 mod tests;
 
-use deferred::Deferred;
-use endian::{BigEndian, Endian, LittleEndian};
+pub use builder::Builder;
+use deferred::{
+    BufferState, Buffered, Counter, Deferred, DeferredOffset, Label, LabelState, Location, RefKind,
+    Reservation,
+};
+use endian::{
+    BigEndian, ByteOrder, DynEndian, Endian, Endianness, LittleEndian, MiddleEndian, NativeEndian,
+};
+pub use error::Error;
+use offtab::{OffsetTable, TableEntry};
+pub use reader::Reader;
+use strtab::StringTable;
 
 /// Writes arbitrary binary data to the given writer `w` using the given
 /// function `f`, where writes will be little-endian by default.
@@ -151,12 +269,9 @@ pub fn write<W, F, E, R>(w: &mut W, f: F) -> Result<R>
 where
     W: Write + Seek,
     for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
-    E: Endian,
+    E: ByteOrder,
 {
-    let mut wr = Writer::new(w);
-    let ret = f(&mut wr)?;
-    wr.finalize()?;
-    return Ok(ret);
+    Builder::new().write(w, f)
 }
 
 /// Generic equivalent of [`write_vec_le`](write_vec_le) and
@@ -165,10 +280,458 @@ where
 pub fn write_vec<F, E, R>(into: &mut Vec<u8>, f: F) -> Result<R>
 where
     for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>) -> Result<R>,
-    E: Endian,
+    E: ByteOrder,
+{
+    Builder::new().write_vec(into, f)
+}
+
+/// Appends arbitrary binary data to the given byte vector using the given
+/// function `f`, writing little-endian by default.
+///
+/// Unlike [`write_vec_le`](write_vec_le), this writes to the vector through
+/// a [`VecBuf`](vecbuf::VecBuf) rather than a [`Cursor`](std::io::Cursor),
+/// so updating placeholders is just a slice write. Positions are indices
+/// into the whole vector, including any content it already had.
+pub fn append_vec_le<'v, F, R>(into: &'v mut Vec<u8>, f: F) -> Result<R>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut vecbuf::VecBuf<'v>, LittleEndian>) -> Result<R>,
+{
+    append_vec::<_, LittleEndian, _>(into, f)
+}
+
+/// Appends arbitrary binary data to the given byte vector using the given
+/// function `f`, writing big-endian by default.
+///
+/// This is the big-endian equivalent of [`append_vec_le`](append_vec_le).
+pub fn append_vec_be<'v, F, R>(into: &'v mut Vec<u8>, f: F) -> Result<R>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut vecbuf::VecBuf<'v>, BigEndian>) -> Result<R>,
+{
+    append_vec::<_, BigEndian, _>(into, f)
+}
+
+/// Generic equivalent of [`append_vec_le`](append_vec_le) and
+/// [`append_vec_be`](append_vec_be), with endianness selected by a type
+/// parameter.
+pub fn append_vec<'v, F, E, R>(into: &'v mut Vec<u8>, f: F) -> Result<R>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut vecbuf::VecBuf<'v>, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    Builder::new().append_vec(into, f)
+}
+
+/// Appends arbitrary binary data to the end of the given stream using the
+/// given function `f`, where writes will be little-endian by default.
+///
+/// Positions are offsets from the start of the stream, so that new data
+/// can refer to data written earlier, such as in previous runs of the
+/// program. Use a [`Registry`](registry::Registry) to save the offsets of
+/// named symbols between runs.
+pub fn append_le<W, F, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut W, LittleEndian>) -> Result<R>,
+{
+    append::<_, _, LittleEndian, _>(w, f)
+}
+
+/// Appends arbitrary binary data to the end of the given stream using the
+/// given function `f`, where writes will be big-endian by default.
+///
+/// This is the big-endian equivalent of [`append_le`](append_le).
+pub fn append_be<W, F, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut W, BigEndian>) -> Result<R>,
+{
+    append::<_, _, BigEndian, _>(w, f)
+}
+
+/// Generic equivalent of [`append_le`](append_le) and
+/// [`append_be`](append_be), with endianness selected by a type parameter.
+pub fn append<W, F, E, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut W, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    Builder::new().append(w, f)
+}
+
+/// Edits existing data in the given stream using the given function `f`,
+/// where writes will be little-endian by default.
+///
+/// The writer starts at the beginning of the stream, rather than at its
+/// current position, so that positions are offsets into the existing
+/// data. Use [`Writer::read_at`](Writer::read_at) and
+/// [`Writer::derive`](Writer::derive) to inspect the existing data, and
+/// [`Writer::patch`](Writer::patch) or [`Writer::at`](Writer::at) to
+/// rewrite fields at known positions. Data that isn't overwritten is left
+/// unchanged.
+///
+/// ```
+/// # use std::io::{Cursor, Result};
+/// # fn main() -> Result<()> {
+/// let mut file = Cursor::new(b"\x02\x00\x00\x00data".to_vec());
+/// binbin::edit_le(&mut file, |w| {
+///     let version: u32 = w.read_at(0)?;
+///     w.patch(0, version + 1)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(file.into_inner(), b"\x03\x00\x00\x00data");
+/// # Ok(())
+/// # }
+/// ```
+pub fn edit_le<RW, F, R>(rw: &mut RW, f: F) -> Result<R>
+where
+    RW: Read + Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut RW, LittleEndian>) -> Result<R>,
+{
+    edit::<_, _, LittleEndian, _>(rw, f)
+}
+
+/// Edits existing data in the given stream using the given function `f`,
+/// where writes will be big-endian by default.
+///
+/// This is the big-endian equivalent of [`edit_le`](edit_le).
+pub fn edit_be<RW, F, R>(rw: &mut RW, f: F) -> Result<R>
+where
+    RW: Read + Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut RW, BigEndian>) -> Result<R>,
+{
+    edit::<_, _, BigEndian, _>(rw, f)
+}
+
+/// Generic equivalent of [`edit_le`](edit_le) and [`edit_be`](edit_be),
+/// with endianness selected by a type parameter.
+pub fn edit<RW, F, E, R>(rw: &mut RW, f: F) -> Result<R>
+where
+    RW: Read + Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut RW, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    Builder::new().edit(rw, f)
+}
+
+/// Writes arbitrary binary data to the given writer `w` using the given
+/// function `f`, where writes will use the target platform's native byte
+/// order by default.
+///
+/// This is useful for formats that mirror in-memory data structures, such
+/// as memory dumps, but the result is not portable between platforms with
+/// different byte orders.
+pub fn write_ne<W, F, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut W, NativeEndian>) -> Result<R>,
+{
+    write::<_, _, NativeEndian, _>(w, f)
+}
+
+/// Writes arbitrary binary data into a byte vector using the given
+/// function `f`, writing using the target platform's native byte order by
+/// default.
+pub fn write_vec_ne<F, R>(into: &mut Vec<u8>, f: F) -> Result<R>
+where
+    for<'w> F:
+        FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, NativeEndian>) -> Result<R>,
+{
+    write_vec::<_, NativeEndian, _>(into, f)
+}
+
+/// Writes arbitrary binary data to the given writer `w` using the given
+/// function `f`, with the default endianness selected at runtime.
+///
+/// This is for formats like TIFF where the byte order is chosen per file.
+/// Where the byte order is known at compile time, prefer
+/// [`write_le`](write_le) or [`write_be`](write_be), which avoid checking
+/// the endianness for each value written.
+pub fn write_dyn<W, F, R>(w: &mut W, endianness: Endianness, f: F) -> Result<R>
+where
+    W: Write + Seek,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut W, DynEndian>) -> Result<R>,
+{
+    Builder::new().endianness(endianness).write(w, f)
+}
+
+/// Writes arbitrary binary data into a byte vector using the given
+/// function `f`, with the default endianness selected at runtime.
+pub fn write_vec_dyn<F, R>(into: &mut Vec<u8>, endianness: Endianness, f: F) -> Result<R>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, DynEndian>) -> Result<R>,
 {
     let mut cursor = std::io::Cursor::new(into);
-    write(&mut cursor, f)
+    write_dyn(&mut cursor, endianness, f)
+}
+
+/// Writes arbitrary binary data to the given non-seekable writer `w` using
+/// the given function `f`, where writes will be little-endian by default.
+///
+/// Because `w` cannot seek to update placeholders in-place, the entire
+/// output is buffered in memory and then written to `w` in a single pass
+/// once all deferred values are resolved. This makes it possible to write
+/// to streams such as sockets or standard output.
+pub fn write_buffered_le<W, F, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write,
+    for<'w> F:
+        FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, LittleEndian>) -> Result<R>,
+{
+    write_buffered::<_, _, LittleEndian, _>(w, f)
+}
+
+/// Writes arbitrary binary data to the given non-seekable writer `w` using
+/// the given function `f`, where writes will be big-endian by default.
+///
+/// Because `w` cannot seek to update placeholders in-place, the entire
+/// output is buffered in memory and then written to `w` in a single pass
+/// once all deferred values are resolved. This makes it possible to write
+/// to streams such as sockets or standard output.
+pub fn write_buffered_be<W, F, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, BigEndian>) -> Result<R>,
+{
+    write_buffered::<_, _, BigEndian, _>(w, f)
+}
+
+/// Generic equivalent of [`write_buffered_le`](write_buffered_le) and
+/// [`write_buffered_be`](write_buffered_be), with endianness selected by a
+/// type parameter.
+pub fn write_buffered<W, F, E, R>(w: &mut W, f: F) -> Result<R>
+where
+    W: Write,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    let mut buf = Vec::new();
+    let ret = write_vec(&mut buf, f)?;
+    w.write_all(&buf[..])?;
+    w.flush()?;
+    Ok(ret)
+}
+
+/// Runs the given function `f` against a writer that discards its output,
+/// where writes will be little-endian by default, and returns the length of
+/// the output along with the function's result.
+///
+/// This is useful for formats that need to know the size or layout of
+/// some of their content before writing it. See
+/// [`Discard`](measure::Discard) for how to run the same function for both
+/// measuring and writing.
+pub fn measure_le<F, R>(f: F) -> Result<(u64, R)>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Discard, LittleEndian>) -> Result<R>,
+{
+    measure::<_, LittleEndian, _>(f)
+}
+
+/// Runs the given function `f` against a writer that discards its output,
+/// where writes will be big-endian by default, and returns the length of
+/// the output along with the function's result.
+pub fn measure_be<F, R>(f: F) -> Result<(u64, R)>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Discard, BigEndian>) -> Result<R>,
+{
+    measure::<_, BigEndian, _>(f)
+}
+
+/// Generic equivalent of [`measure_le`](measure_le) and
+/// [`measure_be`](measure_be), with endianness selected by a type
+/// parameter.
+pub fn measure<F, E, R>(f: F) -> Result<(u64, R)>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Discard, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    let mut sink = measure::Discard::new();
+    let ret = write(&mut sink, f)?;
+    Ok((sink.len(), ret))
+}
+
+/// Runs the given function `f` against a writer that passes its output to
+/// the given observer and then discards it, where writes will be
+/// little-endian by default, and returns the length of the output along
+/// with the function's result.
+///
+/// This is for computing a checksum or hash of some output, such as to
+/// choose between two encodings, without keeping the output in memory.
+/// See [`Digest`](measure::Digest) for the restrictions on deferred values.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// let mut sum = 0_u32;
+/// let (len, ()) = binbin::digest_le(
+///     |b| sum += b.iter().map(|b| *b as u32).sum::<u32>(),
+///     |w| {
+///         w.write(0x0201_u16)?;
+///         w.write(&b"\x03"[..])?;
+///         Ok(())
+///     },
+/// )?;
+/// assert_eq!((len, sum), (3, 6));
+/// # Ok(())
+/// # }
+/// ```
+pub fn digest_le<O, F, R>(observer: O, f: F) -> Result<(u64, R)>
+where
+    O: FnMut(&[u8]),
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Digest<O>, LittleEndian>) -> Result<R>,
+{
+    digest::<_, _, LittleEndian, _>(observer, f)
+}
+
+/// Runs the given function `f` against a writer that passes its output to
+/// the given observer and then discards it, where writes will be
+/// big-endian by default, and returns the length of the output along with
+/// the function's result.
+pub fn digest_be<O, F, R>(observer: O, f: F) -> Result<(u64, R)>
+where
+    O: FnMut(&[u8]),
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Digest<O>, BigEndian>) -> Result<R>,
+{
+    digest::<_, _, BigEndian, _>(observer, f)
+}
+
+/// Generic equivalent of [`digest_le`](digest_le) and
+/// [`digest_be`](digest_be), with endianness selected by a type parameter.
+pub fn digest<O, F, E, R>(observer: O, f: F) -> Result<(u64, R)>
+where
+    O: FnMut(&[u8]),
+    for<'w> F: FnOnce(&mut Writer<'w, &mut measure::Digest<O>, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    let mut sink = measure::Digest::new(observer);
+    let ret = write(&mut sink, f)?;
+    Ok((sink.len(), ret))
+}
+
+/// Builds a [`Section`](section::Section) using the given function `f`,
+/// where writes will be little-endian by default.
+///
+/// All deferred slots created by `f` must be resolved before it returns,
+/// but labels may remain unplaced if they are named symbols.
+pub fn build_section_le<F, R>(f: F) -> Result<(section::Section, R)>
+where
+    for<'w> F:
+        FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, LittleEndian>) -> Result<R>,
+{
+    build_section::<_, LittleEndian, _>(f)
+}
+
+/// Builds a [`Section`](section::Section) using the given function `f`,
+/// where writes will be big-endian by default.
+///
+/// All deferred slots created by `f` must be resolved before it returns,
+/// but labels may remain unplaced if they are named symbols.
+pub fn build_section_be<F, R>(f: F) -> Result<(section::Section, R)>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, BigEndian>) -> Result<R>,
+{
+    build_section::<_, BigEndian, _>(f)
+}
+
+/// Generic equivalent of [`build_section_le`](build_section_le) and
+/// [`build_section_be`](build_section_be), with endianness selected by a
+/// type parameter.
+pub fn build_section<F, E, R>(f: F) -> Result<(section::Section, R)>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>) -> Result<R>,
+    E: ByteOrder,
+{
+    let mut data = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut data);
+    let mut wr = Writer::new(&mut cursor);
+    wr.state.in_section = true;
+    let ret = f(&mut wr)?;
+    let labels = wr.take_section_labels()?;
+    wr.finalize()?;
+    Ok((section::Section { data, labels }, ret))
+}
+
+/// Reads arbitrary binary data from the given reader `r` using the given
+/// function `f`, where reads will be little-endian by default.
+pub fn read_le<R, F, T>(r: &mut R, f: F) -> Result<T>
+where
+    R: Read + Seek,
+    F: FnOnce(&mut Reader<&mut R, LittleEndian>) -> Result<T>,
+{
+    read::<_, _, LittleEndian, _>(r, f)
+}
+
+/// Reads arbitrary binary data from a byte slice using the given function
+/// `f`, reading little-endian by default.
+pub fn read_slice_le<F, T>(from: &[u8], f: F) -> Result<T>
+where
+    F: FnOnce(&mut Reader<&mut std::io::Cursor<&[u8]>, LittleEndian>) -> Result<T>,
+{
+    read_slice::<_, LittleEndian, _>(from, f)
+}
+
+/// Reads arbitrary binary data from the given reader `r` using the given
+/// function `f`, where reads will be big-endian by default.
+pub fn read_be<R, F, T>(r: &mut R, f: F) -> Result<T>
+where
+    R: Read + Seek,
+    F: FnOnce(&mut Reader<&mut R, BigEndian>) -> Result<T>,
+{
+    read::<_, _, BigEndian, _>(r, f)
+}
+
+/// Reads arbitrary binary data from a byte slice using the given function
+/// `f`, reading big-endian by default.
+pub fn read_slice_be<F, T>(from: &[u8], f: F) -> Result<T>
+where
+    F: FnOnce(&mut Reader<&mut std::io::Cursor<&[u8]>, BigEndian>) -> Result<T>,
+{
+    read_slice::<_, BigEndian, _>(from, f)
+}
+
+/// Reads arbitrary binary data from the given reader `r` using the given
+/// function `f`, with the default endianness selected at runtime.
+pub fn read_dyn<R, F, T>(r: &mut R, endianness: Endianness, f: F) -> Result<T>
+where
+    R: Read + Seek,
+    F: FnOnce(&mut Reader<&mut R, DynEndian>) -> Result<T>,
+{
+    let mut rd = Reader::new_with_endianness(r, endianness);
+    f(&mut rd)
+}
+
+/// Reads arbitrary binary data from a byte slice using the given function
+/// `f`, with the default endianness selected at runtime.
+pub fn read_slice_dyn<F, T>(from: &[u8], endianness: Endianness, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Reader<&mut std::io::Cursor<&[u8]>, DynEndian>) -> Result<T>,
+{
+    let mut cursor = std::io::Cursor::new(from);
+    read_dyn(&mut cursor, endianness, f)
+}
+
+/// Generic equivalent of [`read_le`](read_le) and [`read_be`](read_be),
+/// with endianness selected by a type parameter.
+pub fn read<R, F, E, T>(r: &mut R, f: F) -> Result<T>
+where
+    R: Read + Seek,
+    F: FnOnce(&mut Reader<&mut R, E>) -> Result<T>,
+    E: ByteOrder,
+{
+    let mut rd = Reader::new(r);
+    f(&mut rd)
+}
+
+/// Generic equivalent of [`read_slice_le`](read_slice_le) and
+/// [`read_slice_be`](read_slice_be), with endianness selected by a type
+/// parameter.
+pub fn read_slice<F, E, T>(from: &[u8], f: F) -> Result<T>
+where
+    F: FnOnce(&mut Reader<&mut std::io::Cursor<&[u8]>, E>) -> Result<T>,
+    E: ByteOrder,
+{
+    let mut cursor = std::io::Cursor::new(from);
+    read(&mut cursor, f)
 }
 
 /// Wraps a seekable writer with extra functions to conveniently write
@@ -192,57 +755,592 @@ where
 /// writer and treat the result as invalid.
 pub struct Writer<'a, W, E>
 where
-    W: 'a + Write,
-    E: Endian,
+    W: Write,
+    E: ByteOrder,
 {
-    w: W,
-    map: Vec<Vec<u64>>,
-    pad: u8,
+    w: stream::Stream<W>,
+    state: WriterState<'a>,
+    endianness: Endianness,
+    reader: Option<ReaderFn<W>>,
+    hooks: Vec<FinalizeHook<'a, W, E>>,
+
+    /// The earliest position that the [`Seek`](Seek) implementation can
+    /// move to.
+    start: u64,
     _phantom: std::marker::PhantomData<&'a E>,
 }
 
-/// Methods that only write to the current position in the underlying stream.
-impl<'a, W, E> Writer<'a, W, E>
-where
-    W: Write,
-    E: Endian,
-{
-    fn new(w: W) -> Self {
-        Self {
-            w: w,
-            map: Vec::new(),
-            pad: 0,
-            _phantom: std::marker::PhantomData,
-        }
-    }
+/// A function registered by [`Writer::on_finalize`](Writer::on_finalize).
+type FinalizeHook<'a, W, E> = Box<dyn FnOnce(&mut Writer<'a, W, E>) -> Result<()> + 'a>;
 
-    /// Writes a value to the current position in the output.
-    ///
-    /// `write` can accept any value that implements
-    /// [`IntoPack`](pack::IntoPack), and will write the result from packing
-    /// the value to the underlying stream.
-    pub fn write<V: pack::IntoPack>(&mut self, v: V) -> Result<usize> {
-        write_intopack_value::<_, _, E>(&mut self.w, v)
+/// Converts a writer's stream into a reader, for running deferred
+/// derivations during finalization. This is recorded only once
+/// [`Writer::derive_deferred`](Writer::derive_deferred) proves that the
+/// stream is readable.
+type ReaderFn<W> = fn(&mut W) -> &mut dyn derive::ReadSeek;
+
+/// Trait implemented by the types that [`Writer::patch`](Writer::patch)
+/// accepts to select where to write: a `u64` start position, or a
+/// `Range<u64>` whose length must match the patched value.
+pub trait PatchTarget {
+    /// Returns the range that a value of `len` bytes would occupy, or
+    /// `None` if the value doesn't fit the target exactly.
+    fn patch_range(self, len: u64) -> Option<std::ops::Range<u64>>;
+}
+
+impl PatchTarget for u64 {
+    fn patch_range(self, len: u64) -> Option<std::ops::Range<u64>> {
+        Some(self..self.checked_add(len)?)
     }
+}
 
-    /// Inserts the given number of bytes of padding.
-    pub fn skip(&mut self, count: usize) -> Result<usize> {
-        for _ in 0..count {
-            self.w.write(std::slice::from_ref(&self.pad))?;
+impl PatchTarget for std::ops::Range<u64> {
+    fn patch_range(self, len: u64) -> Option<std::ops::Range<u64>> {
+        if self.end.checked_sub(self.start)? == len {
+            Some(self)
+        } else {
+            None
         }
-        Ok(count)
     }
+}
 
-    /// Changes the padding value used for future calls to
-    /// [`align`](Self::align), and possibly for other functionality added
-    /// in future that might also create padding.
-    pub fn set_padding(&mut self, v: u8) {
-        self.pad = v;
-    }
+/// The bookkeeping for a [`Writer`](Writer), which is independent of the
+/// writer's type parameters so that it can be temporarily lent to a
+/// re-typed writer by [`Writer::with_endian`](Writer::with_endian).
+#[derive(Default)]
+struct WriterState<'a> {
+    slots: Vec<Slot>,
+    derivations: Vec<Derivation<'a>>,
+    labels: Vec<LabelState>,
+    pending: Vec<PendingDerive<'a>>,
+    pad: Padding,
+    buffers: Vec<BufferState>,
+    cur_buffer: Option<usize>,
+    symbols: std::collections::HashMap<String, usize>,
+    in_section: bool,
+    counters: Vec<CounterFn<'a>>,
+    queued: Vec<(Location, Vec<u8>)>,
+    context: Vec<String>,
+    strict: bool,
+    default_alignment: usize,
+    buffer_capacity: usize,
+    unresolved: builder::UnresolvedPolicy,
+    journal: Option<Vec<(u64, Vec<u8>)>>,
+    regions: Vec<(Vec<String>, Location, Location, Endianness)>,
+    vaddrs: std::collections::BTreeMap<u64, u64>,
+}
 
-    fn finalize(mut self) -> Result<W> {
-        self.w.flush()?;
-        Ok(self.w)
+/// Where a [`Location`](Location) can currently be written to, from the
+/// perspective of a particular writer.
+enum Target {
+    /// At the given position in this writer's own stream.
+    Here(u64),
+
+    /// At the given position in the data of a completed buffered region
+    /// that hasn't been emitted yet.
+    Buffer(usize, u64),
+
+    /// Somewhere this writer can't reach, such as the parent stream of a
+    /// buffered region that's still being written.
+    Elsewhere(Location),
+}
+
+/// A repeating pattern of padding bytes, along with the stream position
+/// where the first byte of the pattern would appear.
+#[derive(Clone)]
+struct Padding {
+    pattern: Vec<u8>,
+    base: u64,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self {
+            pattern: vec![0],
+            base: 0,
+        }
+    }
+}
+
+/// Tracks the positions and byte orders of all of the placeholders for a
+/// deferred slot, along with its final value once resolved.
+struct Slot {
+    positions: Vec<(Location, Endianness)>,
+    initial: Box<dyn SlotValue>,
+    value: Option<Box<dyn SlotValue>>,
+    allow_unresolved: bool,
+}
+
+/// A type-erased final value for a deferred slot.
+trait SlotValue {
+    /// Packs the value using the given byte order.
+    fn pack(&self, endianness: Endianness) -> Vec<u8>;
+
+    fn as_any(&self) -> &dyn std::any::Any;
+}
+
+/// The content of a slot created by [`Writer::reserve`](Writer::reserve),
+/// which is raw bytes rather than a packed value.
+struct RawBytes(Vec<u8>);
+
+impl SlotValue for RawBytes {
+    fn pack(&self, _: Endianness) -> Vec<u8> {
+        self.0.clone()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+impl<T> SlotValue for T
+where
+    T: pack::IntoPack + Clone + 'static,
+    <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+{
+    fn pack(&self, endianness: Endianness) -> Vec<u8> {
+        let pv = self.clone().into_pack();
+        let mut buf = vec![0_u8; pack::Pack::pack_len(&pv)];
+        endianness.pack_into_slice(&pv, &mut buf[..]);
+        buf
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// A deferred slot whose value is derived from other deferred slots, and
+/// so can be resolved automatically once all of its inputs are resolved.
+struct Derivation<'a> {
+    inputs: Vec<usize>,
+    output: usize,
+    compute: Option<DerivationFn<'a>>,
+}
+
+type DerivationFn<'a> = Box<dyn FnOnce(&[Slot]) -> Result<Box<dyn SlotValue>> + 'a>;
+
+/// Produces the final value of a [`Counter`](Counter), along with the index
+/// of its slot, during finalization.
+type CounterFn<'a> = Box<dyn FnOnce() -> Result<(usize, Box<dyn SlotValue>)> + 'a>;
+
+/// A value to be derived from a region of the stream during finalization,
+/// as registered by [`Writer::derive_deferred`](Writer::derive_deferred).
+struct PendingDerive<'a> {
+    rng: std::ops::Range<u64>,
+    output: usize,
+    compute: PendingDeriveFn<'a>,
+}
+
+type PendingDeriveFn<'a> = Box<
+    dyn FnOnce(&mut derive::DeriveRead<dyn derive::ReadSeek + '_>) -> Result<Box<dyn SlotValue>>
+        + 'a,
+>;
+
+/// Methods that only write to the current position in the underlying stream.
+impl<'a, W, E> Writer<'a, W, E>
+where
+    W: Write,
+    E: ByteOrder,
+{
+    fn new(w: W) -> Self {
+        Self::new_with_endianness(w, Endianness::select::<E>(Endianness::Little))
+    }
+
+    fn new_with_endianness(w: W, endianness: Endianness) -> Self {
+        Self {
+            w: stream::Stream::new(w),
+            state: WriterState::default(),
+            endianness,
+            reader: None,
+            hooks: Vec::new(),
+            start: 0,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns the byte order the writer uses for multi-byte values.
+    ///
+    /// This is always the byte order selected by the writer's type
+    /// parameter, unless that is [`DynEndian`](endian::DynEndian), in which
+    /// case it's the byte order selected at runtime when the writer was
+    /// created.
+    pub fn endianness(&self) -> Endianness {
+        Endianness::select::<E>(self.endianness)
+    }
+
+    /// Writes a value to the current position in the output.
+    ///
+    /// `write` can accept any value that implements
+    /// [`IntoPack`](pack::IntoPack), and will write the result from packing
+    /// the value to the underlying stream.
+    pub fn write<V: pack::IntoPack>(&mut self, v: V) -> Result<usize> {
+        let endianness = self.endianness();
+        self.w.set_label(|| std::any::type_name::<V>().into());
+        let ret = write_intopack_value(&mut self.w, v, endianness);
+        self.w.clear_label();
+        ret
+    }
+
+    /// Writes all of the given primitive values to the current position in
+    /// the output, using the writer's endianness, returning the number of
+    /// bytes written.
+    ///
+    /// This produces the same result as writing the slice using
+    /// [`write`](Self::write), but is much faster for large slices: if the
+    /// writer's byte order matches the target platform's then the slice's
+    /// memory is written directly, and otherwise the values are converted
+    /// in large chunks so that the underlying stream sees only a few large
+    /// writes.
+    pub fn write_slice<T: pack::Primitive>(&mut self, vs: &[T]) -> Result<usize> {
+        let endianness = self.endianness();
+        let native = Endianness::select::<NativeEndian>(endianness);
+        let elem_len = <T as pack::FixedLenPack>::PACK_LEN;
+        if elem_len == 1 || endianness == native {
+            let bytes = pack::primitive_bytes(vs);
+            self.w.write_all(bytes)?;
+            return Ok(bytes.len());
+        }
+        let mut buf = [0_u8; 4096];
+        for chunk in vs.chunks(buf.len() / elem_len) {
+            let len = chunk.len() * elem_len;
+            for (v, into) in chunk.iter().zip(buf[..len].chunks_exact_mut(elem_len)) {
+                endianness.pack_into_slice(v, into);
+            }
+            self.w.write_all(&buf[..len])?;
+        }
+        Ok(vs.len() * elem_len)
+    }
+
+    /// Converts each of the given values to the primitive type `T` and
+    /// writes the results, returning the number of bytes written.
+    ///
+    /// This is useful for writing a slice of `f32` values in a reduced
+    /// precision format such as [`F16`](float::F16), without first
+    /// converting the whole slice in memory.
+    pub fn write_slice_as<T, S>(&mut self, vs: &[S]) -> Result<usize>
+    where
+        T: pack::Primitive + From<S>,
+        S: Copy,
+    {
+        let endianness = self.endianness();
+        let elem_len = <T as pack::FixedLenPack>::PACK_LEN;
+        let mut buf = [0_u8; 4096];
+        for chunk in vs.chunks(buf.len() / elem_len) {
+            let len = chunk.len() * elem_len;
+            for (v, into) in chunk.iter().zip(buf[..len].chunks_exact_mut(elem_len)) {
+                endianness.pack_into_slice(&T::from(*v), into);
+            }
+            self.w.write_all(&buf[..len])?;
+        }
+        Ok(vs.len() * elem_len)
+    }
+
+    /// Writes each of the values produced by the given iterator in turn,
+    /// returning the total number of bytes written.
+    pub fn write_iter<I>(&mut self, iter: I) -> Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: pack::IntoPack,
+    {
+        let mut total = 0;
+        for v in iter {
+            total += self.write(v)?;
+        }
+        Ok(total)
+    }
+
+    /// Like [`write_iter`](Self::write_iter), but writes the given
+    /// separator between each pair of consecutive values.
+    pub fn write_iter_separated<I, S>(&mut self, iter: I, sep: S) -> Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: pack::IntoPack,
+        S: pack::IntoPack + Clone,
+    {
+        let mut total = 0;
+        for (i, v) in iter.into_iter().enumerate() {
+            if i != 0 {
+                total += self.write(sep.clone())?;
+            }
+            total += self.write(v)?;
+        }
+        Ok(total)
+    }
+
+    /// Writes the least significant `width` bytes of an unsigned integer to
+    /// the current position in the output, using the writer's endianness.
+    ///
+    /// This is for formats where the width of a field is chosen at runtime.
+    /// Returns an error if the value cannot be represented in `width` bytes,
+    /// or if `width` is greater than eight.
+    pub fn write_uint(&mut self, v: u64, width: usize) -> Result<usize> {
+        if width > 8 {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        let endianness = self.endianness();
+        write_uint_value(&mut self.w, v, width, endianness)
+    }
+
+    /// Writes the least significant `width` bytes of a signed integer to
+    /// the current position in the output in two's complement form, using
+    /// the writer's endianness.
+    ///
+    /// This is for formats where the width of a field is chosen at runtime.
+    /// Returns an error if the value cannot be represented in `width` bytes,
+    /// or if `width` is greater than eight.
+    pub fn write_int(&mut self, v: i64, width: usize) -> Result<usize> {
+        let endianness = self.endianness();
+        write_int_value(&mut self.w, v, width, endianness)
+    }
+
+    /// Copies bytes from the given reader into the output, returning the
+    /// number of bytes copied.
+    ///
+    /// If `len` is `None` then this copies until the reader reaches the
+    /// end of its data. Otherwise, it copies exactly `len` bytes and
+    /// returns an [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof)
+    /// error if the reader ends sooner.
+    ///
+    /// The data is copied through a fixed-size buffer, so this is suitable
+    /// for embedding large files without first reading them into memory.
+    /// Use it inside [`subregion`](Self::subregion) or
+    /// [`write_sized_subregion`](Self::write_sized_subregion) to find the
+    /// position of the copied data.
+    pub fn copy_from<R: Read>(&mut self, r: R, len: Option<u64>) -> Result<u64> {
+        match len {
+            Some(len) => {
+                let copied = std::io::copy(&mut r.take(len), &mut self.w)?;
+                if copied < len {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+                }
+                Ok(copied)
+            }
+            None => {
+                let mut r = r;
+                std::io::copy(&mut r, &mut self.w)
+            }
+        }
+    }
+
+    /// Writes the given byte `count` times, returning the number of bytes
+    /// written.
+    pub fn fill(&mut self, v: u8, count: usize) -> Result<usize> {
+        let chunk = [v; 512];
+        let mut remain = count;
+        while remain > 0 {
+            let n = std::cmp::min(remain, chunk.len());
+            self.w.write_all(&chunk[..n])?;
+            remain -= n;
+        }
+        Ok(count)
+    }
+
+    /// Writes the given string followed by a null terminator and then enough
+    /// zero bytes to make the total length a multiple of `n`, returning that
+    /// padded length.
+    ///
+    /// This is the form of strings in ELF notes and Mach-O load commands,
+    /// among others, which typically also record the padded length. The
+    /// padding is relative to the start of the string rather than to the
+    /// start of the stream, and is always zeros regardless of
+    /// [`set_padding`](Self::set_padding).
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the string
+    /// contains a null byte or `n` is zero.
+    pub fn write_cstr_padded(&mut self, s: impl AsRef<[u8]>, n: usize) -> Result<usize> {
+        let s = s.as_ref();
+        if s.contains(&0) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "string contains a null byte",
+            ));
+        }
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "alignment must not be zero",
+            ));
+        }
+        let len = (s.len() + 1).div_ceil(n) * n;
+        self.w.write_all(s)?;
+        self.fill(0, len - s.len())?;
+        Ok(len)
+    }
+
+    /// Writes the given value `count` times, returning the number of bytes
+    /// written.
+    ///
+    /// The value is packed only once, and the copies are written in large
+    /// chunks, so this is suitable for filling large tables with a sentinel
+    /// value.
+    pub fn write_repeated<V: pack::IntoPack>(&mut self, v: V, count: usize) -> Result<usize> {
+        let v = v.into_pack();
+        let len = pack::Pack::pack_len(&v);
+        if len == 0 || count == 0 {
+            return Ok(0);
+        }
+        let mut one = vec![0_u8; len];
+        self.endianness().pack_into_slice(&v, &mut one[..]);
+        let per_chunk = std::cmp::max(1, 4096 / len);
+        let chunk = one.repeat(std::cmp::min(per_chunk, count));
+        let mut remain = count;
+        while remain > 0 {
+            let n = std::cmp::min(remain, per_chunk);
+            self.w.write_all(&chunk[..n * len])?;
+            remain -= n;
+        }
+        Ok(count * len)
+    }
+
+    /// Changes the padding value used for future calls to
+    /// [`align`](Self::align), and possibly for other functionality added
+    /// in future that might also create padding.
+    pub fn set_padding(&mut self, v: u8) {
+        self.set_padding_pattern(&[v], 0);
+    }
+
+    /// Changes the padding to a repeating pattern of bytes, such as the
+    /// encoding of a "no-op" instruction, for future calls to
+    /// [`align`](Self::align) and other functionality that creates padding.
+    ///
+    /// The pattern is positioned so that its first byte would appear at
+    /// stream position `base` and then at every multiple of the pattern
+    /// length before and after it, so padding always lines up with the
+    /// pattern regardless of where it begins.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is empty.
+    pub fn set_padding_pattern(&mut self, pattern: &[u8], base: u64) {
+        assert!(!pattern.is_empty(), "padding pattern must not be empty");
+        self.state.pad = Padding {
+            pattern: pattern.to_vec(),
+            base,
+        };
+    }
+
+    /// Calls the given function with a writer that shares this writer's
+    /// stream, deferred slots, and labels but uses byte order `E2`, for
+    /// formats that embed data of a different endianness than their
+    /// surroundings.
+    ///
+    /// Each placeholder remembers the byte order it was written with, so
+    /// a deferred slot created outside the closure but resolved inside it
+    /// (or vice-versa) still gets each of its values in the right order.
+    /// If `E2` is [`DynEndian`](DynEndian) then the nested writer inherits
+    /// this writer's byte order.
+    pub fn with_endian<E2, F, R>(&mut self, f: F) -> Result<R>
+    where
+        E2: ByteOrder + 'a,
+        F: FnOnce(&mut Writer<'a, &mut W, E2>) -> Result<R>,
+    {
+        let endianness = Endianness::select::<E2>(self.endianness());
+        let trace = self.w.trace().cloned();
+        let max_len = self.w.max_len();
+        let progress = self.w.progress().cloned();
+        let cancel = self.w.cancel().cloned();
+        // The scoped stream can't seek to find its own position, so it
+        // starts from ours in order to enforce max_len.
+        let pos = self.w.known_pos()?;
+        let mut scoped = Writer {
+            w: stream::Stream::new(self.w.get_mut()?),
+            state: std::mem::take(&mut self.state),
+            endianness,
+            reader: None,
+            hooks: Vec::new(),
+            start: self.start,
+            _phantom: std::marker::PhantomData,
+        };
+        scoped.w.assume_pos(pos);
+        scoped.w.set_trace(trace);
+        scoped.w.set_max_len(max_len);
+        scoped.w.set_progress(progress);
+        scoped.w.set_cancel(cancel);
+        let ret = f(&mut scoped).and_then(|ret| {
+            scoped.run_finalize_hooks()?;
+            Ok(ret)
+        });
+        self.state = scoped.state;
+        ret
+    }
+
+    /// Enables or disables strict mode, in which alignments given to
+    /// [`align`](Self::align) and similar must be powers of two and
+    /// alignments declared using [`write_aligned`](Self::write_aligned) must
+    /// already hold rather than being fixed with padding.
+    ///
+    /// Strict mode is off by default. Call this at the start of the writing
+    /// function to catch layout mistakes that would otherwise silently
+    /// produce a misaligned file.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.state.strict = strict;
+    }
+
+    /// Registers a function to run when the writer is finalized, after the
+    /// function that created the writer has returned.
+    ///
+    /// This is for values that are only known once all other writing is
+    /// complete, such as the total size of the output. Finalization hooks
+    /// run in the order they were registered, and run before checking that
+    /// all deferred slots are resolved, so they can resolve any remaining
+    /// slots. A hook can register further hooks, which then run after all
+    /// of the previously-registered hooks.
+    ///
+    /// Hooks registered inside a [`buffered`](Self::buffered) region or a
+    /// [`with_endian`](Self::with_endian) scope run when that region or
+    /// scope is complete, rather than when the outermost writer is
+    /// finalized.
+    pub fn on_finalize<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self) -> Result<()> + 'a,
+    {
+        self.hooks.push(Box::new(f));
+    }
+
+    fn run_finalize_hooks(&mut self) -> Result<()> {
+        while !self.hooks.is_empty() {
+            let hooks = std::mem::take(&mut self.hooks);
+            for hook in hooks {
+                hook(self)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creating writers without a closure.
+impl<'a, W> Writer<'a, W, LittleEndian>
+where
+    W: Seek + Write,
+{
+    /// Creates a writer over the given stream, where writes will be
+    /// little-endian by default, as an alternative to
+    /// [`write_le`](write_le) for callers that can't do all of their
+    /// writing inside a single closure.
+    ///
+    /// Call [`finish`](Writer::finish) once writing is complete, to resolve
+    /// placeholders and retrieve the stream. Deferred values, labels, and
+    /// other handles created by the writer must be used only with that
+    /// same writer, which the closure-based functions would otherwise
+    /// ensure.
+    pub fn new_le(w: W) -> Result<Self> {
+        Builder::new().build(w)
+    }
+}
+
+/// Creating writers without a closure.
+impl<'a, W> Writer<'a, W, BigEndian>
+where
+    W: Seek + Write,
+{
+    /// Creates a writer over the given stream, where writes will be
+    /// big-endian by default.
+    ///
+    /// This is the big-endian equivalent of [`new_le`](Writer::new_le).
+    pub fn new_be(w: W) -> Result<Self> {
+        Builder::new().build(w)
     }
 }
 
@@ -250,8 +1348,31 @@ where
 impl<'a, W, E> Writer<'a, W, E>
 where
     W: Seek + Write,
-    E: Endian,
+    E: ByteOrder,
 {
+    /// Completes writing, as the closure-based functions such as
+    /// [`write_le`](write_le) do after their closure returns, and then
+    /// returns the underlying stream.
+    ///
+    /// This runs any [`on_finalize`](Self::on_finalize) hooks, updates all
+    /// of the placeholders for deferred values and labels, and returns an
+    /// error if any deferred value is unresolved.
+    pub fn finish(self) -> Result<W> {
+        self.finalize()
+    }
+
+    /// Returns the underlying stream, so that it can be used directly, such
+    /// as to query file metadata.
+    ///
+    /// Any buffered writes are flushed first, so the stream is positioned at
+    /// the writer's current position and has all of the data written so
+    /// far, although placeholders may not yet be updated. Writing to the
+    /// stream directly bypasses the writer's bookkeeping, so anything
+    /// written over a placeholder is replaced when it's updated.
+    pub fn get_mut(&mut self) -> Result<&mut W> {
+        self.w.get_mut()
+    }
+
     /// Returns the current write position in the underlying writer.
     ///
     /// Use this with [`resolve`](Self::resolve) to resolve a deferred slot that
@@ -261,117 +1382,1962 @@ where
         self.w.stream_position()
     }
 
-    /// Moves the current stream position forward to a position aligned to the
-    /// given number of bytes, writing padding bytes as necessary. Returns the
-    /// number of padding bytes written.
+    /// Like [`write`](Self::write), but returns the range of positions
+    /// that the value occupies rather than its length.
+    ///
+    /// The range is useful for later [`derive`](Self::derive) calls,
+    /// patches, or directory entries describing where the value landed.
+    pub fn write_range<V: pack::IntoPack>(&mut self, v: V) -> Result<std::ops::Range<u64>> {
+        let start = self.position()?;
+        let len = self.write(v)?;
+        Ok(start..start + len as u64)
+    }
+
+    /// Like [`write_placeholder`](Self::write_placeholder), but returns the
+    /// range of positions that the placeholder occupies rather than its
+    /// length.
+    pub fn write_placeholder_range<T>(
+        &mut self,
+        deferred: Deferred<'a, T>,
+    ) -> Result<std::ops::Range<u64>>
+    where
+        T: pack::IntoPack,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let start = self.position()?;
+        let len = self.write_placeholder(deferred)?;
+        Ok(start..start + len as u64)
+    }
+
+    /// Like [`align`](Self::align), but returns the range of positions of
+    /// the padding written rather than its length, so that the end of the
+    /// range is the aligned position.
+    pub fn align_range(&mut self, n: usize) -> Result<std::ops::Range<u64>> {
+        let start = self.position()?;
+        let len = self.align(n)?;
+        Ok(start..start + len as u64)
+    }
+
+    /// Moves the current stream position forward by the given number of
+    /// bytes without writing padding bytes, returning the new position.
+    ///
+    /// Unlike [`skip`](Self::skip), this seeks the underlying stream, so
+    /// large gaps in a file can remain sparse on filesystems that support
+    /// that. Any part of the gap beyond the previous end of the stream
+    /// will read as zeros regardless of the padding byte selected with
+    /// [`set_padding`](Self::set_padding), and any part within the
+    /// existing data is left unchanged.
+    ///
+    /// If the gap extends beyond the previous end of the stream, this
+    /// writes a single zero byte at the end of the gap to ensure the stream
+    /// is extended even if nothing else is written after it. In-memory
+    /// streams like [`Cursor`](std::io::Cursor) over a `Vec<u8>` cannot be
+    /// sparse, so for those the gap is filled with zeros.
+    pub fn seek_forward(&mut self, n: u64) -> Result<u64> {
+        let pos = self.w.stream_position()?;
+        let target = pos
+            .checked_add(n)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        let end = self.w.seek(std::io::SeekFrom::End(0))?;
+        if target > end {
+            self.w.seek(std::io::SeekFrom::Start(target - 1))?;
+            self.w.write_all(&[0])?;
+            Ok(target)
+        } else {
+            self.w.seek(std::io::SeekFrom::Start(target))
+        }
+    }
+
+    /// Calls the given function with the writer temporarily moved to the
+    /// given absolute position, for revisiting earlier parts of the output
+    /// such as header fields.
+    ///
+    /// The previous position is restored afterwards even if the function
+    /// returns an error, in which case that error is returned. Writing
+    /// in the closure overwrites any existing data at the given position.
+    pub fn at<F, R>(&mut self, pos: u64, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        self.apply_journal()?;
+        let reset_pos = self.position()?;
+        self.w.seek(std::io::SeekFrom::Start(pos))?;
+        let ret = f(self);
+        let restored = self.w.seek(std::io::SeekFrom::Start(reset_pos));
+        let ret = ret?;
+        restored?;
+        Ok(ret)
+    }
+
+    /// Overwrites part of the output that was already written with the
+    /// given value, without moving the current position.
+    ///
+    /// `at` is either the start position as a `u64` or the exact range to
+    /// overwrite, in which case the range must be the same length as the
+    /// packed value. Returns an error if the patch would extend beyond the
+    /// end of the data written so far, or doesn't match the given range.
+    pub fn patch<P, V>(&mut self, at: P, v: V) -> Result<std::ops::Range<u64>>
+    where
+        P: PatchTarget,
+        V: pack::IntoPack,
+    {
+        let v = v.into_pack();
+        let len = pack::Pack::pack_len(&v) as u64;
+        let range = at
+            .patch_range(len)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        let reset_pos = self.position()?;
+        let end = self.w.seek(std::io::SeekFrom::End(0))?;
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        if range.end > end {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "patch at {:#x}..{:#x} extends beyond end of output at {:#x}",
+                    range.start, range.end, end
+                ),
+            ));
+        }
+        let endianness = self.endianness();
+        self.at(range.start, |w| write_pack_value(&mut w.w, &v, endianness))?;
+        Ok(range)
+    }
+
+    /// Calls the given function with the given name added to the context
+    /// reported for any error it returns.
+    ///
+    /// Errors returned from within any number of nested `context` calls
+    /// wrap an [`Error`](Error) describing the names of all of the active
+    /// contexts, outermost first, and the position where the error was
+    /// returned.
+    pub fn context<F, R>(&mut self, name: impl Into<String>, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        self.push_context(name.into());
+        let ret = f(self).map_err(|err| self.error_context(err, None));
+        self.pop_context();
+        ret
+    }
+
+    fn push_context(&mut self, name: String) {
+        if let Some(progress) = self.w.progress() {
+            progress.set_annotation(Some(&name));
+        }
+        self.state.context.push(name);
+    }
+
+    fn pop_context(&mut self) {
+        self.state.context.pop();
+        if let Some(progress) = self.w.progress() {
+            progress.set_annotation(self.state.context.last().map(String::as_str));
+        }
+    }
+
+    /// Registers a function to report progress, which is called with the
+    /// total number of bytes written so far each time at least
+    /// `granularity` more bytes have been written.
+    ///
+    /// The function also receives the name of the innermost
+    /// [`context`](Self::context) or [`annotate`](Self::annotate) region
+    /// being written, if any. All writes to the underlying stream count,
+    /// including data copied using [`copy_from`](Self::copy_from) and
+    /// placeholders updated during finalization, so the total may exceed
+    /// the final length of the output. Data written into a
+    /// [`buffered`](Self::buffered) region counts when the region is
+    /// emitted.
+    pub fn set_progress<F>(&mut self, granularity: u64, f: F)
+    where
+        F: FnMut(u64, Option<&str>) + 'static,
+    {
+        self.w
+            .set_progress(Some(progress::Progress::new(granularity, f)));
+    }
+
+    /// Selects a token for cancelling writing, such as from another thread
+    /// when the user aborts a long-running export.
+    ///
+    /// Once the token is set, every write fails with an error recognized by
+    /// [`Error::is_cancelled`](Error::is_cancelled), including writes made
+    /// by [`copy_from`](Self::copy_from) and during finalization, so the
+    /// caller can clean up the partial output. Finalization also checks the
+    /// token before each derivation registered using
+    /// [`derive_deferred`](Self::derive_deferred).
+    pub fn set_cancel(&mut self, token: std::sync::Arc<std::sync::atomic::AtomicBool>) {
+        self.w.set_cancel(Some(token));
+    }
+
+    /// Like [`context`](Self::context), but also records the range written
+    /// by the given function as a named region in the writer's
+    /// [`LayoutMap`](layout::LayoutMap).
+    pub fn annotate<F, R>(&mut self, name: impl Into<String>, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        self.push_context(name.into());
+        let ret = self
+            .annotated(f)
+            .map_err(|err| self.error_context(err, None));
+        self.pop_context();
+        ret
+    }
+
+    fn annotated<F, R>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        let start = self.location()?;
+        let idx = self.state.regions.len();
+        let endianness = self.endianness();
+        self.state
+            .regions
+            .push((self.state.context.clone(), start, start, endianness));
+        let ret = f(self)?;
+        self.state.regions[idx].2 = self.location()?;
+        Ok(ret)
+    }
+
+    /// Returns the layout of the output written so far, including the
+    /// regions named using [`annotate`](Self::annotate) and the placeholders
+    /// for each deferred slot.
+    ///
+    /// Regions and placeholders inside [`buffered`](Self::buffered) regions
+    /// are included only once the region has been emitted.
+    pub fn layout_map(&self) -> layout::LayoutMap {
+        let endianness = self.endianness();
+        let mut map = layout::LayoutMap::default();
+        for (path, start, end, endianness) in &self.state.regions {
+            if let (Target::Here(start), Target::Here(end)) =
+                (self.locate(*start), self.locate(*end))
+            {
+                map.regions.push(layout::Region {
+                    path: path.clone(),
+                    range: start..end,
+                    endianness: *endianness,
+                });
+            }
+        }
+        for (index, slot) in self.state.slots.iter().enumerate() {
+            let len = slot.initial.pack(endianness).len() as u64;
+            let (placeholders, endianness) = slot
+                .positions
+                .iter()
+                .filter_map(|(loc, endianness)| match self.locate(*loc) {
+                    Target::Here(pos) => Some((pos..pos + len, *endianness)),
+                    _ => None,
+                })
+                .unzip();
+            map.slots.push(layout::SlotLayout {
+                index,
+                placeholders,
+                endianness,
+                resolved: slot.value.is_some(),
+            });
+        }
+        map
+    }
+
+    /// Wraps the given error in an [`Error`](Error) describing the current
+    /// context, unless it already has context.
+    fn error_context(&mut self, err: std::io::Error, slot: Option<usize>) -> std::io::Error {
+        if Error::from_io(&err).is_some() {
+            return err;
+        }
+        let position = self.w.stream_position().ok();
+        Error::new(err, position, self.state.context.clone(), slot).into()
+    }
+
+    /// Inserts the given number of bytes of padding.
+    pub fn skip(&mut self, count: usize) -> Result<usize> {
+        let pattern = &self.state.pad.pattern;
+        if let [v] = pattern[..] {
+            return self.fill(v, count);
+        }
+
+        // We build a chunk that is a whole number of repetitions of the
+        // pattern, starting at the right phase for the current position, so
+        // that the phase is the same at the start of every chunk.
+        let pos = self.w.stream_position()?;
+        let len = pattern.len() as u64;
+        let phase = if pos >= self.state.pad.base {
+            (pos - self.state.pad.base) % len
+        } else {
+            (len - (self.state.pad.base - pos) % len) % len
+        } as usize;
+        let reps = 512_usize.div_ceil(pattern.len());
+        let chunk: Vec<u8> = pattern
+            .iter()
+            .cycle()
+            .skip(phase)
+            .take(reps * pattern.len())
+            .copied()
+            .collect();
+        let mut remain = count;
+        while remain > 0 {
+            let n = std::cmp::min(remain, chunk.len());
+            self.w.write_all(&chunk[..n])?;
+            remain -= n;
+        }
+        Ok(count)
+    }
+
+    /// Writes padding bytes until the stream position reaches the given
+    /// absolute position, returning the number of padding bytes written.
+    ///
+    /// This is useful for formats that require a fixed total size, such as
+    /// firmware images. Returns an error if the stream position is already
+    /// beyond `size`.
+    pub fn pad_to(&mut self, size: u64) -> Result<usize> {
+        let pos = self.position()?;
+        if pos > size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("position {} is already beyond padding target {}", pos, size),
+            ));
+        }
+        let count = size_value::<usize>(size - pos)?;
+        self.skip(count)
+    }
+
+    /// Moves the current stream position forward to a position aligned to the
+    /// given number of bytes, writing padding bytes as necessary. Returns the
+    /// number of padding bytes written.
+    ///
+    /// A new `Writer` defaults to using zeros for padding. Use
+    /// [`set_padding`](Self::set_padding) to override the padding byte for
+    /// future writes, if needed.
+    pub fn align(&mut self, n: usize) -> Result<usize> {
+        self.align_from(0, n)
+    }
+
+    /// Aligns to the default alignment selected when the writer was
+    /// created, using [`Builder::default_alignment`](Builder::default_alignment),
+    /// which is one byte (no alignment) unless otherwise selected.
+    pub fn align_default(&mut self) -> Result<usize> {
+        self.align(self.state.default_alignment.max(1))
+    }
+
+    /// Like [`align`](Self::align), but aligns relative to the given base
+    /// position rather than to the start of the stream.
+    ///
+    /// This is for nested structures whose alignment requirements are
+    /// relative to their own start, which might not itself be aligned
+    /// within the file. Typically `base` would be the start position of
+    /// an enclosing [`subregion`](Self::subregion). Returns an error if the
+    /// current position is before `base`.
+    pub fn align_from(&mut self, base: u64, n: usize) -> Result<usize> {
+        self.check_alignment_arg(n)?;
+        let pos = self.position()?;
+        if pos < base {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("position {} is before alignment base {}", pos, base),
+            ));
+        }
+        let ofs = (pos - base) % (n as u64);
+        if ofs == 0 {
+            return Ok(0);
+        }
+        let inc = ((n as u64) - ofs) as usize;
+        self.skip(inc)
+    }
+
+    /// Returns an error if the current position is not a multiple of `n`.
+    ///
+    /// This is for checking that a structure which must be aligned is
+    /// where the format requires, without silently inserting padding as
+    /// [`align`](Self::align) would.
+    pub fn assert_aligned(&mut self, n: usize) -> Result<()> {
+        self.check_alignment_arg(n)?;
+        let pos = self.position()?;
+        if n == 0 || pos % (n as u64) != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("position {:#x} is not aligned to {} bytes", pos, n),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes a field that must be aligned to `n` bytes.
+    ///
+    /// Normally this writes padding as necessary before the value, like
+    /// [`align`](Self::align). In strict mode, selected using
+    /// [`set_strict`](Self::set_strict), the alignment is instead checked
+    /// as with [`assert_aligned`](Self::assert_aligned), so that a field
+    /// that unexpectedly needs padding is reported as an error.
+    pub fn write_aligned<V: pack::IntoPack>(&mut self, n: usize, v: V) -> Result<usize> {
+        if self.state.strict {
+            self.assert_aligned(n)?;
+        } else {
+            self.align(n)?;
+        }
+        self.write(v)
+    }
+
+    /// Like [`write_iter`](Self::write_iter), but aligns each value to `n`
+    /// bytes as with [`write_aligned`](Self::write_aligned), for arrays of
+    /// records that must each be aligned.
+    ///
+    /// The result includes any padding written before each value.
+    pub fn write_iter_aligned<I>(&mut self, n: usize, iter: I) -> Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: pack::IntoPack,
+    {
+        let start = self.position()?;
+        for v in iter {
+            self.write_aligned(n, v)?;
+        }
+        Ok((self.position()? - start) as usize)
+    }
+
+    /// Writes a C struct using the given function, inserting the padding
+    /// that a C compiler would under the given ABI so that the output
+    /// matches the memory layout of a `#[repr(C)]` struct.
+    ///
+    /// The function writes each field using
+    /// [`CStruct::field`](abi::CStruct::field), which aligns it relative to
+    /// the start of the struct, and then `c_struct` writes any trailing
+    /// padding needed to make the size of the struct a multiple of its
+    /// alignment.
+    ///
+    /// ```
+    /// # use std::io::Result;
+    /// # fn main() -> Result<()> {
+    /// use binbin::abi::Abi;
+    ///
+    /// let mut buf = Vec::<u8>::new();
+    /// binbin::write_vec_le(&mut buf, |w| {
+    ///     w.c_struct(Abi::SystemV64, |s| {
+    ///         s.field(1_u8)?;
+    ///         s.field(2_u32)?;
+    ///         s.field(3_u16)?;
+    ///         Ok(())
+    ///     })
+    /// })?;
+    /// assert_eq!(buf, [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn c_struct<F, R>(&mut self, abi: abi::Abi, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut abi::CStruct<'_, 'a, W, E>) -> Result<R>,
+    {
+        let mut s = abi::CStruct::new(self, abi)?;
+        let ret = f(&mut s)?;
+        s.finish()?;
+        Ok(ret)
+    }
+
+    fn check_alignment_arg(&self, n: usize) -> Result<()> {
+        if self.state.strict && !n.is_power_of_two() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("alignment {} is not a power of two", n),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates a region of the output whose final bounds must be known for
+    /// use elsewhere in the output.
+    ///
+    /// If the given function completes successfully, `subregion` returns
+    /// a range describing the start and end positions of the subregion
+    /// in the underlying stream.
+    pub fn subregion<F>(&mut self, f: F) -> Result<std::ops::Range<u64>>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        self.subregion_with(f).map(|(rng, ())| rng)
+    }
+
+    /// Like [`subregion`](Self::subregion), but the given function can
+    /// return a value, which `subregion_with` returns along with the range
+    /// of the subregion.
+    pub fn subregion_with<F, R>(&mut self, f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        let start_pos = self.w.stream_position()?;
+        let ret = f(self)?;
+        let end_pos = self.w.stream_position()?;
+        Ok((start_pos..end_pos, ret))
+    }
+
+    /// Writes a body using the function `body` and then a trailer describing
+    /// it using the function `trailer`, which receives the range of
+    /// positions that the body occupies.
+    ///
+    /// This is for records that end with a checksum or length of everything
+    /// before it. The trailer function can use [`derive`](Self::derive) or
+    /// [`crc32`](Self::crc32) over the range if the underlying stream is
+    /// readable, but the body must then have no unresolved placeholders;
+    /// use [`with_derived_trailer`](Self::with_derived_trailer) for a
+    /// trailer that is instead calculated during finalization.
+    ///
+    /// Returns the results of both functions.
+    pub fn with_trailer<F, G, R, T>(&mut self, body: F, trailer: G) -> Result<(R, T)>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+        G: FnOnce(&mut Self, std::ops::Range<u64>) -> Result<T>,
+    {
+        let (rng, ret) = self.subregion_with(body)?;
+        let t = trailer(self, rng)?;
+        Ok((ret, t))
+    }
+
+    /// Like [`subregion_with`](Self::subregion_with), but returns an error
+    /// as soon as a write would extend the subregion beyond `max_len`
+    /// bytes.
+    ///
+    /// This is for sections with a hard size budget, such as a boot sector.
+    /// The given function receives a writer over a
+    /// [`Limited`](limit::Limited) stream that shares this writer's
+    /// deferred slots and labels. Placeholders before the start of the
+    /// subregion can still be updated from inside it.
+    pub fn subregion_limited<F, R>(
+        &mut self,
+        max_len: u64,
+        f: F,
+    ) -> Result<(std::ops::Range<u64>, R)>
+    where
+        F: FnOnce(&mut Writer<'a, limit::Limited<&mut W>, E>) -> Result<R>,
+    {
+        let trace = self.w.trace().cloned();
+        let output_max_len = self.w.max_len();
+        let progress = self.w.progress().cloned();
+        let cancel = self.w.cancel().cloned();
+        let start = self.position()?;
+        let mut limited = Writer {
+            w: stream::Stream::new(limit::Limited::new(self.w.get_mut()?, max_len)?),
+            state: std::mem::take(&mut self.state),
+            endianness: self.endianness,
+            reader: None,
+            hooks: Vec::new(),
+            start,
+            _phantom: std::marker::PhantomData,
+        };
+        // As for with_endian, the limited stream starts from our position
+        // in order to enforce the output's max_len.
+        limited.w.assume_pos(Some(start));
+        limited.w.set_trace(trace);
+        limited.w.set_max_len(output_max_len);
+        limited.w.set_progress(progress);
+        limited.w.set_cancel(cancel);
+        let ret = limited.subregion_with(|w| {
+            let ret = f(w)?;
+            w.run_finalize_hooks()?;
+            Ok(ret)
+        });
+        self.state = limited.state;
+        ret
+    }
+
+    /// Writes a size prefix of type `T` followed by a subregion produced by
+    /// the given function, and then updates the prefix to match the final
+    /// size of the subregion.
+    ///
+    /// This is a common pattern in chunked formats, where each chunk begins
+    /// with the length of its content. The size written is that of the
+    /// content only, not including the prefix itself.
+    ///
+    /// If the given function completes successfully,
+    /// `write_sized_subregion` returns a range describing the start and end
+    /// positions of the content in the underlying stream, along with the
+    /// function's own result. Returns an error if the size of the content
+    /// cannot be represented as a `T`.
+    pub fn write_sized_subregion<T, F, R>(&mut self, f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<u64> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        let size = self.write_deferred(size_value::<T>(0)?)?;
+        let start_pos = self.w.stream_position()?;
+        let ret = f(self)?;
+        let end_pos = self.w.stream_position()?;
+        self.resolve(size, size_value(end_pos - start_pos)?)?;
+        Ok((start_pos..end_pos, ret))
+    }
+
+    /// Writes a chunk in the style of RIFF and IFF container formats: a
+    /// four-byte tag, a `u32` giving the length of the payload, the payload
+    /// produced by the given function, and then a padding byte if needed
+    /// to make the payload length even.
+    ///
+    /// The length is written using the writer's endianness, so use a
+    /// little-endian writer for RIFF and a big-endian writer for IFF and
+    /// AIFF. Chunks can be nested by calling `chunk` inside the function,
+    /// as for RIFF `LIST` and IFF `FORM` chunks.
+    ///
+    /// Returns a range describing the start and end positions of the
+    /// payload in the underlying stream, excluding any padding, along with
+    /// the function's own result.
+    pub fn chunk<F, R>(&mut self, tag: &[u8; 4], f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        self.chunk_padded(tag, 2, f)
+    }
+
+    /// Like [`chunk`](Self::chunk), but pads the payload to a multiple of
+    /// `n` bytes rather than to an even length. The padding uses the
+    /// writer's padding byte, as set by [`set_padding`](Self::set_padding).
+    pub fn chunk_padded<F, R>(
+        &mut self,
+        tag: &[u8; 4],
+        n: usize,
+        f: F,
+    ) -> Result<(std::ops::Range<u64>, R)>
+    where
+        F: FnOnce(&mut Self) -> Result<R>,
+    {
+        self.w.write_all(&tag[..])?;
+        let (rng, ret) = self.write_sized_subregion::<u32, _, _>(f)?;
+        self.align_from(rng.start, n)?;
+        Ok((rng, ret))
+    }
+
+    /// Writes a region produced by the given function after compressing it
+    /// with the given compression algorithm.
+    ///
+    /// The function receives a separate writer over an in-memory buffer,
+    /// with the same endianness and padding as this writer. Any deferred
+    /// slots it creates must be resolved before it returns, because the
+    /// data must be final before it can be compressed. Any offsets it
+    /// writes are relative to the start of the uncompressed data.
+    ///
+    /// If the given function completes successfully, `compressed_subregion`
+    /// returns a [`Compressed`](compress::Compressed) describing the sizes
+    /// of the compressed and uncompressed data, along with the function's
+    /// own result. Pass those sizes to [`resolve`](Self::resolve) to
+    /// complete any headers that describe the compressed region.
+    #[cfg(feature = "compress")]
+    pub fn compressed_subregion<C, F, R>(
+        &mut self,
+        mut compressor: C,
+        f: F,
+    ) -> Result<(compress::Compressed, R)>
+    where
+        C: compress::Compressor,
+        for<'w> F: FnOnce(&mut Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>) -> Result<R>,
+    {
+        let mut buf = Vec::<u8>::new();
+        let mut cursor = std::io::Cursor::new(&mut buf);
+        let mut wr = Writer::new_with_endianness(&mut cursor, self.endianness());
+        wr.state.pad = self.state.pad.clone();
+        let ret = f(&mut wr)?;
+        wr.finalize()?;
+
+        let start_pos = self.w.stream_position()?;
+        compressor.compress(&buf[..], &mut self.w)?;
+        let end_pos = self.w.stream_position()?;
+        let compressed = compress::Compressed {
+            range: start_pos..end_pos,
+            uncompressed_len: buf.len() as u64,
+        };
+        Ok((compressed, ret))
+    }
+
+    /// Writes a region produced by the given function into memory, so that
+    /// it can be written to the output later using [`emit`](Self::emit).
+    ///
+    /// This is for formats where some data must appear before other data
+    /// that it depends on, such as a directory that precedes the entries it
+    /// describes. The function receives a writer over an in-memory buffer
+    /// that shares this writer's deferred slots and labels, so deferred
+    /// slots and labels created inside the region can be resolved, placed,
+    /// and referred to from anywhere, and will be updated with their final
+    /// values once the region is emitted.
+    ///
+    /// Positions reported by the inner writer, such as by
+    /// [`position`](Self::position) and [`place`](Self::place), are
+    /// relative to the start of the region. Use labels for any offsets
+    /// that must be relative to the start of the final output.
+    ///
+    /// A region that is never emitted is discarded, but finalization will
+    /// fail if any label was placed inside it.
+    pub fn buffered<F, R>(&mut self, f: F) -> Result<(Buffered<'a>, R)>
+    where
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>) -> Result<R>,
+    {
+        let idx = self.state.buffers.len();
+        self.state.buffers.push(BufferState {
+            data: None,
+            emitted_at: None,
+        });
+        let parent = self.state.cur_buffer.replace(idx);
+        let mut inner = Writer {
+            w: stream::Stream::new(std::io::Cursor::new(Vec::with_capacity(
+                self.state.buffer_capacity,
+            ))),
+            state: std::mem::take(&mut self.state),
+            endianness: self.endianness,
+            reader: None,
+            hooks: Vec::new(),
+            start: 0,
+            _phantom: std::marker::PhantomData,
+        };
+        inner.w.set_trace(self.w.trace().cloned());
+        inner.w.set_cancel(self.w.cancel().cloned());
+        inner
+            .w
+            .trace_event(|| format!("begin buffered region {} (positions are relative)", idx))?;
+        let ret = f(&mut inner).and_then(|ret| {
+            inner.run_finalize_hooks()?;
+            Ok(ret)
+        });
+        self.state = inner.state;
+        self.state.cur_buffer = parent;
+        self.state.buffers[idx].data = Some(inner.w.into_inner().into_inner());
+        self.w
+            .trace_event(|| format!("end buffered region {}", idx))?;
+        let ret = ret?;
+        self.apply_queued()?;
+        Ok((Buffered::new(idx), ret))
+    }
+
+    /// Writes a tag-length-value record: the given tag, the length of the
+    /// value as type `L`, and then the value produced by the given function.
+    ///
+    /// The value is written into a [`buffered`](Self::buffered) region
+    /// first so that its length is known before writing the length field,
+    /// and so `L` can be a variable-length type such as
+    /// [`Uleb128`](varint::Uleb128). To write the tag or length with a
+    /// different endianness than the writer, wrap the tag with
+    /// [`as_big_endian`](pack::as_big_endian) or similar, or write the whole
+    /// record inside [`with_endian`](Self::with_endian).
+    ///
+    /// Returns a range describing the start and end positions of the value
+    /// in the underlying stream, along with the function's own result.
+    /// Returns an error if the length cannot be represented as an `L`.
+    pub fn tlv<T, L, F, R>(&mut self, tag: T, f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        T: pack::IntoPack,
+        L: pack::IntoPack + std::convert::TryFrom<u64>,
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>) -> Result<R>,
+    {
+        self.write_tlv::<T, L, F, R>(tag, false, f)
+    }
+
+    /// Like [`tlv`](Self::tlv), but the length includes the lengths of the
+    /// tag and length fields as well as the value.
+    pub fn tlv_inclusive<T, L, F, R>(&mut self, tag: T, f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        T: pack::IntoPack,
+        L: pack::IntoPack + std::convert::TryFrom<u64>,
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>) -> Result<R>,
+    {
+        self.write_tlv::<T, L, F, R>(tag, true, f)
+    }
+
+    fn write_tlv<T, L, F, R>(
+        &mut self,
+        tag: T,
+        inclusive: bool,
+        f: F,
+    ) -> Result<(std::ops::Range<u64>, R)>
+    where
+        T: pack::IntoPack,
+        L: pack::IntoPack + std::convert::TryFrom<u64>,
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>) -> Result<R>,
+    {
+        use pack::Pack;
+
+        let (buffered, ret) = self.buffered(f)?;
+        let value_len = self.state.buffers[buffered.idx]
+            .data
+            .as_ref()
+            .unwrap()
+            .len() as u64;
+        let tag = tag.into_pack();
+        let mut len = size_value::<L>(value_len)?.into_pack();
+        if inclusive {
+            // For variable-length encodings the length of the length field
+            // can depend on its own value, so we iterate until it's stable.
+            let header_len = tag.pack_len() as u64;
+            loop {
+                let len_len = len.pack_len();
+                len = size_value::<L>(value_len + header_len + len_len as u64)?.into_pack();
+                if len.pack_len() == len_len {
+                    break;
+                }
+            }
+        }
+        let endianness = self.endianness();
+        write_pack_value(&mut self.w, &tag, endianness)?;
+        write_pack_value(&mut self.w, &len, endianness)?;
+        let rng = self.emit(buffered)?;
+        Ok((rng, ret))
+    }
+
+    /// Writes an integer field whose width is chosen based on its final
+    /// value, followed by the content produced by the given function.
+    ///
+    /// This is for formats like ZIP64 that use a narrow encoding of type
+    /// `N` for values that fit in it, and a wide encoding of type `Wd`
+    /// otherwise, such as for sizes and offsets that may exceed 4 GiB. A
+    /// field can't change width after the data following it is written, so
+    /// the given function writes that data into a [`buffered`](Self::buffered)
+    /// region instead. It also receives a deferred slot for the field's
+    /// value, which it must resolve before returning. The field is then
+    /// written using `N` if the value can be converted to `N`, or using
+    /// `Wd` otherwise, followed by the buffered data. Returns an error if
+    /// the value can't be converted to `Wd` either.
+    ///
+    /// Positions reported by the inner writer are relative to the start
+    /// of the buffered data, as with [`buffered`](Self::buffered), and so
+    /// the value should not depend on the width of its own field. Labels
+    /// placed in the buffered data are updated with their final positions.
+    pub fn write_auto_width<N, Wd, F, R>(&mut self, f: F) -> Result<R>
+    where
+        N: pack::IntoPack + std::convert::TryFrom<u64>,
+        <N as pack::IntoPack>::PackType: pack::FixedLenPack,
+        Wd: pack::IntoPack + std::convert::TryFrom<u64>,
+        <Wd as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>, Deferred<'a, u64>) -> Result<R>,
+    {
+        let value = self.deferred(0_u64);
+        let (buffered, ret) = self.buffered(|w| f(w, value))?;
+        if self.state.slots[value.idx].value.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "auto-width field was not resolved by the end of its region",
+            ));
+        }
+        let v = resolved_value(&self.state.slots, value);
+        let endianness = self.endianness();
+        match N::try_from(v) {
+            Ok(n) => write_intopack_value(&mut self.w, n, endianness)?,
+            Err(_) => write_intopack_value(&mut self.w, size_value::<Wd>(v)?, endianness)?,
+        };
+        self.emit(buffered)?;
+        Ok(ret)
+    }
+
+    /// Writes a region previously produced by [`buffered`](Self::buffered)
+    /// at the current position, returning the range it occupies in the
+    /// underlying stream.
+    pub fn emit(&mut self, buffered: Buffered<'a>) -> Result<std::ops::Range<u64>> {
+        let start = self.location()?;
+        // Each region's data is populated when its writer completes, and
+        // the handle is consumed here, so the data must be present.
+        let data = self.state.buffers[buffered.idx].data.take().unwrap();
+        self.w
+            .set_label(|| format!("buffered region {}", buffered.idx).into());
+        let ret = self.w.write_all(&data[..]);
+        self.w.clear_label();
+        ret?;
+        self.state.buffers[buffered.idx].emitted_at = Some(start);
+        Ok(start.pos..start.pos + data.len() as u64)
+    }
+
+    /// Creates a slot for a value whose resolution will come later in
+    /// the process of writing all of the data.
+    ///
+    /// You can use [`write_placeholder`](Self::write_placeholder) to reserve
+    /// an area of the output where the final value will eventually be
+    /// written. The reserved space will initially contain the value
+    /// given in `initial`.
+    ///
+    /// Call [`resolve`](Self::resolve) to set the final value for this slot.
+    /// That will then overwrite any placeholders written earlier with
+    /// the final value, and any placeholders written afterwards will
+    /// contain the final value directly.
+    ///
+    /// Any type whose packed form has a fixed length can be deferred,
+    /// including byte arrays such as `[u8; 16]`, which is useful for
+    /// digests, identifiers and other opaque blocks whose content is only
+    /// known at the end.
+    pub fn deferred<T>(&mut self, initial: T) -> Deferred<'a, T>
+    where
+        T: pack::IntoPack + Clone + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let next_idx = self.state.slots.len();
+        self.state.slots.push(Slot {
+            positions: Vec::new(),
+            initial: Box::new(initial),
+            value: None,
+            allow_unresolved: false,
+        });
+        deferred::Deferred::new(next_idx)
+    }
+
+    /// Writes a placeholder for the given deferred slot to the current
+    /// position in the output.
+    ///
+    /// At some later point you should pass the same deferred slot to
+    /// [`resolve`](Self::resolve) along with its final value, at which point
+    /// the placeholder will be overwritten. If the slot is already resolved
+    /// then this just writes its final value.
+    pub fn write_placeholder<T>(&mut self, deferred: Deferred<'a, T>) -> Result<usize>
+    where
+        T: pack::IntoPack,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        // We write the slot's initial value for now, but also track
+        // in self.state.slots where this was so that resolving it later can
+        // overwrite with the final value.
+        let pos = self.location()?;
+        let endianness = self.endianness();
+        let slot = &self.state.slots[deferred.idx];
+        let bytes = slot
+            .value
+            .as_ref()
+            .unwrap_or(&slot.initial)
+            .pack(endianness);
+        self.w.set_label(|| {
+            format!(
+                "placeholder for slot {} ({})",
+                deferred.idx,
+                std::any::type_name::<T>()
+            )
+            .into()
+        });
+        let ret = self.w.write_all(&bytes[..]);
+        self.w.clear_label();
+        ret?;
+        let size = bytes.len();
+        self.state.slots[deferred.idx]
+            .positions
+            .push((pos, endianness));
+        Ok(size)
+    }
+
+    /// Writes `n` zero bytes as a placeholder for raw content that will be
+    /// provided later using [`fill_reservation`](Self::fill_reservation).
+    pub fn reserve(&mut self, n: usize) -> Result<Reservation<'a>> {
+        let idx = self.state.slots.len();
+        let pos = self.location()?;
+        self.fill(0, n)?;
+        self.state.slots.push(Slot {
+            positions: vec![(pos, self.endianness())],
+            initial: Box::new(RawBytes(vec![0; n])),
+            value: None,
+            allow_unresolved: false,
+        });
+        Ok(Reservation::new(idx, n))
+    }
+
+    /// Overwrites an area reserved using [`reserve`](Self::reserve) with the
+    /// given bytes, which must be exactly the reserved length.
+    pub fn fill_reservation(&mut self, r: Reservation<'a>, bytes: &[u8]) -> Result<()> {
+        if bytes.len() != r.len {
+            let err = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "reservation is {} bytes but {} bytes were provided",
+                    r.len,
+                    bytes.len()
+                ),
+            );
+            return Err(self.error_context(err, Some(r.idx)));
+        }
+        self.resolve_slot(r.idx, Box::new(RawBytes(bytes.to_vec())))
+    }
+
+    /// Creates a new [`Counter`](Counter), whose slot will be resolved with
+    /// its final count when the writer is finalized.
+    ///
+    /// Finalization will fail if the final count cannot be represented as
+    /// a `T`.
+    pub fn counter<T>(&mut self) -> Result<Counter<'a, T>>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<u64> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let deferred = self.deferred(size_value::<T>(0)?);
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let final_count = count.clone();
+        self.state.counters.push(Box::new(move || {
+            let v = size_value::<T>(final_count.get())?;
+            Ok((deferred.idx, Box::new(v) as Box<dyn SlotValue>))
+        }));
+        Ok(Counter { deferred, count })
+    }
+
+    /// Creates a new [`DeferredOffset`](DeferredOffset) for an offset
+    /// relative to the given base position, such as the start of the
+    /// enclosing chunk, rather than to the start of the output. Its slot
+    /// initially contains zero.
+    ///
+    /// Positions are as reported by [`position`](Self::position), so a base
+    /// captured inside a [`buffered`](Self::buffered) region is relative to
+    /// the start of that region, as is the position the offset is later
+    /// resolved at.
+    pub fn deferred_offset<T>(&mut self, base: u64) -> Result<DeferredOffset<'a, T>>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<i128> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let deferred = self.deferred(offset_value::<T>(0, base)?);
+        Ok(DeferredOffset { deferred, base })
+    }
+
+    /// A shorthand combining [`deferred_offset`](Self::deferred_offset) and
+    /// [`write_placeholder`](Self::write_placeholder), for the common case
+    /// where the offset field is written immediately.
+    pub fn write_deferred_offset<T>(&mut self, base: u64) -> Result<DeferredOffset<'a, T>>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<i128> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let ret = self.deferred_offset(base)?;
+        self.write_placeholder(ret.deferred)?;
+        Ok(ret)
+    }
+
+    /// A shorthand combining [`deferred`](Self::deferred) and
+    /// [`write_placeholder`](Self::write_placeholder), to create a new
+    /// deferred slot and write a placeholder for it in a single call.
+    pub fn write_deferred<T>(&mut self, initial: T) -> Result<Deferred<'a, T>>
+    where
+        T: pack::IntoPack + Clone + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let ret = self.deferred(initial);
+        self.write_placeholder(ret)?;
+        Ok(ret)
+    }
+
+    /// Assigns a final value to a deferred data slot previously established
+    /// using [`deferred`](deferred).
+    ///
+    /// Resolving a slot also automatically resolves any slots derived from
+    /// it using [`deferred_map`](Self::deferred_map) and similar, once all of
+    /// their inputs are resolved.
+    ///
+    /// A slot may be resolved before any of its placeholders are written.
+    /// Resolving an already-resolved slot again with the same value has no
+    /// effect, but resolving it with a different value returns an error,
+    /// because that usually means two parts of the program disagree about
+    /// what the final value should be.
+    pub fn resolve<T>(&mut self, deferred: Deferred<'a, T>, v: T) -> Result<T>
+    where
+        T: pack::IntoPack + Clone + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        self.resolve_slot(deferred.idx, Box::new(v.clone()))?;
+        Ok(v)
+    }
+
+    /// Like [`resolve`](Self::resolve), but converts the given value to
+    /// the slot's type first, returning an error if the value doesn't fit.
+    ///
+    /// This is for resolving a slot of a narrow type with a value computed
+    /// as a wider type, such as a `u64` position resolved into a
+    /// `Deferred<u16>`, without silently truncating it.
+    pub fn resolve_checked<T, V>(&mut self, deferred: Deferred<'a, T>, v: V) -> Result<T>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<V> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+        V: Copy + std::fmt::Display,
+    {
+        let narrow = T::try_from(v).map_err(|_| {
+            let err = std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "value {} is out of range for deferred slot {}",
+                    v, deferred.idx
+                ),
+            );
+            self.error_context(err, Some(deferred.idx))
+        })?;
+        self.resolve(deferred, narrow)
+    }
+
+    /// Resolves the given relative offset slot with the offset of the
+    /// current position from its base, returning the offset.
+    ///
+    /// Returns an error if the offset doesn't fit in `T`, including if the
+    /// current position is before the base and `T` is unsigned.
+    pub fn resolve_offset<T>(&mut self, offset: DeferredOffset<'a, T>) -> Result<T>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<i128> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        self.resolve_offset_from(offset.deferred, offset.base)
+    }
+
+    /// Resolves the given slot with the offset of the current position from
+    /// the given base position, returning the offset.
+    ///
+    /// This is like [`resolve_offset`](Self::resolve_offset), but for a slot
+    /// created without a base, such as by
+    /// [`write_deferred`](Self::write_deferred).
+    pub fn resolve_offset_from<T>(&mut self, deferred: Deferred<'a, T>, base: u64) -> Result<T>
+    where
+        T: pack::IntoPack + Clone + std::convert::TryFrom<i128> + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let pos = self.position()?;
+        let v = offset_value::<T>(pos as i128 - base as i128, base)
+            .map_err(|err| self.error_context(err, Some(deferred.idx)))?;
+        self.resolve(deferred, v)
+    }
+
+    fn resolve_slot(&mut self, idx: usize, v: Box<dyn SlotValue>) -> Result<()> {
+        if let Some(existing) = &self.state.slots[idx].value {
+            let endianness = self.endianness();
+            if existing.pack(endianness) == v.pack(endianness) {
+                return Ok(());
+            }
+            let err = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "deferred slot {} is already resolved with a different value",
+                    idx
+                ),
+            );
+            return Err(self.error_context(err, Some(idx)));
+        }
+        self.w.trace_event(|| format!("resolve slot {}", idx))?;
+        let reset_pos = self.position()?; // will restore at the end
+        let result = self.write_resolved_values(idx, &*v);
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        result.map_err(|err| self.error_context(err, Some(idx)))?;
+        self.state.slots[idx].value = Some(v);
+        self.resolve_derivations()
+    }
+
+    fn write_resolved_values(&mut self, idx: usize, v: &dyn SlotValue) -> Result<()> {
+        for (loc, endianness) in self.state.slots[idx].positions.clone() {
+            self.w.set_label(|| format!("slot {}", idx).into());
+            self.patch_location(loc, v.pack(endianness))?;
+            self.w.clear_label();
+        }
+        Ok(())
+    }
+
+    /// Returns the current position as a [`Location`](Location), which is
+    /// relative to the start of the buffered region if this writer is
+    /// writing one.
+    fn location(&mut self) -> Result<Location> {
+        Ok(Location {
+            buffer: self.state.cur_buffer,
+            pos: self.position()?,
+        })
+    }
+
+    fn locate(&self, loc: Location) -> Target {
+        let mut loc = loc;
+        while let Some(idx) = loc.buffer {
+            if Some(idx) == self.state.cur_buffer {
+                break;
+            }
+            let buffer = &self.state.buffers[idx];
+            match buffer.emitted_at {
+                Some(at) => {
+                    loc = Location {
+                        buffer: at.buffer,
+                        pos: at.pos + loc.pos,
+                    }
+                }
+                None if buffer.data.is_some() => return Target::Buffer(idx, loc.pos),
+                None => return Target::Elsewhere(loc),
+            }
+        }
+        if loc.buffer == self.state.cur_buffer {
+            Target::Here(loc.pos)
+        } else {
+            Target::Elsewhere(loc)
+        }
+    }
+
+    /// Overwrites the data at the given location with the given bytes, or
+    /// queues the change for later if the location isn't reachable from
+    /// this writer.
+    ///
+    /// This may change the stream position, so callers must restore it
+    /// afterwards.
+    fn patch_location(&mut self, loc: Location, bytes: Vec<u8>) -> Result<()> {
+        match self.locate(loc) {
+            Target::Here(pos) => match self.state.journal.as_mut() {
+                Some(journal) if self.state.cur_buffer.is_none() => {
+                    journal.push((pos, bytes));
+                    Ok(())
+                }
+                _ => {
+                    self.w.seek(std::io::SeekFrom::Start(pos))?;
+                    self.w.write_all(&bytes[..])
+                }
+            },
+            Target::Buffer(idx, pos) => {
+                let data = self.state.buffers[idx].data.as_mut().unwrap();
+                let start = pos as usize;
+                data[start..start + bytes.len()].copy_from_slice(&bytes[..]);
+                Ok(())
+            }
+            Target::Elsewhere(loc) => {
+                self.state.queued.push((loc, bytes));
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes all of the patches recorded while batching patches, in order
+    /// of increasing offset, as selected using
+    /// [`Builder::batch_patches`](Builder::batch_patches).
     ///
-    /// A new `Writer` defaults to using zeros for padding. Use
-    /// [`set_padding`](Self::set_padding) to override the padding byte for
-    /// future writes, if needed.
-    pub fn align(&mut self, n: usize) -> Result<usize> {
-        let pos = self.position()?;
-        let ofs = pos % (n as u64);
-        if ofs == 0 {
-            return Ok(0);
+    /// This must happen before anything reads back or overwrites data in the
+    /// stream, so that the patches don't appear to be missing or later
+    /// overwrite newer data.
+    fn apply_journal(&mut self) -> Result<()> {
+        let entries = match self.state.journal.as_mut() {
+            Some(journal) if !journal.is_empty() => std::mem::take(journal),
+            _ => return Ok(()),
+        };
+        let mut entries = entries;
+        // The sort is stable, so later patches at the same offset still
+        // overwrite earlier ones.
+        entries.sort_by_key(|(pos, _)| *pos);
+        let reset_pos = self.position()?;
+        for (pos, bytes) in entries {
+            self.w.seek(std::io::SeekFrom::Start(pos))?;
+            self.w.write_all(&bytes[..])?;
         }
-        let inc = ((n as u64) - ofs) as usize;
-        self.skip(inc)
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        Ok(())
     }
 
-    /// Creates a region of the output whose final bounds must be known for
-    /// use elsewhere in the output.
+    /// Applies any queued changes that are now reachable from this writer.
+    fn apply_queued(&mut self) -> Result<()> {
+        let reset_pos = self.position()?; // will restore at the end
+        let queued = std::mem::take(&mut self.state.queued);
+        let mut result = Ok(());
+        for (loc, bytes) in queued {
+            if result.is_ok() {
+                result = self.patch_location(loc, bytes);
+            }
+        }
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        result
+    }
+
+    /// Marks the given deferred slot as intentionally unresolved, so that
+    /// finalization will succeed even if it's never passed to
+    /// [`resolve`](Self::resolve).
     ///
-    /// If the given function completes successfully, `subregion` returns
-    /// a range describing the start and end positions of the subregion
-    /// in the underlying stream.
-    pub fn subregion<F>(&mut self, f: F) -> Result<std::ops::Range<u64>>
+    /// Finalization normally fails if any deferred slot remains unresolved,
+    /// because that usually indicates a bug in the calling program. Any
+    /// placeholders for a slot marked in this way will retain the slot's
+    /// initial value if it's never resolved.
+    pub fn allow_unresolved<T>(&mut self, deferred: Deferred<'a, T>) {
+        self.state.slots[deferred.idx].allow_unresolved = true;
+    }
+
+    /// Creates a deferred slot whose value is derived from the value of
+    /// another deferred slot using the given function.
+    ///
+    /// The new slot is resolved automatically once its input is resolved.
+    /// Its initial value is the result of applying the function to the
+    /// input slot's initial value.
+    pub fn deferred_map<T, U, F>(&mut self, d: Deferred<'a, T>, f: F) -> Result<Deferred<'a, U>>
     where
-        F: FnOnce(&mut Self) -> Result<()>,
+        T: pack::IntoPack + Clone + 'static,
+        U: pack::IntoPack + Clone + 'static,
+        <U as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: Fn(T) -> U + 'a,
     {
-        let start_pos = self.w.stream_position()?;
-        f(self)?;
-        let end_pos = self.w.stream_position()?;
-        Ok(start_pos..end_pos)
+        let initial = f(initial_value(&self.state.slots, d));
+        self.deferred_derived(vec![d.idx], initial, move |slots| {
+            Ok(f(resolved_value(slots, d)))
+        })
     }
 
-    /// Creates a slot for a value whose resolution will come later in
-    /// the process of writing all of the data.
+    /// Creates a deferred slot whose value is the sum of the values of two
+    /// other deferred slots.
     ///
-    /// You can use [`write_placeholder`](Self::write_placeholder) to reserve
-    /// an area of the output where the final value will eventually be
-    /// written. The reserved space will initially contain the value
-    /// given in `initial`.
+    /// The new slot is resolved automatically once both of its inputs are
+    /// resolved. Resolving it fails with an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the sum
+    /// overflows `T`.
+    pub fn deferred_add<T>(
+        &mut self,
+        a: Deferred<'a, T>,
+        b: Deferred<'a, T>,
+    ) -> Result<Deferred<'a, T>>
+    where
+        T: pack::IntoPack + deferred::DeferredArith + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        // The initial value is only a placeholder, so it's allowed to wrap.
+        let initial =
+            initial_value(&self.state.slots, a).wrapping_add(initial_value(&self.state.slots, b));
+        self.deferred_derived(vec![a.idx, b.idx], initial, move |slots| {
+            resolved_value(slots, a)
+                .checked_add(resolved_value(slots, b))
+                .ok_or_else(arith_overflow)
+        })
+    }
+
+    /// Creates a deferred slot whose value is the difference between the
+    /// values of two other deferred slots, `a - b`.
     ///
-    /// Call [`resolve`](Self::resolve) to set the final value for this slot.
-    /// That will then overwrite any placeholders written earlier with
-    /// the final value.
-    pub fn deferred<T>(&mut self, initial: T) -> Deferred<'a, T>
+    /// This is useful for calculating the size of a region of the output
+    /// whose start and end offsets are both deferred. The new slot is
+    /// resolved automatically once both of its inputs are resolved.
+    /// Resolving it fails with an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the difference
+    /// overflows `T`, such as when `b` is greater than `a` for an unsigned
+    /// type.
+    pub fn deferred_sub<T>(
+        &mut self,
+        a: Deferred<'a, T>,
+        b: Deferred<'a, T>,
+    ) -> Result<Deferred<'a, T>>
     where
-        T: pack::IntoPack + Copy,
+        T: pack::IntoPack + deferred::DeferredArith + 'static,
         <T as pack::IntoPack>::PackType: pack::FixedLenPack,
     {
-        let next_idx = self.map.len();
-        self.map.push(Vec::new());
-        return deferred::Deferred::new(next_idx, initial);
+        // The initial value is only a placeholder, so it's allowed to wrap.
+        let initial =
+            initial_value(&self.state.slots, a).wrapping_sub(initial_value(&self.state.slots, b));
+        self.deferred_derived(vec![a.idx, b.idx], initial, move |slots| {
+            resolved_value(slots, a)
+                .checked_sub(resolved_value(slots, b))
+                .ok_or_else(arith_overflow)
+        })
     }
 
-    /// Writes a placeholder for the given deferred slot to the current
-    /// position in the output.
+    /// Creates a deferred slot whose value is the value of another deferred
+    /// slot with the fixed amount `n` added to it.
     ///
-    /// At some later point you should pass the same deferred slot to
-    /// [`resolve`](Self::resolve) along with its final value, at which point
-    /// the placeholder will be overwritten.
-    pub fn write_placeholder<T>(&mut self, deferred: Deferred<'a, T>) -> Result<usize>
+    /// The new slot is resolved automatically once its input is resolved.
+    /// Resolving it fails with an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the sum
+    /// overflows `T`.
+    pub fn deferred_offset_by<T>(&mut self, d: Deferred<'a, T>, n: T) -> Result<Deferred<'a, T>>
     where
-        T: pack::IntoPack,
+        T: pack::IntoPack + deferred::DeferredArith + 'static,
         <T as pack::IntoPack>::PackType: pack::FixedLenPack,
     {
-        // We write the slot's initial value for now, but also track
-        // in self.map where this was so that resolving it later can
-        // overwrite with the final value.
-        let pos = self.position()?;
-        let size = write_intopack_value::<_, _, E>(&mut self.w, deferred.initial)?;
-        self.map[deferred.idx].push(pos);
-        return Ok(size);
+        // The initial value is only a placeholder, so it's allowed to wrap.
+        let initial = initial_value(&self.state.slots, d).wrapping_add(n);
+        self.deferred_derived(vec![d.idx], initial, move |slots| {
+            resolved_value(slots, d)
+                .checked_add(n)
+                .ok_or_else(arith_overflow)
+        })
     }
 
-    /// A shorthand combining [`deferred`](Self::deferred) and
-    /// [`write_placeholder`](Self::write_placeholder), to create a new
-    /// deferred slot and write a placeholder for it in a single call.
-    pub fn write_deferred<T>(&mut self, initial: T) -> Result<Deferred<'a, T>>
+    fn deferred_derived<T, F>(
+        &mut self,
+        inputs: Vec<usize>,
+        initial: T,
+        f: F,
+    ) -> Result<Deferred<'a, T>>
     where
-        T: pack::IntoPack + Copy,
+        T: pack::IntoPack + Clone + 'static,
         <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: FnOnce(&[Slot]) -> Result<T> + 'a,
     {
         let ret = self.deferred(initial);
-        self.write_placeholder(ret)?;
+        self.state.derivations.push(Derivation {
+            inputs,
+            output: ret.idx,
+            compute: Some(Box::new(move |slots: &[Slot]| {
+                Ok(Box::new(f(slots)?) as Box<dyn SlotValue>)
+            })),
+        });
+        // The inputs might already be resolved, in which case we can
+        // resolve the new slot immediately.
+        self.resolve_derivations()?;
         Ok(ret)
     }
 
-    /// Assigns a final value to a deferred data slot previously established
-    /// using [`deferred`](deferred).
-    pub fn resolve<T>(&mut self, deferred: Deferred<'a, T>, v: T) -> Result<T>
+    fn resolve_derivations(&mut self) -> Result<()> {
+        loop {
+            let slots = &self.state.slots;
+            let ready = self.state.derivations.iter_mut().find(|d| {
+                d.compute.is_some() && d.inputs.iter().all(|idx| slots[*idx].value.is_some())
+            });
+            let (output, compute) = match ready {
+                Some(d) => (d.output, d.compute.take().unwrap()),
+                None => return Ok(()),
+            };
+            let v = compute(&self.state.slots[..])
+                .map_err(|err| self.error_context(err, Some(output)))?;
+            self.resolve_slot(output, v)?;
+        }
+    }
+
+    /// Adds the given string to a string table, returning a deferred slot
+    /// for its offset relative to the start of the table.
+    ///
+    /// If the table already contains an identical string then `intern`
+    /// returns the existing slot for that string, so each distinct string
+    /// appears only once in the table.
+    ///
+    /// The slot is resolved when the table is written using
+    /// [`emit_string_table`](Self::emit_string_table).
+    pub fn intern<S: AsRef<[u8]>>(
+        &mut self,
+        table: &mut StringTable<'a>,
+        s: S,
+    ) -> Deferred<'a, u32> {
+        let s = s.as_ref();
+        if let Some(existing) = table.get(s) {
+            return existing;
+        }
+        let ret = self.deferred(0);
+        table.insert(s, ret);
+        ret
+    }
+
+    /// Writes the given string table at the current position, with each
+    /// string followed by a null terminator, and resolves the offsets of all
+    /// of the strings that were interned into it.
+    ///
+    /// Returns a range describing the start and end positions of the table
+    /// in the underlying stream. Returns an error if any of the strings
+    /// contains a null byte, or if the table grows too large for its offsets
+    /// to fit in a `u32`.
+    pub fn emit_string_table(&mut self, table: &StringTable<'a>) -> Result<std::ops::Range<u64>> {
+        let start_pos = self.position()?;
+        for (s, deferred) in &table.strings {
+            if s.contains(&0) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "string table entries must not contain null bytes",
+                ));
+            }
+            let ofs = self.position()? - start_pos;
+            let ofs = <u32 as std::convert::TryFrom<u64>>::try_from(ofs).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("string table offset {} does not fit in a u32", ofs),
+                )
+            })?;
+            self.resolve(*deferred, ofs)?;
+            self.w.write_all(&s[..])?;
+            self.w.write_all(&[0])?;
+        }
+        let end_pos = self.position()?;
+        Ok(start_pos..end_pos)
+    }
+
+    /// Adds a new entry to the given offset table, returning the entry
+    /// with its deferred offset and size slots.
+    ///
+    /// Write the blob the entry describes using
+    /// [`write_table_blob`](Self::write_table_blob) to resolve its slots.
+    pub fn table_entry(&mut self, table: &mut OffsetTable<'a>) -> TableEntry<'a> {
+        let entry = TableEntry {
+            offset: self.deferred(0),
+            size: self.deferred(0),
+        };
+        table.entries.push(entry);
+        entry
+    }
+
+    /// Writes the blob for a table entry using the given function, and
+    /// then resolves the entry's offset and size to describe it.
+    ///
+    /// Returns a range describing the start and end positions of the blob
+    /// in the underlying stream, along with the function's own result.
+    pub fn write_table_blob<F, R>(
+        &mut self,
+        entry: TableEntry<'a>,
+        f: F,
+    ) -> Result<(std::ops::Range<u64>, R)>
     where
-        T: pack::IntoPack + Copy,
-        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: FnOnce(&mut Self) -> Result<R>,
     {
-        let reset_pos = self.position()?; // will restore at the end
-        let result = self.write_resolved_values(deferred, v);
-        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
-        result
+        let (rng, ret) = self.subregion_with(f)?;
+        self.resolve(entry.offset, rng.start)?;
+        self.resolve(entry.size, rng.end - rng.start)?;
+        Ok((rng, ret))
     }
 
-    fn write_resolved_values<T>(&mut self, deferred: Deferred<'a, T>, v: T) -> Result<T>
+    /// Writes the given offset table at the current position by calling
+    /// the given function once for each entry in order, returning a range
+    /// describing the start and end positions of the table.
+    ///
+    /// The function decides how each entry is encoded. If the blobs haven't
+    /// been written yet, it can write placeholders for the entry's slots,
+    /// possibly converted to narrower types using
+    /// [`deferred_map`](Self::deferred_map).
+    pub fn emit_table<F>(
+        &mut self,
+        table: &OffsetTable<'a>,
+        mut f: F,
+    ) -> Result<std::ops::Range<u64>>
     where
-        T: pack::IntoPack + Copy,
-        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+        F: FnMut(&mut Self, TableEntry<'a>) -> Result<()>,
     {
-        let pv = v.into_pack();
-        for offset in &self.map[deferred.idx] {
-            self.w.seek(std::io::SeekFrom::Start(*offset))?;
-            write_pack_value::<_, _, E>(&mut self.w, &pv)?;
+        self.subregion(|w| {
+            for entry in table.entries() {
+                f(w, *entry)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Creates a new label representing a position in the output that
+    /// isn't known yet.
+    ///
+    /// Use [`write_offset_of`](Self::write_offset_of) to write references to
+    /// the label's eventual offset, and [`place`](Self::place) to bind the
+    /// label to the current position. Every label must be placed before the
+    /// writer is finalized, or finalization will fail.
+    pub fn label(&mut self) -> Label<'a> {
+        let next_idx = self.state.labels.len();
+        self.state.labels.push(LabelState {
+            pos: None,
+            refs: Vec::new(),
+        });
+        Label::new(next_idx)
+    }
+
+    /// Returns the label for the named symbol, creating it if this is the
+    /// first use of the name.
+    ///
+    /// Named symbols are labels that can be shared with independently-built
+    /// [`Section`](section::Section)s, which refer to them by name. They
+    /// otherwise behave like labels created with [`label`](Self::label).
+    pub fn symbol(&mut self, name: &str) -> Label<'a> {
+        if let Some(idx) = self.state.symbols.get(name) {
+            return Label::new(*idx);
         }
-        Ok(v)
+        let ret = self.label();
+        self.state.symbols.insert(name.to_string(), ret.idx);
+        ret
+    }
+
+    /// Returns the offsets of all of the named symbols created using
+    /// [`symbol`](Self::symbol) that have been placed, for saving and then
+    /// loading into a later session using
+    /// [`import_symbols`](Self::import_symbols).
+    ///
+    /// Symbols placed in a [`buffered`](Self::buffered) region are included
+    /// only once the region has been emitted.
+    pub fn export_symbols(&self) -> registry::Registry {
+        let mut ret = registry::Registry::new();
+        for (name, idx) in &self.state.symbols {
+            if let Some(Target::Here(pos)) = self.state.labels[*idx].pos.map(|loc| self.locate(loc))
+            {
+                ret.insert(name.clone(), pos);
+            }
+        }
+        ret
+    }
+
+    /// Places the named symbols recorded in the given registry at their
+    /// recorded offsets, as if they had been placed using
+    /// [`place`](Self::place) earlier in this session.
+    ///
+    /// Returns an error if any of the symbols was already placed at a
+    /// different offset.
+    pub fn import_symbols(&mut self, registry: &registry::Registry) -> Result<()> {
+        for (name, pos) in registry.iter() {
+            let label = self.symbol(name);
+            let state = &mut self.state.labels[label.idx];
+            match state.pos {
+                Some(loc) if loc.buffer.is_some() || loc.pos != pos => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("symbol {:?} was already placed elsewhere", name),
+                    ));
+                }
+                _ => state.pos = Some(Location { buffer: None, pos }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the section functions of the given [`Planner`](plan::Planner)
+    /// until their layout is stable, and then writes all of the sections
+    /// at the current position, returning their final layout.
+    ///
+    /// Returns an error if any of the section functions fails, or if the
+    /// layout doesn't stabilize within the planner's maximum number of
+    /// passes.
+    pub fn write_planned(&mut self, planner: &mut plan::Planner<'_, E>) -> Result<plan::Plan> {
+        let base = self.position()?;
+        let (plan, bufs) = planner.run(base, self.endianness())?;
+        for buf in bufs {
+            self.w.write_all(&buf[..])?;
+        }
+        Ok(plan)
+    }
+
+    /// Writes the given [`Section`](section::Section) at the current
+    /// position, returning the range it occupies in the underlying stream.
+    ///
+    /// The section's labels are rebased to its final position, and any
+    /// named symbols it placed or referred to are merged with the symbols
+    /// of the same name in this writer. Returns an error if the section
+    /// places a symbol that was already placed.
+    pub fn emit_section(&mut self, section: section::Section) -> Result<std::ops::Range<u64>> {
+        let start = self.location()?;
+        self.w.write_all(&section.data[..])?;
+        for sl in section.labels {
+            let label = match &sl.name {
+                Some(name) => self.symbol(name),
+                None => self.label(),
+            };
+            let state = &mut self.state.labels[label.idx];
+            if let Some(pos) = sl.pos {
+                if state.pos.is_some() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "symbol {:?} was already placed",
+                            sl.name.unwrap_or_default()
+                        ),
+                    ));
+                }
+                state.pos = Some(Location {
+                    buffer: start.buffer,
+                    pos: start.pos + pos,
+                });
+            }
+            for (pos, len, endianness, kind) in sl.refs {
+                let loc = Location {
+                    buffer: start.buffer,
+                    pos: start.pos + pos,
+                };
+                state.refs.push((loc, len, endianness, kind));
+            }
+        }
+        Ok(start.pos..start.pos + section.data.len() as u64)
+    }
+
+    /// Removes all of the labels from this writer, for inclusion in a
+    /// [`Section`](section::Section).
+    fn take_section_labels(&mut self) -> Result<Vec<section::SectionLabel>> {
+        let mut names: std::collections::HashMap<usize, String> = self
+            .state
+            .symbols
+            .drain()
+            .map(|(name, idx)| (idx, name))
+            .collect();
+        let labels = std::mem::take(&mut self.state.labels);
+        let mut ret = Vec::with_capacity(labels.len());
+        for (idx, state) in labels.into_iter().enumerate() {
+            let name = names.remove(&idx);
+            let pos = match state.pos.map(|loc| self.locate(loc)) {
+                Some(Target::Here(pos)) => Some(pos),
+                Some(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "label {} was placed in a buffered region that was never emitted",
+                            idx
+                        ),
+                    ))
+                }
+                None if name.is_some() => None,
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("label {} was never placed", idx),
+                    ))
+                }
+            };
+            let refs = state
+                .refs
+                .into_iter()
+                .filter_map(|(loc, len, endianness, kind)| match self.locate(loc) {
+                    Target::Here(pos) => Some((pos, len, endianness, kind)),
+                    // References in buffered regions that were never emitted
+                    // are discarded along with the rest of the region's data.
+                    _ => None,
+                })
+                .collect();
+            ret.push(section::SectionLabel { name, pos, refs });
+        }
+        Ok(ret)
+    }
+
+    /// Binds the given label to the current position in the output,
+    /// returning that position.
+    ///
+    /// Returns an error if the label was already placed.
+    pub fn place(&mut self, label: Label<'a>) -> Result<u64> {
+        let pos = self.location()?;
+        let state = &mut self.state.labels[label.idx];
+        if state.pos.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("label {} was already placed", label.idx),
+            ));
+        }
+        state.pos = Some(pos);
+        Ok(pos.pos)
+    }
+
+    /// Writes the offset of the given label as an unsigned integer of type
+    /// `T`, or a placeholder to be updated during finalization if the label
+    /// hasn't been placed yet.
+    ///
+    /// Finalization will fail if the label's final offset is too large to
+    /// fit in `T`.
+    pub fn write_offset_of<T>(&mut self, label: Label<'a>) -> Result<usize>
+    where
+        T: pack::FixedLenPack,
+    {
+        self.write_label_ref(label, <T as pack::FixedLenPack>::PACK_LEN, RefKind::Offset)
+    }
+
+    /// Like [`write_offset_of`](Self::write_offset_of), but writes the
+    /// virtual address that the label's offset maps to, as selected using
+    /// [`set_vaddr_base`](Self::set_vaddr_base).
+    ///
+    /// Finalization will fail if the label's final offset has no virtual
+    /// address mapping, or if the address is too large to fit in `T`.
+    pub fn write_vaddr_of<T>(&mut self, label: Label<'a>) -> Result<usize>
+    where
+        T: pack::FixedLenPack,
+    {
+        self.write_label_ref(label, <T as pack::FixedLenPack>::PACK_LEN, RefKind::Vaddr)
+    }
+
+    fn write_label_ref(&mut self, label: Label<'a>, len: usize, kind: RefKind) -> Result<usize> {
+        let pos = self.location()?;
+        let placed = self.state.labels[label.idx].pos.map(|loc| self.locate(loc));
+        let v = match placed {
+            // Positions are only final if we're writing directly to the
+            // output, rather than to a buffer or section that will move.
+            Some(Target::Here(v)) if self.state.cur_buffer.is_none() && !self.state.in_section => {
+                match kind {
+                    RefKind::Offset => v,
+                    // The mapping might be added later, so this is only a
+                    // best effort until finalization.
+                    RefKind::Vaddr => self.vaddr_of(v).unwrap_or(0),
+                    RefKind::Reloc { addend, .. } => reloc_value(v, addend)?,
+                }
+            }
+            _ => 0,
+        };
+        // The reference is recorded even if we already know the label's
+        // position, because insert_at might move the label later.
+        let endianness = self.endianness();
+        self.state.labels[label.idx]
+            .refs
+            .push((pos, len, endianness, kind));
+        write_uint_value(&mut self.w, v, len, endianness)
+    }
+
+    /// Writes a reference to the given label of type `T` that becomes a
+    /// relocation if the label is never placed, such as for a symbol
+    /// defined in some other object file.
+    ///
+    /// If the label is placed then the field is updated with its offset
+    /// plus `addend` during finalization, as with
+    /// [`write_offset_of`](Self::write_offset_of). Otherwise the field is
+    /// left containing zero, and [`relocations`](Self::relocations) reports
+    /// it along with the given format-specific `kind` and `addend`, so
+    /// that a relocation table can be emitted in whatever encoding the
+    /// target format uses.
+    pub fn write_reloc<T>(&mut self, label: Label<'a>, kind: u32, addend: i64) -> Result<usize>
+    where
+        T: pack::FixedLenPack,
+    {
+        self.write_label_ref(
+            label,
+            <T as pack::FixedLenPack>::PACK_LEN,
+            RefKind::Reloc { kind, addend },
+        )
+    }
+
+    /// Returns the relocations for all of the references written using
+    /// [`write_reloc`](Self::write_reloc) to labels that aren't placed yet,
+    /// in order of increasing offset.
+    ///
+    /// To emit a relocation table after all labels that will be placed
+    /// have been, call this from a function registered using
+    /// [`on_finalize`](Self::on_finalize). References inside
+    /// [`buffered`](Self::buffered) regions are included only once the
+    /// region has been emitted.
+    pub fn relocations(&self) -> Vec<reloc::Relocation> {
+        let mut names = std::collections::HashMap::new();
+        for (name, idx) in &self.state.symbols {
+            names.insert(*idx, name);
+        }
+        let mut ret = Vec::new();
+        for (idx, label) in self.state.labels.iter().enumerate() {
+            if label.pos.is_some() {
+                continue;
+            }
+            for (loc, len, _, kind) in &label.refs {
+                if let (RefKind::Reloc { kind, addend }, Target::Here(offset)) =
+                    (kind, self.locate(*loc))
+                {
+                    ret.push(reloc::Relocation {
+                        offset,
+                        len: *len,
+                        symbol: names.get(&idx).map(|name| name.to_string()),
+                        kind: *kind,
+                        addend: *addend,
+                    });
+                }
+            }
+        }
+        ret.sort_by_key(|r| r.offset);
+        ret
+    }
+
+    /// Maps the output from the given file offset onwards to virtual
+    /// addresses starting at `vaddr`, for formats such as executables that
+    /// refer to data by the address it will be loaded at.
+    ///
+    /// Each mapping applies until the file offset of the next mapping, so
+    /// calling this for the start of each segment describes the whole
+    /// output. Setting a mapping for an offset that already has one
+    /// replaces it.
+    pub fn set_vaddr_base(&mut self, file_off: u64, vaddr: u64) {
+        self.state.vaddrs.insert(file_off, vaddr);
+    }
+
+    /// Returns the virtual address that the given file offset maps to,
+    /// using the mappings selected by [`set_vaddr_base`](Self::set_vaddr_base).
+    ///
+    /// Returns an error if the offset is before the first mapping.
+    pub fn vaddr_of(&self, file_off: u64) -> Result<u64> {
+        let (base, vaddr) = self
+            .state
+            .vaddrs
+            .range(..=file_off)
+            .next_back()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("offset {:#x} has no virtual address mapping", file_off),
+                )
+            })?;
+        vaddr.checked_add(file_off - base).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("virtual address for offset {:#x} overflows", file_off),
+            )
+        })
+    }
+
+    /// Returns the virtual address of the current position, as with
+    /// [`vaddr_of`](Self::vaddr_of).
+    ///
+    /// Returns an error inside a [`buffered`](Self::buffered) region, whose
+    /// final position isn't known yet.
+    pub fn vaddr(&mut self) -> Result<u64> {
+        if self.state.cur_buffer.is_some() || self.state.in_section {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "virtual addresses are not known inside a buffered region or section",
+            ));
+        }
+        let pos = self.position()?;
+        self.vaddr_of(pos)
+    }
+
+    fn finalize(mut self) -> Result<W> {
+        self.complete()?;
+        Ok(self.w.into_inner())
+    }
+
+    /// Performs all of the work of [`finalize`](Self::finalize) except for
+    /// returning the underlying stream, so that the writer's state can be
+    /// inspected afterwards.
+    fn complete(&mut self) -> Result<()> {
+        self.run_finalize_hooks()?;
+        self.apply_queued()?;
+
+        let counters = std::mem::take(&mut self.state.counters);
+        for counter in counters {
+            let (idx, v) = counter()?;
+            self.resolve_slot(idx, v)?;
+        }
+
+        let reset_pos = self.position()?; // will restore at the end
+        let labels = std::mem::take(&mut self.state.labels);
+        for (idx, state) in labels.iter().enumerate() {
+            let v = match state.pos.map(|loc| self.locate(loc)) {
+                Some(Target::Here(v)) => v,
+                Some(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "label {} was placed in a buffered region that was never emitted",
+                            idx
+                        ),
+                    ))
+                }
+                // Relocations are left for some later process to resolve.
+                None if state
+                    .refs
+                    .iter()
+                    .all(|r| matches!(r.3, RefKind::Reloc { .. })) =>
+                {
+                    continue
+                }
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("label {} was never placed", idx),
+                    ))
+                }
+            };
+            for (loc, len, endianness, kind) in &state.refs {
+                let v = match kind {
+                    RefKind::Offset => v,
+                    RefKind::Vaddr => self.vaddr_of(v)?,
+                    RefKind::Reloc { addend, .. } => reloc_value(v, *addend)?,
+                };
+                let mut bytes = Vec::with_capacity(*len);
+                write_uint_value(&mut bytes, v, *len, *endianness)?;
+                // References in buffered regions that were never emitted
+                // are discarded along with the rest of the region's data.
+                if let Target::Here(_) = self.locate(*loc) {
+                    self.patch_location(*loc, bytes)?;
+                }
+            }
+        }
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        self.apply_journal()?;
+
+        let pending = std::mem::take(&mut self.state.pending);
+        for derivation in pending {
+            self.w.check_cancel()?;
+            let reader = self.reader.ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "derive_deferred was used in a nested writer, but the outermost writer's stream is not known to be readable",
+                )
+            })?;
+            let len = derivation.rng.end - derivation.rng.start;
+            self.w
+                .seek(std::io::SeekFrom::Start(derivation.rng.start))?;
+            let mut lr = derive::DeriveRead::new(reader(self.w.get_mut()?), len);
+            let result = (derivation.compute)(&mut lr);
+            self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+            self.resolve_slot(derivation.output, result?)?;
+        }
+
+        for (idx, slot) in self.state.slots.iter().enumerate() {
+            let allowed = slot.allow_unresolved
+                || self.state.unresolved == builder::UnresolvedPolicy::KeepInitial;
+            if slot.value.is_none() && !allowed {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "deferred slot {} was never resolved (placeholders at offsets {:?})",
+                        idx,
+                        slot.positions
+                            .iter()
+                            .map(|(loc, _)| loc.pos)
+                            .collect::<Vec<_>>(),
+                    ),
+                ));
+            }
+        }
+
+        self.apply_journal()?;
+        self.w.flush()?;
+        Ok(())
     }
 }
 
@@ -380,8 +3346,122 @@ where
 impl<'a, W, E> Writer<'a, W, E>
 where
     W: Seek + Write + Read,
-    E: Endian,
+    E: ByteOrder,
 {
+    /// Records that the stream is readable, so that derivations postponed by
+    /// [`derive_deferred`](Self::derive_deferred) in nested writers can run
+    /// when this writer is finalized.
+    pub(crate) fn set_readable(&mut self) {
+        self.reader = Some(|w| w);
+    }
+
+    /// Inserts the region produced by the given function at the given
+    /// earlier position, moving everything already written at or after
+    /// that position later in the stream to make room.
+    ///
+    /// This is for when a part of a format turns out to need content that
+    /// wasn't planned for when writing began, such as an optional extension
+    /// header. The function receives a writer over an in-memory buffer, as
+    /// with [`buffered`](Self::buffered). Placeholders for deferred slots
+    /// and labels, references to labels, and labels themselves that were at
+    /// or after `pos` are all moved along with the existing content, so
+    /// offsets written using [`write_offset_of`](Self::write_offset_of)
+    /// will still be correct after finalization. Offsets resolved into
+    /// deferred slots directly are not adjusted, so use labels for any
+    /// offsets that might be affected by an insertion.
+    ///
+    /// Returns the range that the inserted content occupies, along with
+    /// the function's own result. The current position moves along with
+    /// the existing content if it was at or after `pos`.
+    pub fn insert_at<F, R>(&mut self, pos: u64, f: F) -> Result<(std::ops::Range<u64>, R)>
+    where
+        F: FnOnce(&mut Writer<'a, std::io::Cursor<Vec<u8>>, E>) -> Result<R>,
+    {
+        self.apply_journal()?;
+        let reset_pos = self.position()?;
+        let end = self.w.seek(std::io::SeekFrom::End(0))?;
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        if pos > end {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        let (buffered, ret) = self.buffered(f)?;
+
+        let mut tail = Vec::with_capacity((end - pos) as usize);
+        self.w.seek(std::io::SeekFrom::Start(pos))?;
+        (&mut self.w).take(end - pos).read_to_end(&mut tail)?;
+
+        // The existing locations must be shifted before the inserted region
+        // is emitted, because emitting records the region's own location,
+        // which is already in its final place.
+        let len = match &self.state.buffers[buffered.idx].data {
+            Some(data) => data.len() as u64,
+            None => 0,
+        };
+        self.shift_locations(pos, len);
+        self.w.seek(std::io::SeekFrom::Start(pos))?;
+        let inserted = self.emit(buffered)?;
+        self.w.write_all(&tail[..])?;
+
+        let reset_pos = if reset_pos >= pos {
+            reset_pos + len
+        } else {
+            reset_pos
+        };
+        self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+        Ok((inserted, ret))
+    }
+
+    /// Moves all recorded locations in the current stream that are at or
+    /// after `pos` forward by `len` bytes, after an insertion.
+    fn shift_locations(&mut self, pos: u64, len: u64) {
+        let cur = self.state.cur_buffer;
+        let shift = |loc: &mut Location| {
+            if loc.buffer == cur && loc.pos >= pos {
+                loc.pos += len;
+            }
+        };
+        let state = &mut self.state;
+        for slot in state.slots.iter_mut() {
+            for (loc, _) in slot.positions.iter_mut() {
+                shift(loc);
+            }
+        }
+        for label in state.labels.iter_mut() {
+            if let Some(loc) = label.pos.as_mut() {
+                shift(loc);
+            }
+            for (loc, _, _, _) in label.refs.iter_mut() {
+                shift(loc);
+            }
+        }
+        for (loc, _) in state.queued.iter_mut() {
+            shift(loc);
+        }
+        for (_, start, end, _) in state.regions.iter_mut() {
+            shift(start);
+            // A region that contains the insertion point grows to include
+            // the inserted data.
+            if end.buffer == cur && end.pos > pos {
+                end.pos += len;
+            }
+        }
+        for buffer in state.buffers.iter_mut() {
+            if let Some(loc) = buffer.emitted_at.as_mut() {
+                shift(loc);
+            }
+        }
+        if cur.is_none() {
+            for pending in state.pending.iter_mut() {
+                if pending.rng.start >= pos {
+                    pending.rng.start += len;
+                }
+                if pending.rng.end > pos {
+                    pending.rng.end += len;
+                }
+            }
+        }
+    }
+
     /// Derive a value from an already-written region of the underlying
     /// stream.
     ///
@@ -401,33 +3481,277 @@ where
         if rng.end < rng.start {
             return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
         }
+        self.apply_journal()?;
         let len = rng.end - rng.start;
         let after_pos = self.position()?;
         self.w.seek(std::io::SeekFrom::Start(rng.start))?;
-        let w = &mut self.w;
+        let w = self.w.get_mut()?;
         let mut lr = derive::DeriveRead::new(w, len);
         let ret = f(&mut lr);
         self.w.seek(std::io::SeekFrom::Start(after_pos))?;
         return ret;
     }
+
+    /// Reads a value of type `T` from the given position in the underlying
+    /// stream, using the writer's endianness, without moving the current
+    /// position.
+    ///
+    /// This is mainly for inspecting existing fields when editing, such as
+    /// with [`edit_le`](edit_le).
+    pub fn read_at<T: unpack::Unpack>(&mut self, pos: u64) -> Result<T> {
+        let len = T::UNPACK_LEN as u64;
+        let endianness = self.endianness();
+        self.derive(pos..pos + len, |r| {
+            let mut buf = vec![0_u8; len as usize];
+            r.read_exact(&mut buf[..])?;
+            Ok(endianness.unpack_from_slice(&buf[..]))
+        })
+    }
+
+    /// Calculates a CRC over an already-written region of the underlying
+    /// stream, using the given CRC parameters.
+    ///
+    /// The result is in the least significant
+    /// [`width`](checksum::CrcParams::width) bits of the returned value.
+    #[cfg(feature = "checksum")]
+    pub fn crc(&mut self, rng: std::ops::Range<u64>, params: checksum::CrcParams) -> Result<u64> {
+        self.derive(rng, |r| {
+            let mut crc = checksum::Crc::new(params);
+            std::io::copy(r, &mut crc)?;
+            Ok(crc.finish())
+        })
+    }
+
+    /// Calculates the standard CRC-32 used by PNG, ZIP, and many others
+    /// over an already-written region of the underlying stream.
+    #[cfg(feature = "checksum")]
+    pub fn crc32(&mut self, rng: std::ops::Range<u64>) -> Result<u32> {
+        self.crc(rng, checksum::CrcParams::CRC_32).map(|v| v as u32)
+    }
+
+    /// Like [`derive`](Self::derive), but postpones the derivation until
+    /// the end of writing and then resolves the given deferred slot with
+    /// its result.
+    ///
+    /// Because the derivation runs only after all other deferred slots and
+    /// labels have been resolved, the region it reads will contain their
+    /// final values rather than their placeholders. This is useful for
+    /// checksums that cover a whole file, including its header fields.
+    ///
+    /// If several derivations are postponed then they run in the order
+    /// they were requested, so a later derivation may include the result
+    /// of an earlier one in its region.
+    ///
+    /// Inside a [`with_endian`](Self::with_endian) scope or a
+    /// [`subregion_limited`](Self::subregion_limited) region, the
+    /// derivation runs when the outermost writer is finalized, so that
+    /// writer must also know that its stream is readable. Writers created by
+    /// [`write_vec_le`](crate::write_vec_le), [`append_vec_le`](crate::append_vec_le),
+    /// [`edit_le`](crate::edit_le) and similar functions always do, as does
+    /// any writer that has itself used `derive_deferred`.
+    pub fn derive_deferred<F, T>(
+        &mut self,
+        rng: std::ops::Range<u64>,
+        deferred: Deferred<'a, T>,
+        f: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut derive::DeriveRead<dyn derive::ReadSeek + '_>) -> Result<T> + 'a,
+        T: pack::IntoPack + Clone + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        if rng.end < rng.start {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        if self.state.cur_buffer.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "derive_deferred cannot be used inside a buffered region",
+            ));
+        }
+        self.set_readable();
+        self.state.pending.push(PendingDerive {
+            rng,
+            output: deferred.idx,
+            compute: Box::new(move |r| Ok(Box::new(f(r)?) as Box<dyn SlotValue>)),
+        });
+        Ok(())
+    }
+
+    /// Writes a body using the function `body` and then a placeholder for a
+    /// trailer, which is calculated from the body by the function `f`
+    /// during finalization, as with
+    /// [`derive_deferred`](Self::derive_deferred).
+    ///
+    /// Because the trailer is calculated only once all other placeholders
+    /// are updated, the body can contain deferred values that are resolved
+    /// later. Returns the result of `body` along with the deferred slot for
+    /// the trailer, which has the given initial value until finalization.
+    pub fn with_derived_trailer<B, R, F, T>(
+        &mut self,
+        initial: T,
+        body: B,
+        f: F,
+    ) -> Result<(R, Deferred<'a, T>)>
+    where
+        B: FnOnce(&mut Self) -> Result<R>,
+        F: FnOnce(&mut derive::DeriveRead<dyn derive::ReadSeek + '_>) -> Result<T> + 'a,
+        T: pack::IntoPack + Clone + 'static,
+        <T as pack::IntoPack>::PackType: pack::FixedLenPack,
+    {
+        let (rng, ret) = self.subregion_with(body)?;
+        let trailer = self.write_deferred(initial)?;
+        self.derive_deferred(rng, trailer, f)?;
+        Ok((ret, trailer))
+    }
+}
+
+fn resolved_value<T: Clone + 'static>(slots: &[Slot], deferred: Deferred<'_, T>) -> T {
+    // Derivations only run once all of their inputs are resolved, and
+    // each slot can only be resolved with a value of its own type.
+    slots[deferred.idx]
+        .value
+        .as_ref()
+        .and_then(|v| v.as_any().downcast_ref::<T>())
+        .unwrap()
+        .clone()
+}
+
+fn initial_value<T: Clone + 'static>(slots: &[Slot], deferred: Deferred<'_, T>) -> T {
+    // Each slot's initial value is always of its own type.
+    slots[deferred.idx]
+        .initial
+        .as_any()
+        .downcast_ref::<T>()
+        .unwrap()
+        .clone()
+}
+
+fn arith_overflow() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "derived value overflowed its type",
+    )
 }
 
-fn write_intopack_value<W: Write, V: pack::IntoPack, E: Endian>(mut w: W, v: V) -> Result<usize> {
+fn write_intopack_value<W: Write, V: pack::IntoPack>(
+    mut w: W,
+    v: V,
+    endianness: Endianness,
+) -> Result<usize> {
     let v = v.into_pack();
-    write_pack_value::<_, _, E>(&mut w, &v)
+    write_pack_value(&mut w, &v, endianness)
+}
+
+fn reloc_value(v: u64, addend: i64) -> Result<u64> {
+    v.checked_add_signed(addend).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("offset {:#x} with addend {} is out of range", v, addend),
+        )
+    })
+}
+
+fn size_value<T: std::convert::TryFrom<u64>>(v: u64) -> Result<T> {
+    T::try_from(v).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("size {} is out of range for the size field", v),
+        )
+    })
+}
+
+fn offset_value<T: std::convert::TryFrom<i128>>(v: i128, base: u64) -> Result<T> {
+    T::try_from(v).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "offset {} from base {:#x} is out of range for the offset field",
+                v, base
+            ),
+        )
+    })
+}
+
+fn write_uint_value<W: Write>(
+    w: &mut W,
+    v: u64,
+    len: usize,
+    endianness: Endianness,
+) -> Result<usize> {
+    if len < 8 && (v >> (8 * len)) != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("value {} does not fit in {} bytes", v, len),
+        ));
+    }
+    if len > 16 {
+        // Integer encodings are at most sixteen bytes, so wider fields
+        // have the value in their least significant sixteen bytes and
+        // zeros in the rest.
+        let mut buf = vec![0_u8; len];
+        match endianness {
+            Endianness::Little => endianness.write_integer(v as u128, &mut buf[..16]),
+            Endianness::Big => endianness.write_integer(v as u128, &mut buf[len - 16..]),
+            Endianness::Middle => {
+                Endianness::Big.write_integer(v as u128, &mut buf[len - 16..]);
+                for word in buf.chunks_exact_mut(2) {
+                    word.swap(0, 1);
+                }
+            }
+        }
+        w.write_all(&buf[..])?;
+        return Ok(len);
+    }
+    let mut buf = [0_u8; 16];
+    endianness.write_integer(v as u128, &mut buf[..len]);
+    w.write_all(&buf[..len])?;
+    Ok(len)
+}
+
+fn write_int_value<W: Write>(
+    w: &mut W,
+    v: i64,
+    len: usize,
+    endianness: Endianness,
+) -> Result<usize> {
+    if len > 8 {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    }
+    // A value fits if shifting away the bytes we're going to write leaves
+    // only sign bits behind.
+    let fits = match len {
+        0 => v == 0,
+        8 => true,
+        _ => {
+            let rest = v >> (8 * len - 1);
+            rest == 0 || rest == -1
+        }
+    };
+    if !fits {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("value {} does not fit in {} bytes", v, len),
+        ));
+    }
+    let mut buf = [0_u8; 8];
+    endianness.write_integer(v as u128, &mut buf[..len]);
+    w.write_all(&buf[..len])?;
+    Ok(len)
 }
 
-fn write_pack_value<W: Write, V: pack::Pack, E: Endian>(w: &mut W, v: &V) -> Result<usize> {
-    let l = v.pack_len();
-    let mut buf = vec![0 as u8; l];
-    v.pack_into_slice::<E>(&mut buf[..]);
-    w.write(&buf[..])
+fn write_pack_value<W: Write, V: pack::Pack>(
+    w: &mut W,
+    v: &V,
+    endianness: Endianness,
+) -> Result<usize> {
+    endianness.pack_to_writer(v, w)
 }
 
 impl<'a, T, E> Write for Writer<'a, T, E>
 where
     T: Seek + Write,
-    E: Endian,
+    E: ByteOrder,
 {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         self.w.write(buf)
@@ -437,3 +3761,111 @@ where
         self.w.flush()
     }
 }
+
+/// Writers can seek, so that they can be passed to functions that expect
+/// a stream implementing both [`Write`](Write) and [`Seek`](Seek).
+///
+/// Deferred slots and labels keep their positions regardless of seeking,
+/// so placeholders are still updated in the right place when resolved,
+/// replacing anything written over them before then. Seeking before the
+/// position where the writer started, such as the end of the existing data
+/// for a writer created using [`append_le`](append_le), returns an error of
+/// kind [`InvalidInput`](std::io::ErrorKind::InvalidInput); use
+/// [`at`](Writer::at) to write there instead.
+impl<'a, T, E> Seek for Writer<'a, T, E>
+where
+    T: Seek + Write,
+    E: ByteOrder,
+{
+    fn seek(&mut self, to: std::io::SeekFrom) -> Result<u64> {
+        self.apply_journal()?;
+        let (base, delta) = match to {
+            std::io::SeekFrom::Start(pos) => (pos, 0),
+            std::io::SeekFrom::End(delta) => {
+                let reset_pos = self.position()?;
+                let end = self.w.seek(std::io::SeekFrom::End(0))?;
+                self.w.seek(std::io::SeekFrom::Start(reset_pos))?;
+                (end, delta)
+            }
+            std::io::SeekFrom::Current(delta) => (self.position()?, delta),
+        };
+        match base.checked_add_signed(delta) {
+            Some(pos) if pos >= self.start => self.w.seek(std::io::SeekFrom::Start(pos)),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot seek before the writer's start position {}",
+                    self.start
+                ),
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        self.position()
+    }
+}
+
+/// Writes a sequence of fields using the given writer, as a more compact
+/// alternative to a long series of [`Writer::write`](Writer::write) calls.
+///
+/// Each field has a name and a type, separated by a colon, and then one of
+/// the following:
+///
+/// - `= value` writes the given value as the given type.
+/// - `= defer` writes a placeholder for a deferred value of the given type,
+///   which must implement `Default`, and binds the resulting
+///   [`Deferred`](deferred::Deferred) to a variable with the field's name
+///   so that it can be resolved later.
+///
+/// A field whose type is `cstr` writes the given string followed by a null
+/// terminator, and a field written as `name: align(n)` with no value
+/// writes padding to align the next field to `n` bytes. Other field names
+/// are for documentation only.
+///
+/// The expansion uses the `?` operator, so it can be used only in functions
+/// that return [`std::io::Result`], and the writer expression is evaluated
+/// once for each field.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// let mut buf = Vec::<u8>::new();
+/// binbin::write_vec_le(&mut buf, |w| {
+///     binbin::write_struct!(w, {
+///         magic: u32 = 0x1234,
+///         size: u16 = defer,
+///         name: cstr = "hi",
+///         _pad: align(8),
+///     });
+///     let end = w.position()?;
+///     w.resolve(size, end as u16)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(buf, b"\x34\x12\x00\x00\x10\x00hi\x00\x00\x00\x00\x00\x00\x00\x00");
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! write_struct {
+    ($w:expr, { $($fields:tt)* }) => {
+        $crate::write_struct!(@fields $w; $($fields)*);
+    };
+    (@fields $w:expr; ) => {};
+    (@fields $w:expr; $name:ident : align($n:expr) $(, $($rest:tt)*)?) => {
+        $w.align($n)?;
+        $crate::write_struct!(@fields $w; $($($rest)*)?);
+    };
+    (@fields $w:expr; $name:ident : cstr = $v:expr $(, $($rest:tt)*)?) => {
+        $w.write_cstr_padded($v, 1)?;
+        $crate::write_struct!(@fields $w; $($($rest)*)?);
+    };
+    (@fields $w:expr; $name:ident : $t:ty = defer $(, $($rest:tt)*)?) => {
+        let $name = $w.write_deferred(<$t as ::std::default::Default>::default())?;
+        $crate::write_struct!(@fields $w; $($($rest)*)?);
+    };
+    (@fields $w:expr; $name:ident : $t:ty = $v:expr $(, $($rest:tt)*)?) => {
+        $w.write::<$t>($v)?;
+        $crate::write_struct!(@fields $w; $($($rest)*)?);
+    };
+}
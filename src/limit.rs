@@ -0,0 +1,87 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A stream wrapper that refuses writes that would extend a region beyond
+/// a fixed end position, used by
+/// [`Writer::subregion_limited`](crate::Writer::subregion_limited).
+///
+/// Writes that begin before the start of the region are allowed, so that
+/// placeholders written earlier in the stream can still be updated from
+/// inside the region.
+#[derive(Debug)]
+pub struct Limited<W> {
+    inner: W,
+    pos: u64,
+    start: u64,
+    limit: u64,
+}
+
+impl<W: Seek> Limited<W> {
+    /// Wraps the given stream, allowing at most `max_len` bytes to be
+    /// written starting at its current position.
+    pub fn new(mut inner: W, max_len: u64) -> std::io::Result<Self> {
+        let start = inner.stream_position()?;
+        let limit = start
+            .checked_add(max_len)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        Ok(Self {
+            inner,
+            pos: start,
+            start,
+            limit,
+        })
+    }
+
+    /// Returns the number of bytes that can still be written at the current
+    /// position before reaching the limit.
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.pos)
+    }
+
+    /// Consumes the wrapper, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let end = self.pos.saturating_add(buf.len() as u64);
+        if self.pos >= self.start && end > self.limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "write of {} bytes at {:#x} exceeds region limit of {} bytes",
+                    buf.len(),
+                    self.pos,
+                    self.limit - self.start
+                ),
+            ));
+        }
+        let n = self.inner.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for Limited<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<W: Read> Read for Limited<W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
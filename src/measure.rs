@@ -0,0 +1,213 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A stream that discards all data written to it while tracking the
+/// stream position and length, for measuring the layout of some output
+/// without producing it.
+///
+/// Use [`measure_le`](crate::measure_le) or similar to run a writing
+/// function against a `Discard` stream. To measure and then write the same
+/// output, write the function to be generic over the writer's stream
+/// type:
+///
+/// ```
+/// # use std::io::{Result, Seek, Write};
+/// # fn main() -> Result<()> {
+/// use binbin::endian::LittleEndian;
+/// use binbin::Writer;
+///
+/// fn body<W: Write + Seek>(w: &mut Writer<W, LittleEndian>) -> Result<u64> {
+///     w.write(&b"header"[..])?;
+///     w.align(16)?;
+///     w.position()
+/// }
+///
+/// let (len, data_start) = binbin::measure_le(|w| body(w))?;
+/// assert_eq!((len, data_start), (16, 16));
+///
+/// let mut buf = Vec::<u8>::new();
+/// binbin::write_vec_le(&mut buf, |w| body(w))?;
+/// assert_eq!(buf.len() as u64, len);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Reading from a `Discard` stream, as [`Writer::derive`](crate::Writer::derive)
+/// does, produces zeros for any position before the end of the stream.
+#[derive(Default, Debug)]
+pub struct Discard {
+    pos: u64,
+    len: u64,
+}
+
+impl Discard {
+    /// Creates a new, empty stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the length of the data that would have been written.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if no data would have been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Write for Discard {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.len = std::cmp::max(self.len, self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Discard {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::End(v) => self.len.checked_add_signed(v),
+            SeekFrom::Current(v) => self.pos.checked_add_signed(v),
+        };
+        match new_pos {
+            Some(v) => {
+                self.pos = v;
+                Ok(v)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl Read for Discard {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remain = self.len.saturating_sub(self.pos);
+        let n = std::cmp::min(remain, buf.len() as u64) as usize;
+        buf[..n].fill(0);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// A stream that passes all data written to it to an observer function,
+/// such as one that updates a checksum or hash, and then discards it.
+///
+/// This is the same as [`Discard`](Discard) except for the observer, and
+/// is for computing a checksum of some output without keeping the output
+/// in memory. Use [`digest_le`](crate::digest_le) or similar to run a
+/// writing function against a `Digest` stream.
+///
+/// The observer sees the bytes in stream order, so any write that would
+/// change bytes the observer has already seen fails with
+/// [`InvalidInput`](std::io::ErrorKind::InvalidInput), as with
+/// [`Tee`](crate::tee::Tee). Deferred values must therefore either be
+/// resolved before their placeholders are written, or be written inside a
+/// region produced by [`Writer::buffered`](crate::Writer::buffered) that is
+/// emitted once they are resolved. Any gap left by seeking beyond the end
+/// of the data is passed to the observer as zeros.
+pub struct Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    discard: Discard,
+    observer: F,
+    observed: u64,
+}
+
+impl<F> Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    /// Creates a new, empty stream that passes data to the given observer.
+    pub fn new(observer: F) -> Self {
+        Self {
+            discard: Discard::new(),
+            observer,
+            observed: 0,
+        }
+    }
+
+    /// Returns the length of the data that would have been written.
+    pub fn len(&self) -> u64 {
+        self.discard.len()
+    }
+
+    /// Returns true if no data would have been written.
+    pub fn is_empty(&self) -> bool {
+        self.discard.is_empty()
+    }
+
+    /// Discards the stream, returning the observer function.
+    pub fn into_inner(self) -> F {
+        self.observer
+    }
+}
+
+impl<F> Write for Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let pos = self.discard.pos;
+        if pos < self.observed {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "write would change data already passed to the observer",
+            ));
+        }
+        while self.observed < pos {
+            let gap = std::cmp::min(pos - self.observed, 512) as usize;
+            (self.observer)(&[0_u8; 512][..gap]);
+            self.observed += gap as u64;
+        }
+        let n = self.discard.write(buf)?;
+        (self.observer)(&buf[..n]);
+        self.observed += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Seek for Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.discard.seek(pos)
+    }
+}
+
+impl<F> Read for Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    /// Reads produce zeros, as for [`Discard`](Discard), because the data
+    /// isn't retained.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.discard.read(buf)
+    }
+}
+
+impl<F> std::fmt::Debug for Digest<F>
+where
+    F: FnMut(&[u8]),
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Digest")
+            .field("discard", &self.discard)
+            .field("observed", &self.observed)
+            .finish()
+    }
+}
@@ -0,0 +1,56 @@
+use crate::deferred::Deferred;
+
+/// A table of entries describing the positions and sizes of blobs written
+/// elsewhere in the output, as used in directories of archive formats like
+/// WAD, and in font table directories.
+///
+/// Add entries using [`Writer::table_entry`](crate::Writer::table_entry),
+/// write the blobs they describe using
+/// [`Writer::write_table_blob`](crate::Writer::write_table_blob), and write
+/// the table itself using [`Writer::emit_table`](crate::Writer::emit_table),
+/// which lets the caller choose how to encode each entry. The table and the
+/// blobs can be written in either order.
+pub struct OffsetTable<'a> {
+    pub(crate) entries: Vec<TableEntry<'a>>,
+}
+
+/// An entry in an [`OffsetTable`](OffsetTable), whose deferred slots are
+/// resolved once its blob is written.
+#[derive(Copy, Clone)]
+pub struct TableEntry<'a> {
+    /// The position of the start of the blob in the underlying stream.
+    pub offset: Deferred<'a, u64>,
+
+    /// The size of the blob in bytes.
+    pub size: Deferred<'a, u64>,
+}
+
+impl<'a> OffsetTable<'a> {
+    /// Creates a new, empty table.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entries in the table, in the order they were added.
+    pub fn entries(&self) -> &[TableEntry<'a>] {
+        &self.entries[..]
+    }
+}
+
+impl<'a> Default for OffsetTable<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{BigEndian, Endian, LittleEndian};
+use crate::{BigEndian, Endian, LittleEndian, MiddleEndian};
 
 /// Trait implemented by types that can be packed into a sequence of bytes
 /// to be written into a file.
@@ -12,8 +12,38 @@ pub trait Pack {
     /// If given a slice of the wrong length, the behavior is undefined,
     /// including possible panics.
     fn pack_into_slice<E: Endian>(&self, into: &mut [u8]);
+
+    /// Packs the value and writes the result to the given writer, returning
+    /// the number of bytes written.
+    ///
+    /// The default implementation packs values of up to
+    /// [`STACK_PACK_LEN`](STACK_PACK_LEN) bytes into a buffer on the stack,
+    /// so that writing small values doesn't allocate. Types whose packed
+    /// form already exists in memory, such as strings, override this to
+    /// write it directly.
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let l = self.pack_len();
+        if l <= STACK_PACK_LEN {
+            let mut buf = [0_u8; STACK_PACK_LEN];
+            self.pack_into_slice::<E>(&mut buf[..l]);
+            w.write_all(&buf[..l])?;
+        } else {
+            let mut buf = vec![0_u8; l];
+            self.pack_into_slice::<E>(&mut buf[..]);
+            w.write_all(&buf[..])?;
+        }
+        Ok(l)
+    }
 }
 
+/// The largest packed length that the default implementation of
+/// [`Pack::pack_to_writer`](Pack::pack_to_writer) packs on the stack rather
+/// than on the heap.
+pub const STACK_PACK_LEN: usize = 64;
+
 /// Specialization of [`Pack`](Pack) for types where the packed length is
 /// always known at compile time.
 ///
@@ -70,6 +100,19 @@ pub fn as_big_endian<P: Pack + Sized>(v: P) -> EndianOverride<P, BigEndian> {
     }
 }
 
+/// Marks a particular value has being forced as PDP-11 middle-endian when
+/// encoded, and thus ignoring whichever endianness is selected as the
+/// default for a [`Writer`](super::Writer).
+///
+/// Passing an [`EndianOverride`](EndianOverride) to this function will have
+/// no effect on its existing forced endianness.
+pub fn as_middle_endian<P: Pack + Sized>(v: P) -> EndianOverride<P, MiddleEndian> {
+    EndianOverride {
+        v,
+        phantom: std::marker::PhantomData,
+    }
+}
+
 impl Pack for u8 {
     fn pack_len(&self) -> usize {
         <Self as FixedLenPack>::PACK_LEN
@@ -105,7 +148,7 @@ impl Pack for u16 {
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        E::write_integer(*self as u64, &mut buf[0..2])
+        E::write_integer(*self as u128, &mut buf[0..2])
     }
 }
 
@@ -133,7 +176,7 @@ impl Pack for u32 {
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        E::write_integer(*self as u64, &mut buf[0..4])
+        E::write_integer(*self as u128, &mut buf[0..4])
     }
 }
 
@@ -161,7 +204,7 @@ impl Pack for u64 {
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        E::write_integer(*self as u64, &mut buf[0..8])
+        E::write_integer(*self as u128, &mut buf[0..8])
     }
 }
 
@@ -183,6 +226,440 @@ impl FixedLenPack for i64 {
     const PACK_LEN: usize = <u64 as FixedLenPack>::PACK_LEN;
 }
 
+impl Pack for u128 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        E::write_integer(*self, &mut buf[0..16])
+    }
+}
+
+impl FixedLenPack for u128 {
+    const PACK_LEN: usize = std::mem::size_of::<Self>();
+}
+
+impl Pack for i128 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        (*self as u128).pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for i128 {
+    const PACK_LEN: usize = <u128 as FixedLenPack>::PACK_LEN;
+}
+
+/// `f32` values pack as their IEEE 754 binary32 representation.
+impl Pack for f32 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.to_bits().pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for f32 {
+    const PACK_LEN: usize = <u32 as FixedLenPack>::PACK_LEN;
+}
+
+/// `f64` values pack as their IEEE 754 binary64 representation.
+impl Pack for f64 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.to_bits().pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for f64 {
+    const PACK_LEN: usize = <u64 as FixedLenPack>::PACK_LEN;
+}
+
+/// `bool` values pack as a single byte, 1 for `true` or 0 for `false`. Use
+/// [`bool_as`](bool_as) for other conventions.
+impl Pack for bool {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf[0] = *self as u8
+    }
+}
+
+impl FixedLenPack for bool {
+    const PACK_LEN: usize = 1;
+}
+
+/// `char` values pack as their UTF-8 encoding, which is between one and
+/// four bytes long. Use [`CodePoint`](CodePoint) for a fixed-length
+/// encoding.
+impl Pack for char {
+    fn pack_len(&self) -> usize {
+        self.len_utf8()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.encode_utf8(buf);
+    }
+}
+
+/// Wraps a boolean so that it will be packed as `true_value` or
+/// `false_value`, for formats that don't use a single byte of 1 or 0.
+///
+/// For example, `bool_as(v, 1_u32, 0)` packs a 32-bit flag, and
+/// `bool_as(v, 0xff_u8, 0)` packs a byte with all bits set for `true`.
+pub fn bool_as<T: Pack>(v: bool, true_value: T, false_value: T) -> BoolAs<T> {
+    BoolAs {
+        value: v,
+        true_value,
+        false_value,
+    }
+}
+
+/// A boolean packed using arbitrary values for `true` and `false`.
+///
+/// `BoolAs` is the return type of [`bool_as`](bool_as).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BoolAs<T> {
+    value: bool,
+    true_value: T,
+    false_value: T,
+}
+
+impl<T: Pack> Pack for BoolAs<T> {
+    fn pack_len(&self) -> usize {
+        match self.value {
+            true => self.true_value.pack_len(),
+            false => self.false_value.pack_len(),
+        }
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        match self.value {
+            true => self.true_value.pack_into_slice::<E>(buf),
+            false => self.false_value.pack_into_slice::<E>(buf),
+        }
+    }
+}
+
+impl<T: FixedLenPack> FixedLenPack for BoolAs<T> {
+    const PACK_LEN: usize = T::PACK_LEN;
+}
+
+/// A `char` packed as its Unicode code point, in a 32-bit integer using the
+/// writer's endianness, as in UTF-32.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CodePoint(pub char);
+
+impl Pack for CodePoint {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        (self.0 as u32).pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for CodePoint {
+    const PACK_LEN: usize = <u32 as FixedLenPack>::PACK_LEN;
+}
+
+/// IPv4 addresses pack as their four octets in network order, regardless
+/// of the writer's endianness.
+impl Pack for std::net::Ipv4Addr {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.octets())
+    }
+}
+
+impl FixedLenPack for std::net::Ipv4Addr {
+    const PACK_LEN: usize = 4;
+}
+
+/// IPv6 addresses pack as their sixteen octets in network order, regardless
+/// of the writer's endianness.
+impl Pack for std::net::Ipv6Addr {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf[..16].copy_from_slice(&self.octets())
+    }
+}
+
+impl FixedLenPack for std::net::Ipv6Addr {
+    const PACK_LEN: usize = 16;
+}
+
+/// IPv4 socket addresses pack as the address followed by the 16-bit port
+/// number. The port uses the writer's endianness, so use
+/// [`as_big_endian`](as_big_endian) to pack it in network order in a
+/// little-endian writer.
+impl Pack for std::net::SocketAddrV4 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.ip().pack_into_slice::<E>(&mut buf[..4]);
+        self.port().pack_into_slice::<E>(&mut buf[4..6]);
+    }
+}
+
+impl FixedLenPack for std::net::SocketAddrV4 {
+    const PACK_LEN: usize = 6;
+}
+
+/// IPv6 socket addresses pack as the address followed by the 16-bit port
+/// number, like [`SocketAddrV4`](std::net::SocketAddrV4). The flow info and
+/// scope ID are not included.
+impl Pack for std::net::SocketAddrV6 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.ip().pack_into_slice::<E>(&mut buf[..16]);
+        self.port().pack_into_slice::<E>(&mut buf[16..18]);
+    }
+}
+
+impl FixedLenPack for std::net::SocketAddrV6 {
+    const PACK_LEN: usize = 18;
+}
+
+/// A UUID, packed in the RFC 4122 layout where all of the fields are
+/// big-endian, regardless of the writer's endianness.
+///
+/// The wrapped integer is the UUID as it's usually written in text, so
+/// `Uuid(0x00112233_4455_6677_8899_aabbccddeeff)` represents
+/// `00112233-4455-6677-8899-aabbccddeeff`. Use [`Guid`](Guid) for formats
+/// that use the Microsoft layout instead.
+///
+/// This crate doesn't depend on the `uuid` crate, so its `Uuid` type
+/// doesn't implement [`Pack`](Pack) directly. Convert one using
+/// `Uuid(u.as_u128())`, or `Guid(u.as_u128())` for the Microsoft layout.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid(pub u128);
+
+/// A UUID, packed in the Microsoft GUID layout where the first three fields
+/// are little-endian and the remaining eight bytes are in order, regardless
+/// of the writer's endianness. This is the layout used in GPT partition
+/// tables, PE files, and many other Windows-originated formats.
+///
+/// The wrapped integer is the UUID as it's usually written in text, just as
+/// for [`Uuid`](Uuid), so `Guid(0x00112233_4455_6677_8899_aabbccddeeff)`
+/// packs as `33 22 11 00 55 44 77 66 88 99 aa bb cc dd ee ff`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Guid(pub u128);
+
+impl Uuid {
+    /// Returns the UUID's bytes in the RFC 4122 layout.
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl Guid {
+    /// Returns the UUID's bytes in the Microsoft GUID layout.
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut buf = self.0.to_be_bytes();
+        buf[0..4].reverse();
+        buf[4..6].reverse();
+        buf[6..8].reverse();
+        buf
+    }
+}
+
+impl From<Guid> for Uuid {
+    fn from(v: Guid) -> Self {
+        Self(v.0)
+    }
+}
+
+impl From<Uuid> for Guid {
+    fn from(v: Uuid) -> Self {
+        Self(v.0)
+    }
+}
+
+impl Pack for Uuid {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf[..16].copy_from_slice(&self.to_bytes())
+    }
+}
+
+impl FixedLenPack for Uuid {
+    const PACK_LEN: usize = 16;
+}
+
+impl Pack for Guid {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf[..16].copy_from_slice(&self.to_bytes())
+    }
+}
+
+impl FixedLenPack for Guid {
+    const PACK_LEN: usize = 16;
+}
+
+/// A sealed trait implemented by the primitive number types, and wrappers
+/// such as [`F16`](crate::float::F16) around them, whose
+/// in-memory representation is the same as their packed form in the
+/// target platform's byte order.
+///
+/// This allows [`Writer::write_slice`](crate::Writer::write_slice) to write
+/// slices of these types without converting each element when the writer's
+/// byte order matches the target platform's.
+pub trait Primitive: FixedLenPack + Copy + private::Sealed {}
+
+macro_rules! primitive {
+    ($($t:ty),+) => {
+        $(
+            impl Primitive for $t {}
+            impl private::Sealed for $t {}
+        )+
+    };
+}
+
+primitive!(
+    u8,
+    i8,
+    u16,
+    i16,
+    u32,
+    i32,
+    u64,
+    i64,
+    u128,
+    i128,
+    f32,
+    f64,
+    crate::float::F16,
+    crate::float::Bf16
+);
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Returns the in-memory bytes of the given slice of primitive values,
+/// which are in the target platform's byte order.
+pub(crate) fn primitive_bytes<T: Primitive>(vs: &[T]) -> &[u8] {
+    // SAFETY: Primitive is only implemented for the primitive number types
+    // and transparent wrappers around them, which have no padding bytes, and
+    // u8 has no alignment requirement.
+    unsafe { std::slice::from_raw_parts(vs.as_ptr() as *const u8, std::mem::size_of_val(vs)) }
+}
+
+/// Returns the error used by `TryFrom` implementations for the packing
+/// wrapper types in this crate when the value is out of range.
+pub(crate) fn out_of_range_error() -> std::num::TryFromIntError {
+    // TryFromIntError has no public constructor, so we borrow one from a
+    // conversion that is guaranteed to fail.
+    <u8 as std::convert::TryFrom<u64>>::try_from(u64::MAX).unwrap_err()
+}
+
+/// Declares a newtype wrapper for an unsigned integer that packs into fewer
+/// bytes than its underlying Rust integer type.
+macro_rules! odd_width_uint {
+    ($(#[$attr:meta])* $name:ident, $inner:ty, $len:expr) => {
+        $(#[$attr])*
+        ///
+        /// Values too large for the packed width will be truncated to their
+        /// least significant bytes when packed. The
+        /// [`TryFrom<u64>`](std::convert::TryFrom) implementation for this
+        /// type returns an error for such values.
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub $inner);
+
+        impl Pack for $name {
+            fn pack_len(&self) -> usize {
+                <Self as FixedLenPack>::PACK_LEN
+            }
+
+            fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+                E::write_integer(self.0 as u128, &mut buf[0..$len])
+            }
+        }
+
+        impl FixedLenPack for $name {
+            const PACK_LEN: usize = $len;
+        }
+
+        impl crate::unpack::Unpack for $name {
+            const UNPACK_LEN: usize = $len;
+
+            fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+                Self(E::read_integer(&buf[0..$len]) as $inner)
+            }
+        }
+
+        impl std::convert::TryFrom<u64> for $name {
+            type Error = std::num::TryFromIntError;
+
+            fn try_from(v: u64) -> Result<Self, Self::Error> {
+                if (v >> (8 * $len)) != 0 {
+                    return Err(out_of_range_error());
+                }
+                Ok(Self(v as $inner))
+            }
+        }
+    };
+}
+
+odd_width_uint!(
+    /// A 24-bit unsigned integer, packed into three bytes.
+    U24,
+    u32,
+    3
+);
+
+odd_width_uint!(
+    /// A 40-bit unsigned integer, packed into five bytes.
+    U40,
+    u64,
+    5
+);
+
+odd_width_uint!(
+    /// A 48-bit unsigned integer, packed into six bytes.
+    U48,
+    u64,
+    6
+);
+
+odd_width_uint!(
+    /// A 56-bit unsigned integer, packed into seven bytes.
+    U56,
+    u64,
+    7
+);
+
 /// [`CStr`](std::ffi::CStr) values pack as null-terminated strings, with no
 /// additional padding other than the null terminator.
 impl Pack for std::ffi::CStr {
@@ -193,6 +670,15 @@ impl Pack for std::ffi::CStr {
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
         buf.copy_from_slice(self.to_bytes_with_nul());
     }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let bytes = self.to_bytes_with_nul();
+        w.write_all(bytes)?;
+        Ok(bytes.len())
+    }
 }
 
 /// [`CStr`](std::ffi::CStr) values pack as null-terminated strings, with no
@@ -205,23 +691,97 @@ impl Pack for &std::ffi::CStr {
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
         buf.copy_from_slice(self.to_bytes_with_nul());
     }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let bytes = self.to_bytes_with_nul();
+        w.write_all(bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+/// `str` values pack as their UTF-8 bytes, with no length prefix or
+/// terminator. Use [`CStr`](std::ffi::CStr) for null-terminated strings.
+impl Pack for str {
+    fn pack_len(&self) -> usize {
+        self.len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self.as_bytes());
+    }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let bytes = self.as_bytes();
+        w.write_all(bytes)?;
+        Ok(bytes.len())
+    }
 }
 
-/// `[T]` values pack by packing each element of the array in sequence.
+/// `&str` values pack as their UTF-8 bytes, with no length prefix or
+/// terminator. Use [`CStr`](std::ffi::CStr) for null-terminated strings.
+impl Pack for &str {
+    fn pack_len(&self) -> usize {
+        self.len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self.as_bytes());
+    }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let bytes = self.as_bytes();
+        w.write_all(bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+/// `String` values pack as their UTF-8 bytes, with no length prefix or
+/// terminator. Use [`CStr`](std::ffi::CStr) for null-terminated strings.
+impl Pack for String {
+    fn pack_len(&self) -> usize {
+        self.len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self.as_bytes());
+    }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        let bytes = self.as_bytes();
+        w.write_all(bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+/// `[T]` values pack by packing each element of the array in sequence,
+/// with each element using the same endianness.
 impl<T> Pack for [T]
 where
-    T: FixedLenPack,
+    T: Pack,
 {
     fn pack_len(&self) -> usize {
-        self.len() * <T as FixedLenPack>::PACK_LEN
+        self.iter().map(|v| v.pack_len()).sum()
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        let elem_len = <T as FixedLenPack>::PACK_LEN;
-        for (i, v) in self.iter().enumerate() {
-            let idx = elem_len * i;
+        let mut idx = 0;
+        for v in self.iter() {
+            let elem_len = v.pack_len();
             let s = &mut buf[idx..idx + elem_len];
             v.pack_into_slice::<E>(s);
+            idx += elem_len;
         }
     }
 }
@@ -229,52 +789,106 @@ where
 /// `&[T]` values pack by packing each element of the slice in sequence.
 impl<T> Pack for &[T]
 where
-    T: FixedLenPack,
+    T: Pack,
 {
     fn pack_len(&self) -> usize {
-        self.len() * <T as FixedLenPack>::PACK_LEN
+        (**self).pack_len()
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        let elem_len = <T as FixedLenPack>::PACK_LEN;
-        for (i, v) in self.iter().enumerate() {
-            let idx = elem_len * i;
-            let s = &mut buf[idx..idx + elem_len];
-            v.pack_into_slice::<E>(s);
-        }
+        (**self).pack_into_slice::<E>(buf)
     }
 }
 
-impl<T1, T2> Pack for (T1, T2)
+/// `[T; N]` values pack by packing each element of the array in sequence.
+impl<T, const N: usize> Pack for [T; N]
 where
-    T1: Pack,
-    T2: Pack,
+    T: Pack,
 {
     fn pack_len(&self) -> usize {
-        self.0.pack_len() + self.1.pack_len()
+        self[..].pack_len()
     }
 
     fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
-        self.0.pack_into_slice::<E>(&mut buf[..self.0.pack_len()]);
-        self.1.pack_into_slice::<E>(&mut buf[self.0.pack_len()..]);
+        self[..].pack_into_slice::<E>(buf)
     }
 }
 
-impl<T1, T2> FixedLenPack for (T1, T2)
+impl<T, const N: usize> FixedLenPack for [T; N]
 where
-    T1: FixedLenPack,
-    T2: FixedLenPack,
+    T: FixedLenPack,
+{
+    const PACK_LEN: usize = N * T::PACK_LEN;
+}
+
+/// `Vec<T>` values pack by packing each element of the vector in sequence.
+impl<T> Pack for Vec<T>
+where
+    T: Pack,
 {
-    const PACK_LEN: usize = T1::PACK_LEN + T2::PACK_LEN;
+    fn pack_len(&self) -> usize {
+        self[..].pack_len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self[..].pack_into_slice::<E>(buf)
+    }
 }
 
+/// Implements [`Pack`](Pack) for a tuple type, packing each element in
+/// order, and [`FixedLenPack`](FixedLenPack) when all of the elements have
+/// fixed lengths.
+macro_rules! tuple_pack {
+    ($($t:ident . $idx:tt),+) => {
+        impl<$($t),+> Pack for ($($t,)+)
+        where
+            $($t: Pack,)+
+        {
+            fn pack_len(&self) -> usize {
+                0 $(+ self.$idx.pack_len())+
+            }
+
+            #[allow(unused_assignments)]
+            fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+                let mut buf = buf;
+                $(
+                    let (elem, rest) = buf.split_at_mut(self.$idx.pack_len());
+                    self.$idx.pack_into_slice::<E>(elem);
+                    buf = rest;
+                )+
+            }
+        }
+
+        impl<$($t),+> FixedLenPack for ($($t,)+)
+        where
+            $($t: FixedLenPack,)+
+        {
+            const PACK_LEN: usize = 0 $(+ $t::PACK_LEN)+;
+        }
+    };
+}
+
+tuple_pack!(T1.0);
+tuple_pack!(T1.0, T2.1);
+tuple_pack!(T1.0, T2.1, T3.2);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6, T8.7);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6, T8.7, T9.8);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6, T8.7, T9.8, T10.9);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6, T8.7, T9.8, T10.9, T11.10);
+tuple_pack!(T1.0, T2.1, T3.2, T4.3, T5.4, T6.5, T7.6, T8.7, T9.8, T10.9, T11.10, T12.11);
+
 /// A special [`Pack`](Pack) implementation that forces a particular
 /// endianness for some other wrapped value, regardless of the endianness
 /// selected for the writer these values are passed to.
 ///
-/// `EndianOverride` is the return type for both
-/// [`as_little_endian`](as_little_endian) and
-/// [`as_big_endian`](as_big_endian).
+/// `EndianOverride` is the return type for each of
+/// [`as_little_endian`](as_little_endian),
+/// [`as_big_endian`](as_big_endian), and
+/// [`as_middle_endian`](as_middle_endian).
 pub struct EndianOverride<T: Pack, E: Endian> {
     v: T,
     phantom: std::marker::PhantomData<E>,
@@ -291,12 +905,446 @@ impl<T: Pack, E: Endian> Pack for EndianOverride<T, E> {
     fn pack_into_slice<Ignored: Endian>(&self, buf: &mut [u8]) {
         self.v.pack_into_slice::<E>(buf)
     }
+
+    fn pack_to_writer<Ignored: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        self.v.pack_to_writer::<E, W>(w)
+    }
 }
 
 impl<T: FixedLenPack, E: Endian> FixedLenPack for EndianOverride<T, E> {
     const PACK_LEN: usize = T::PACK_LEN;
 }
 
+/// Wraps a value so that it will be packed with a prefix giving its length,
+/// encoded as type `L`, as with "Pascal-style" strings.
+///
+/// By default the length counts the bytes in the packed value. Use
+/// [`LenPrefixed::counting_units`](LenPrefixed::counting_units) to count
+/// larger units instead, and
+/// [`LenPrefixed::with_terminator`](LenPrefixed::with_terminator) to
+/// also write a null terminator after the value.
+///
+/// Returns an error of kind
+/// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the length cannot
+/// be represented as an `L`.
+pub fn len_prefixed<L, P>(v: P) -> crate::Result<LenPrefixed<L, P>>
+where
+    L: Pack + std::convert::TryFrom<u64>,
+    P: Pack,
+{
+    let prefix = len_prefix(&v, 1, None)?;
+    Ok(LenPrefixed {
+        v,
+        prefix,
+        unit_len: 1,
+        terminator: None,
+    })
+}
+
+/// A [`Pack`](Pack) implementation that writes a length prefix before
+/// another wrapped value.
+///
+/// `LenPrefixed` is the return type of [`len_prefixed`](len_prefixed).
+pub struct LenPrefixed<L, P> {
+    v: P,
+    prefix: L,
+    unit_len: usize,
+    terminator: Option<bool>,
+}
+
+impl<L, P> LenPrefixed<L, P>
+where
+    L: Pack + std::convert::TryFrom<u64>,
+    P: Pack,
+{
+    /// Counts the length in units of `unit_len` bytes, rather than in
+    /// individual bytes. For example, a UTF-16 string's length is often
+    /// given in two-byte code units.
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if `unit_len` is
+    /// zero or the new length cannot be represented as an `L`.
+    pub fn counting_units(self, unit_len: usize) -> crate::Result<Self> {
+        if unit_len == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        let prefix = len_prefix(&self.v, unit_len, self.terminator)?;
+        Ok(Self {
+            prefix,
+            unit_len,
+            ..self
+        })
+    }
+
+    /// Writes a null terminator, one unit long, after the value. If
+    /// `counted` is true then the terminator is included in the length.
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if the new length
+    /// cannot be represented as an `L`.
+    pub fn with_terminator(self, counted: bool) -> crate::Result<Self> {
+        let prefix = len_prefix(&self.v, self.unit_len, Some(counted))?;
+        Ok(Self {
+            prefix,
+            terminator: Some(counted),
+            ..self
+        })
+    }
+
+    fn terminator_len(&self) -> usize {
+        match self.terminator {
+            Some(_) => self.unit_len,
+            None => 0,
+        }
+    }
+}
+
+fn len_prefix<L, P>(v: &P, unit_len: usize, terminator: Option<bool>) -> crate::Result<L>
+where
+    L: std::convert::TryFrom<u64>,
+    P: Pack,
+{
+    let mut len = v.pack_len() / unit_len;
+    if let Some(true) = terminator {
+        len += 1;
+    }
+    L::try_from(len as u64).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("length {} is too large for the length prefix", len),
+        )
+    })
+}
+
+impl<L, P> Pack for LenPrefixed<L, P>
+where
+    L: Pack + std::convert::TryFrom<u64>,
+    P: Pack,
+{
+    fn pack_len(&self) -> usize {
+        self.prefix.pack_len() + self.v.pack_len() + self.terminator_len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let (prefix_buf, buf) = buf.split_at_mut(self.prefix.pack_len());
+        let (v_buf, term_buf) = buf.split_at_mut(self.v.pack_len());
+        self.prefix.pack_into_slice::<E>(prefix_buf);
+        self.v.pack_into_slice::<E>(v_buf);
+        for b in term_buf {
+            *b = 0;
+        }
+    }
+}
+
+/// Creates a fixed-length string field of `N` bytes containing the given
+/// string, padded with zero bytes.
+///
+/// Returns an error if the string is longer than `N` bytes. Use
+/// [`fixed_str_truncated`](fixed_str_truncated) to instead discard any
+/// excess bytes.
+pub fn fixed_str<const N: usize>(s: impl AsRef<[u8]>) -> crate::Result<FixedStr<N>> {
+    let s = s.as_ref();
+    if s.len() > N {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("string of {} bytes does not fit in {} bytes", s.len(), N),
+        ));
+    }
+    Ok(fixed_str_truncated(s))
+}
+
+/// Creates a fixed-length string field of `N` bytes containing the given
+/// string, padded with zero bytes and truncated if it's longer than `N`
+/// bytes.
+pub fn fixed_str_truncated<const N: usize>(s: impl AsRef<[u8]>) -> FixedStr<N> {
+    let s = s.as_ref();
+    let len = std::cmp::min(s.len(), N);
+    let mut buf = [0_u8; N];
+    buf[..len].copy_from_slice(&s[..len]);
+    FixedStr { buf, len, pad: 0 }
+}
+
+/// A string packed into a field of exactly `N` bytes, with any remaining
+/// space filled with a padding byte.
+///
+/// Because its length is fixed, `FixedStr` can be used for deferred slots.
+/// Create values of this type using [`fixed_str`](fixed_str) or
+/// [`fixed_str_truncated`](fixed_str_truncated).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FixedStr<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    pad: u8,
+}
+
+impl<const N: usize> FixedStr<N> {
+    /// Changes the byte used to fill the unused part of the field, which
+    /// defaults to zero.
+    pub fn with_padding(self, pad: u8) -> Self {
+        Self { pad, ..self }
+    }
+
+    /// Returns the string content of the field, excluding padding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> Pack for FixedStr<N> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let (content, padding) = buf.split_at_mut(self.len);
+        content.copy_from_slice(self.as_bytes());
+        for b in padding {
+            *b = self.pad;
+        }
+    }
+}
+
+impl<const N: usize> FixedLenPack for FixedStr<N> {
+    const PACK_LEN: usize = N;
+}
+
+/// Unpacking a `FixedStr` treats any trailing zero bytes as padding.
+impl<const N: usize> crate::unpack::Unpack for FixedStr<N> {
+    const UNPACK_LEN: usize = N;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let len = buf.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        fixed_str_truncated(&buf[..len])
+    }
+}
+
+/// Wraps a string so that it will be packed as UTF-16 code units, using the
+/// writer's endianness.
+///
+/// Use [`as_little_endian`](as_little_endian) or
+/// [`as_big_endian`](as_big_endian) to force a particular endianness
+/// regardless of the writer's default.
+pub fn utf16(s: &str) -> Utf16Str {
+    Utf16Str {
+        units: s.encode_utf16().collect(),
+        bom: false,
+        terminator: false,
+        fixed_len: None,
+    }
+}
+
+/// A string packed as a sequence of UTF-16 code units.
+///
+/// `Utf16Str` is the return type of [`utf16`](utf16).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Utf16Str {
+    units: Vec<u16>,
+    bom: bool,
+    terminator: bool,
+    fixed_len: Option<usize>,
+}
+
+impl Utf16Str {
+    /// Writes a byte order mark before the string, which allows a reader
+    /// to detect which endianness was used.
+    pub fn with_bom(self) -> Self {
+        Self { bom: true, ..self }
+    }
+
+    /// Writes a null code unit after the string.
+    pub fn with_terminator(self) -> Self {
+        Self {
+            terminator: true,
+            ..self
+        }
+    }
+
+    /// Makes the packed string exactly `units` code units long, including
+    /// any byte order mark and terminator, by truncating the string if it's
+    /// too long or padding with null code units if it's too short.
+    pub fn with_fixed_len(self, units: usize) -> Self {
+        Self {
+            fixed_len: Some(units),
+            ..self
+        }
+    }
+
+    fn packed_units(&self) -> Vec<u16> {
+        let mut ret = Vec::with_capacity(self.units.len() + 2);
+        if self.bom {
+            ret.push(0xfeff);
+        }
+        ret.extend_from_slice(&self.units[..]);
+        if let Some(len) = self.fixed_len {
+            // If there's a terminator then it must survive truncation.
+            let content_len = if self.terminator {
+                len.saturating_sub(1)
+            } else {
+                len
+            };
+            ret.truncate(content_len);
+            ret.resize(len, 0);
+        } else if self.terminator {
+            ret.push(0);
+        }
+        ret
+    }
+}
+
+impl Pack for Utf16Str {
+    fn pack_len(&self) -> usize {
+        self.packed_units().len() * <u16 as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.packed_units()[..].pack_into_slice::<E>(buf)
+    }
+}
+
+/// A sub-structure built in advance with the [`Writer`](crate::Writer)
+/// API, which packs as the bytes that were written.
+///
+/// This allows reusable parts of a format, such as a header, to be built by
+/// functions that return values rather than only by functions that write
+/// through a given writer. Any deferred values used while building must be
+/// resolved before the build function returns, and positions within the
+/// builder are relative to the start of the `Packed` value rather than to
+/// wherever it is eventually written.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// use binbin::pack::Packed;
+///
+/// let header = Packed::build_le(|w| {
+///     let len = w.write_deferred(0_u16)?;
+///     w.write(&b"HDR"[..])?;
+///     w.resolve(len, 3)?;
+///     Ok(())
+/// })?;
+///
+/// let mut buf = Vec::<u8>::new();
+/// binbin::write_vec_be(&mut buf, |w| {
+///     w.write(1_u16)?;
+///     w.write(&header)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(buf, [0x00, 0x01, 0x03, 0x00, b'H', b'D', b'R']);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Packed {
+    buf: Vec<u8>,
+}
+
+impl Packed {
+    /// Builds a value using the given function, where writes will be
+    /// little-endian by default.
+    pub fn build_le<F>(f: F) -> std::io::Result<Self>
+    where
+        for<'w> F: FnOnce(
+            &mut crate::Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, LittleEndian>,
+        ) -> std::io::Result<()>,
+    {
+        Self::build::<LittleEndian, F>(f)
+    }
+
+    /// Builds a value using the given function, where writes will be
+    /// big-endian by default.
+    pub fn build_be<F>(f: F) -> std::io::Result<Self>
+    where
+        for<'w> F: FnOnce(
+            &mut crate::Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, BigEndian>,
+        ) -> std::io::Result<()>,
+    {
+        Self::build::<BigEndian, F>(f)
+    }
+
+    /// Generic equivalent of [`build_le`](Self::build_le) and
+    /// [`build_be`](Self::build_be), with the default endianness selected by
+    /// a type parameter.
+    pub fn build<E, F>(f: F) -> std::io::Result<Self>
+    where
+        E: Endian,
+        for<'w> F: FnOnce(
+            &mut crate::Writer<'w, &mut std::io::Cursor<&mut Vec<u8>>, E>,
+        ) -> std::io::Result<()>,
+    {
+        let mut buf = Vec::new();
+        crate::write_vec::<_, E, _>(&mut buf, f)?;
+        Ok(Self { buf })
+    }
+
+    /// Returns the bytes that were written.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns the bytes that were written, consuming the value.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Returns the number of bytes that were written.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns true if nothing was written.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+impl From<Packed> for Vec<u8> {
+    fn from(v: Packed) -> Self {
+        v.buf
+    }
+}
+
+/// `Packed` values pack as the bytes that were written when building them,
+/// regardless of the writer's endianness.
+impl Pack for Packed {
+    fn pack_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.buf);
+    }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        w.write_all(&self.buf)?;
+        Ok(self.buf.len())
+    }
+}
+
+/// `&Packed` values pack in the same way as [`Packed`](Packed), so that the
+/// same value can be written more than once.
+impl Pack for &Packed {
+    fn pack_len(&self) -> usize {
+        (**self).pack_len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        (**self).pack_into_slice::<E>(buf)
+    }
+
+    fn pack_to_writer<E: Endian, W: std::io::Write + ?Sized>(
+        &self,
+        w: &mut W,
+    ) -> std::io::Result<usize> {
+        (**self).pack_to_writer::<E, W>(w)
+    }
+}
+
 /// A trait implemented by types that can convert to types that implement
 /// [`Pack`](Pack).
 pub trait IntoPack {
@@ -317,3 +1365,111 @@ impl<P: Pack> IntoPack for P {
         self
     }
 }
+
+/// A trait implemented by field-like enums whose variants each correspond
+/// to a value of an integer type, such as a compression method or record
+/// type code.
+///
+/// Use the [`pack_enum!`](crate::pack_enum) macro to declare an enum that
+/// implements this trait along with [`Pack`](Pack) and
+/// [`FixedLenPack`](FixedLenPack), so that its variants can be written
+/// directly.
+pub trait PackEnum: Sized {
+    /// The integer type that the enum packs as.
+    type Repr: FixedLenPack;
+
+    /// Returns the value corresponding to the variant.
+    fn to_repr(&self) -> Self::Repr;
+
+    /// Returns the variant corresponding to the given value, if any.
+    fn from_repr(v: Self::Repr) -> Option<Self>;
+}
+
+/// Declares an enum whose variants each pack as a particular value of an
+/// integer type, implementing [`PackEnum`](crate::pack::PackEnum),
+/// [`Pack`](crate::pack::Pack), and
+/// [`FixedLenPack`](crate::pack::FixedLenPack) for it.
+///
+/// The integer type follows the enum's name, and becomes the enum's `repr`
+/// type. Every variant must have an explicit discriminant, and because the
+/// discriminants are the packed values the compiler checks that they are
+/// distinct and fit in the integer type.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// binbin::pack_enum! {
+///     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+///     pub enum CompressionMethod: u16 {
+///         Stored = 0,
+///         Deflate = 8,
+///     }
+/// }
+///
+/// let mut buf = Vec::<u8>::new();
+/// binbin::write_vec_le(&mut buf, |w| {
+///     w.write(CompressionMethod::Deflate)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(buf, [0x08, 0x00]);
+///
+/// use binbin::pack::PackEnum;
+/// assert_eq!(CompressionMethod::from_repr(8), Some(CompressionMethod::Deflate));
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! pack_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident : $repr:ident {
+            $(
+                $(#[$vmeta:meta])*
+                $variant:ident = $val:expr
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr($repr)]
+        $vis enum $name {
+            $(
+                $(#[$vmeta])*
+                $variant = $val,
+            )+
+        }
+
+        impl $crate::pack::PackEnum for $name {
+            type Repr = $repr;
+
+            fn to_repr(&self) -> $repr {
+                match self {
+                    $(Self::$variant => $val,)+
+                }
+            }
+
+            fn from_repr(v: $repr) -> ::std::option::Option<Self> {
+                $(
+                    if v == $val {
+                        return ::std::option::Option::Some(Self::$variant);
+                    }
+                )+
+                ::std::option::Option::None
+            }
+        }
+
+        impl $crate::pack::Pack for $name {
+            fn pack_len(&self) -> usize {
+                <$repr as $crate::pack::FixedLenPack>::PACK_LEN
+            }
+
+            fn pack_into_slice<E: $crate::endian::Endian>(&self, buf: &mut [u8]) {
+                let v = $crate::pack::PackEnum::to_repr(self);
+                $crate::pack::Pack::pack_into_slice::<E>(&v, buf)
+            }
+        }
+
+        impl $crate::pack::FixedLenPack for $name {
+            const PACK_LEN: usize = <$repr as $crate::pack::FixedLenPack>::PACK_LEN;
+        }
+    };
+}
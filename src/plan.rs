@@ -0,0 +1,208 @@
+use crate::endian::{ByteOrder, Endianness};
+use crate::Writer;
+use std::io::{Cursor, Result};
+
+/// The function type for sections added to a [`Planner`](Planner).
+pub type SectionFn<'f, E> = Box<
+    dyn for<'w, 'c, 'v> FnMut(
+            &mut Writer<'w, &'c mut Cursor<&'v mut Vec<u8>>, E>,
+            &Plan,
+        ) -> Result<()>
+        + 'f,
+>;
+
+/// Plans the layout of a sequence of sections whose content depends on each
+/// other's positions and sizes, such as headers that record the offsets of
+/// later sections whose sizes in turn depend on the headers.
+///
+/// Each section is produced by a function that receives the
+/// [`Plan`](Plan) from the previous attempt, and the planner repeatedly
+/// runs all of the functions until their sizes stop changing. The first
+/// attempt uses a plan where all sections are empty. Once the plan is
+/// stable, [`Writer::write_planned`](crate::Writer::write_planned) writes
+/// the sections' final content.
+///
+/// Sections appear in the output in the order they were added, except that
+/// [`after`](Self::after) can require a section to follow another one
+/// regardless of the order they were added in.
+pub struct Planner<'f, E: ByteOrder> {
+    sections: Vec<(String, SectionFn<'f, E>)>,
+    after: Vec<(String, String)>,
+    max_passes: usize,
+}
+
+impl<'f, E: ByteOrder> Planner<'f, E> {
+    /// Creates a planner with no sections.
+    pub fn new() -> Self {
+        Self {
+            sections: Vec::new(),
+            after: Vec::new(),
+            max_passes: 16,
+        }
+    }
+
+    /// Adds a section with the given name, produced by the given function.
+    ///
+    /// Positions reported by the writer given to the function are relative
+    /// to the start of the section. Use the given [`Plan`](Plan) to find
+    /// the positions of sections in the final output.
+    pub fn section<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: for<'w, 'c, 'v> FnMut(
+                &mut Writer<'w, &'c mut Cursor<&'v mut Vec<u8>>, E>,
+                &Plan,
+            ) -> Result<()>
+            + 'f,
+    {
+        self.sections.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    /// Requires the section named `name` to appear somewhere after the
+    /// section named `dependency` in the output.
+    pub fn after(&mut self, name: &str, dependency: &str) -> &mut Self {
+        self.after.push((name.to_string(), dependency.to_string()));
+        self
+    }
+
+    /// Changes the maximum number of times the planner will run the section
+    /// functions before giving up on finding a stable plan. The default
+    /// is 16.
+    pub fn max_passes(&mut self, n: usize) -> &mut Self {
+        self.max_passes = n;
+        self
+    }
+
+    /// Returns the indices of the sections in their output order, or an
+    /// error if the ordering constraints are unsatisfiable.
+    fn order(&self) -> Result<Vec<usize>> {
+        let idx_of = |name: &str| {
+            self.sections
+                .iter()
+                .position(|(n, _)| n == name)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("no section named {:?}", name),
+                    )
+                })
+        };
+        let mut deps = vec![Vec::new(); self.sections.len()];
+        for (name, dependency) in &self.after {
+            deps[idx_of(name)?].push(idx_of(dependency)?);
+        }
+
+        // We repeatedly take the first section in declaration order whose
+        // dependencies are all already placed, so that the result is as
+        // close as possible to declaration order.
+        let mut ret: Vec<usize> = Vec::with_capacity(self.sections.len());
+        let mut placed = vec![false; self.sections.len()];
+        while ret.len() < self.sections.len() {
+            let next = (0..self.sections.len())
+                .find(|i| !placed[*i] && deps[*i].iter().all(|d| placed[*d]))
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "section ordering constraints form a cycle",
+                    )
+                })?;
+            placed[next] = true;
+            ret.push(next);
+        }
+        Ok(ret)
+    }
+
+    /// Runs the section functions until their sizes are stable, returning
+    /// the final plan and the content of each section in output order.
+    pub(crate) fn run(
+        &mut self,
+        base: u64,
+        endianness: Endianness,
+    ) -> Result<(Plan, Vec<Vec<u8>>)> {
+        let order = self.order()?;
+        let mut plan = Plan {
+            sections: order
+                .iter()
+                .map(|i| (self.sections[*i].0.clone(), base..base))
+                .collect(),
+        };
+        for _ in 0..self.max_passes {
+            let mut bufs = Vec::with_capacity(order.len());
+            for i in &order {
+                let mut buf = Vec::new();
+                let mut cursor = Cursor::new(&mut buf);
+                let mut wr = Writer::new_with_endianness(&mut cursor, endianness);
+                (self.sections[*i].1)(&mut wr, &plan)?;
+                wr.finalize()?;
+                bufs.push(buf);
+            }
+
+            let mut pos = base;
+            let next = Plan {
+                sections: order
+                    .iter()
+                    .zip(bufs.iter())
+                    .map(|(i, buf)| {
+                        let start = pos;
+                        pos += buf.len() as u64;
+                        (self.sections[*i].0.clone(), start..pos)
+                    })
+                    .collect(),
+            };
+            if next == plan {
+                return Ok((plan, bufs));
+            }
+            plan = next;
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "section layout did not stabilize after {} passes",
+                self.max_passes
+            ),
+        ))
+    }
+}
+
+impl<'f, E: ByteOrder> Default for Planner<'f, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The positions of the sections in a [`Planner`](Planner), as given to
+/// each section's function and returned by
+/// [`Writer::write_planned`](crate::Writer::write_planned).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    sections: Vec<(String, std::ops::Range<u64>)>,
+}
+
+impl Plan {
+    /// Returns the range the named section occupies in the output, or an
+    /// error if there is no such section.
+    pub fn range(&self, name: &str) -> Result<std::ops::Range<u64>> {
+        self.sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, rng)| rng.clone())
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("no section named {:?}", name),
+                )
+            })
+    }
+
+    /// Returns the position of the start of the named section in the
+    /// output, or an error if there is no such section.
+    pub fn offset(&self, name: &str) -> Result<u64> {
+        self.range(name).map(|rng| rng.start)
+    }
+
+    /// Returns the size of the named section, or an error if there is no
+    /// such section.
+    pub fn size(&self, name: &str) -> Result<u64> {
+        self.range(name).map(|rng| rng.end - rng.start)
+    }
+}
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A shared observer selected using
+/// [`Writer::set_progress`](crate::Writer::set_progress), which can be
+/// handed to each of the streams of a writer and its nested writers so that
+/// they all count towards the same total.
+#[derive(Clone)]
+pub(crate) struct Progress {
+    state: Rc<RefCell<ProgressState>>,
+}
+
+struct ProgressState {
+    callback: ProgressFn,
+    granularity: u64,
+
+    /// The total number of bytes written so far.
+    written: u64,
+
+    /// The value of `written` when the callback was last called.
+    reported: u64,
+
+    /// The name of the innermost context or annotated region, if any.
+    annotation: Option<String>,
+}
+
+type ProgressFn = Box<dyn FnMut(u64, Option<&str>)>;
+
+impl Progress {
+    pub(crate) fn new<F>(granularity: u64, callback: F) -> Self
+    where
+        F: FnMut(u64, Option<&str>) + 'static,
+    {
+        Self {
+            state: Rc::new(RefCell::new(ProgressState {
+                callback: Box::new(callback),
+                granularity: granularity.max(1),
+                written: 0,
+                reported: 0,
+                annotation: None,
+            })),
+        }
+    }
+
+    /// Counts `n` more bytes as written, calling the callback if at least
+    /// the granularity has been written since it was last called.
+    pub(crate) fn wrote(&self, n: usize) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.written += n as u64;
+        if state.written - state.reported >= state.granularity {
+            state.reported = state.written;
+            (state.callback)(state.written, state.annotation.as_deref());
+        }
+    }
+
+    pub(crate) fn set_annotation(&self, annotation: Option<&str>) {
+        self.state.borrow_mut().annotation = annotation.map(str::to_string);
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Progress")
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::{Read, Result, Seek, SeekFrom};
+
+use crate::endian::{ByteOrder, Endianness};
+use crate::unpack::Unpack;
+
+/// Wraps a seekable reader with extra functions to conveniently read data
+/// in various common binary formats, including following offsets to other
+/// parts of the stream.
+///
+/// This is the reading counterpart of [`Writer`](crate::Writer). Just as
+/// with a writer, each reader has an endianness as part of its type, which
+/// dictates how it will decode multi-byte values.
+///
+/// A reader may be constrained to a particular subregion of the underlying
+/// stream using [`subregion`](Self::subregion), in which case any attempt
+/// to read beyond the end of that subregion will fail with an error of kind
+/// [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof).
+pub struct Reader<R, E>
+where
+    R: Read + Seek,
+    E: ByteOrder,
+{
+    r: R,
+    limit: Option<u64>,
+    endianness: Endianness,
+    _phantom: std::marker::PhantomData<E>,
+}
+
+impl<R, E> Reader<R, E>
+where
+    R: Read + Seek,
+    E: ByteOrder,
+{
+    pub(crate) fn new(r: R) -> Self {
+        Self::new_with_endianness(r, Endianness::select::<E>(Endianness::Little))
+    }
+
+    pub(crate) fn new_with_endianness(r: R, endianness: Endianness) -> Self {
+        Self {
+            r,
+            limit: None,
+            endianness,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns the byte order the reader uses for multi-byte values.
+    pub fn endianness(&self) -> Endianness {
+        Endianness::select::<E>(self.endianness)
+    }
+
+    /// Returns the current read position in the underlying reader.
+    pub fn position(&mut self) -> Result<u64> {
+        self.r.stream_position()
+    }
+
+    /// Reads a value from the current position in the input.
+    ///
+    /// `read` can produce any value that implements
+    /// [`Unpack`](crate::unpack::Unpack), decoding it using the reader's
+    /// endianness.
+    pub fn read<T: Unpack>(&mut self) -> Result<T> {
+        let mut buf = vec![0_u8; T::UNPACK_LEN];
+        self.read_exact(&mut buf[..])?;
+        Ok(self.endianness().unpack_from_slice(&buf[..]))
+    }
+
+    /// Reads the given number of raw bytes from the current position in the
+    /// input.
+    pub fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0_u8; count];
+        self.read_exact(&mut buf[..])?;
+        Ok(buf)
+    }
+
+    /// Reads a null-terminated string from the current position in the
+    /// input, consuming the null terminator.
+    ///
+    /// This is the reading counterpart of writing a
+    /// [`CStr`](std::ffi::CStr).
+    pub fn read_cstr(&mut self) -> Result<std::ffi::CString> {
+        let mut buf = Vec::new();
+        loop {
+            let b: u8 = self.read()?;
+            if b == 0 {
+                break;
+            }
+            buf.push(b);
+        }
+        // We've already excluded the null terminator, so this cannot fail.
+        Ok(std::ffi::CString::new(buf).unwrap())
+    }
+
+    /// Moves the current position forward by the given number of bytes
+    /// without decoding them.
+    pub fn skip(&mut self, count: usize) -> Result<usize> {
+        self.check_available(count as u64)?;
+        self.r.seek(SeekFrom::Current(count as i64))?;
+        Ok(count)
+    }
+
+    /// Moves the current position forward to a position aligned to the given
+    /// number of bytes, returning the number of bytes skipped.
+    ///
+    /// Returns an error of kind
+    /// [`InvalidInput`](std::io::ErrorKind::InvalidInput) if `n` is zero.
+    pub fn align(&mut self, n: usize) -> Result<usize> {
+        if n == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        let pos = self.position()?;
+        let ofs = pos % (n as u64);
+        if ofs == 0 {
+            return Ok(0);
+        }
+        let inc = ((n as u64) - ofs) as usize;
+        self.skip(inc)
+    }
+
+    /// Follows an offset found elsewhere in the input, calling the given
+    /// function with the reader temporarily positioned at `offset`.
+    ///
+    /// Once the function returns, the reader returns to the position it
+    /// had before `at` was called.
+    pub fn at<F, T>(&mut self, offset: u64, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let reset_pos = self.position()?;
+        self.r.seek(SeekFrom::Start(offset))?;
+        let ret = f(self);
+        self.r.seek(SeekFrom::Start(reset_pos))?;
+        ret
+    }
+
+    /// Parses a bounded region of the input, calling the given function
+    /// with the reader temporarily positioned at the start of `rng` and
+    /// constrained so that it cannot read beyond the end of `rng`.
+    ///
+    /// Once the function returns, the reader returns to the position it
+    /// had before `subregion` was called.
+    pub fn subregion<F, T>(&mut self, rng: std::ops::Range<u64>, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if rng.end < rng.start {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+        let limit = match self.limit {
+            Some(limit) => std::cmp::min(limit, rng.end),
+            None => rng.end,
+        };
+        let reset_limit = self.limit.replace(limit);
+        let ret = self.at(rng.start, f);
+        self.limit = reset_limit;
+        ret
+    }
+
+    fn check_available(&mut self, count: u64) -> Result<()> {
+        if let Some(limit) = self.limit {
+            let pos = self.position()?;
+            if pos + count > limit {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R, E> Read for Reader<R, E>
+where
+    R: Read + Seek,
+    E: ByteOrder,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = match self.limit {
+            Some(limit) => {
+                let pos = self.position()?;
+                let remain = limit.saturating_sub(pos);
+                std::cmp::min(remain, buf.len() as u64) as usize
+            }
+            None => buf.len(),
+        };
+        self.r.read(&mut buf[..len])
+    }
+}
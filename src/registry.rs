@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// A record of the offsets of named symbols, which can be saved and then
+/// reloaded in a later session to refer to data written earlier.
+///
+/// This is for formats that are built incrementally over multiple runs of
+/// a program, such as logs where each appended record refers to earlier
+/// ones. Use [`Writer::export_symbols`](crate::Writer::export_symbols) to
+/// collect the offsets of the symbols placed in one session, and
+/// [`Writer::import_symbols`](crate::Writer::import_symbols) to make them
+/// available to the next, which would typically use
+/// [`append_le`](crate::append_le) or similar to add to the same file.
+///
+/// ```
+/// # use std::io::{Cursor, Result};
+/// # fn main() -> Result<()> {
+/// use binbin::registry::Registry;
+///
+/// let mut file = Cursor::new(Vec::<u8>::new());
+/// let registry = binbin::append_le(&mut file, |w| {
+///     let first = w.symbol("first");
+///     w.place(first)?;
+///     w.write(&b"one"[..])?;
+///     Ok(w.export_symbols())
+/// })?;
+/// let mut saved = Vec::new();
+/// registry.save(&mut saved)?;
+///
+/// let registry = Registry::load(&saved[..])?;
+/// binbin::append_le(&mut file, |w| {
+///     w.import_symbols(&registry)?;
+///     let first = w.symbol("first");
+///     w.write_offset_of::<u8>(first)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(file.into_inner(), b"one\x00");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Registry {
+    offsets: BTreeMap<String, u64>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the offset of the named symbol, replacing any offset
+    /// already recorded for it.
+    pub fn insert(&mut self, name: impl Into<String>, offset: u64) {
+        self.offsets.insert(name.into(), offset);
+    }
+
+    /// Returns the offset recorded for the named symbol, if any.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.offsets.get(name).copied()
+    }
+
+    /// Returns the number of symbols recorded.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns true if no symbols are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Returns the recorded symbols and their offsets, in order of name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.offsets.iter().map(|(name, ofs)| (name.as_str(), *ofs))
+    }
+
+    /// Adds all of the symbols from the given registry to this one,
+    /// replacing the offsets of any symbols recorded in both.
+    pub fn extend(&mut self, other: &Registry) {
+        for (name, ofs) in other.iter() {
+            self.insert(name, ofs);
+        }
+    }
+
+    /// Writes the registry in a compact binary form that can be read back
+    /// using [`load`](Self::load).
+    ///
+    /// The form is a little-endian 32-bit count of symbols, followed by
+    /// each symbol as a 32-bit name length, the name as UTF-8, and a 64-bit
+    /// offset.
+    pub fn save<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        w.write_all(&count(self.offsets.len())?.to_le_bytes())?;
+        for (name, ofs) in &self.offsets {
+            w.write_all(&count(name.len())?.to_le_bytes())?;
+            w.write_all(name.as_bytes())?;
+            w.write_all(&ofs.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a registry previously written using [`save`](Self::save).
+    pub fn load<R: Read>(mut r: R) -> std::io::Result<Self> {
+        let mut ret = Self::new();
+        let n = read_u32(&mut r)?;
+        for _ in 0..n {
+            let len = read_u32(&mut r)? as usize;
+            let mut name = Vec::new();
+            (&mut r).take(len as u64).read_to_end(&mut name)?;
+            if name.len() != len {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+            }
+            let name = String::from_utf8(name)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            let mut ofs = [0_u8; 8];
+            r.read_exact(&mut ofs)?;
+            ret.insert(name, u64::from_le_bytes(ofs));
+        }
+        Ok(ret)
+    }
+}
+
+fn count(n: usize) -> std::io::Result<u32> {
+    <u32 as std::convert::TryFrom<usize>>::try_from(n).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "registry is too large to save",
+        )
+    })
+}
+
+fn read_u32<R: Read>(r: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0_u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
@@ -0,0 +1,49 @@
+/// A reference to a label that wasn't placed in the output, such as a
+/// symbol defined in some other object file, for emitting as an entry in
+/// a relocation table.
+///
+/// Write relocatable references using
+/// [`Writer::write_reloc`](crate::Writer::write_reloc) and collect them
+/// using [`Writer::relocations`](crate::Writer::relocations).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub(crate) offset: u64,
+    pub(crate) len: usize,
+    pub(crate) symbol: Option<String>,
+    pub(crate) kind: u32,
+    pub(crate) addend: i64,
+}
+
+impl Relocation {
+    /// Returns the position of the field to be relocated.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the length of the field to be relocated, in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the field to be relocated is zero bytes long.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the name of the referenced symbol, if the label was created
+    /// using [`Writer::symbol`](crate::Writer::symbol).
+    pub fn symbol(&self) -> Option<&str> {
+        self.symbol.as_deref()
+    }
+
+    /// Returns the format-specific relocation type given to
+    /// [`Writer::write_reloc`](crate::Writer::write_reloc).
+    pub fn kind(&self) -> u32 {
+        self.kind
+    }
+
+    /// Returns the value to be added to the symbol's address.
+    pub fn addend(&self) -> i64 {
+        self.addend
+    }
+}
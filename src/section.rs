@@ -0,0 +1,46 @@
+use crate::endian::Endianness;
+
+/// A section of output built independently of any [`Writer`](crate::Writer),
+/// to be written into one later using
+/// [`Writer::emit_section`](crate::Writer::emit_section).
+///
+/// Sections are built using [`build_section_le`](crate::build_section_le)
+/// or similar. Because a section is just data, it can be built on a
+/// different thread than the writer it will be emitted into, and so
+/// expensive sections can be built in parallel.
+///
+/// Any labels used in a section are relative to the start of the section
+/// until it's emitted, and then are rebased to their final positions in
+/// the output. Labels created using [`Writer::symbol`](crate::Writer::symbol)
+/// are identified by name, and so can refer to positions in other sections
+/// or in the writer the section is emitted into. A section can refer to a
+/// named symbol without placing it, in which case it must be placed
+/// elsewhere before the writer is finalized.
+///
+/// Positions reported while building a section, such as by
+/// [`Writer::position`](crate::Writer::position), are relative to the start
+/// of the section.
+pub struct Section {
+    pub(crate) data: Vec<u8>,
+    pub(crate) labels: Vec<SectionLabel>,
+}
+
+/// A label used in a [`Section`](Section), with all positions relative to
+/// the start of the section.
+pub(crate) struct SectionLabel {
+    pub(crate) name: Option<String>,
+    pub(crate) pos: Option<u64>,
+    pub(crate) refs: Vec<(u64, usize, Endianness, crate::deferred::RefKind)>,
+}
+
+impl Section {
+    /// Returns the length of the section's data.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the section contains no data.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A stream over a borrowed, fixed-size byte slice, such as a memory-mapped
+/// file, where seeking is just moving an index and writing is a slice
+/// copy.
+///
+/// The slice can't grow, so any write that would extend beyond its end
+/// fails with [`ErrorKind::WriteZero`](std::io::ErrorKind::WriteZero)
+/// without writing anything. [`len`](SliceBuf::len) reports how much of
+/// the slice has been written, so that the caller can truncate the
+/// underlying file to match once writing is complete.
+///
+/// This crate doesn't depend on any memory mapping crate, so rather than
+/// supporting a particular mapping type directly, `SliceBuf` works with any
+/// mapping that dereferences to `[u8]`, such as `memmap2::MmapMut`. For
+/// example:
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// use binbin::slicebuf::SliceBuf;
+///
+/// let mut map = vec![0_u8; 8]; // stands in for a mapped file
+/// let mut out = SliceBuf::new(&mut map[..]);
+/// binbin::write_le(&mut out, |w| {
+///     let len = w.write_deferred(0_u8)?;
+///     w.write(&b"abc"[..])?;
+///     w.resolve(len, 3)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(out.len(), 4);
+/// assert_eq!(&map[..4], b"\x03abc");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SliceBuf<'s> {
+    buf: &'s mut [u8],
+    pos: usize,
+    len: usize,
+}
+
+impl<'s> SliceBuf<'s> {
+    /// Creates a stream over the given slice, positioned at its start.
+    pub fn new(buf: &'s mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes from the start of the slice up to the
+    /// end of the furthest write so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the total size of the slice, which is the most that can be
+    /// written.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns the written part of the slice.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<'s> Write for SliceBuf<'s> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let end = self
+            .pos
+            .checked_add(data.len())
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    format!(
+                        "write of {} bytes at {:#x} exceeds output size of {:#x} bytes",
+                        data.len(),
+                        self.pos,
+                        self.buf.len()
+                    ),
+                )
+            })?;
+        // Any gap skipped by seeking past the end of the written data is
+        // zero-filled, as it would be in a newly-extended file.
+        if self.pos > self.len {
+            self.buf[self.len..self.pos].fill(0);
+        }
+        self.buf[self.pos..end].copy_from_slice(data);
+        self.pos = end;
+        self.len = self.len.max(end);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'s> Seek for SliceBuf<'s> {
+    /// Moves the current position. Seeking relative to the end is relative
+    /// to the end of the written data, not to the end of the slice.
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match to {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(delta) => (self.len as u64, delta),
+            SeekFrom::Current(delta) => (self.pos as u64, delta),
+        };
+        let pos = base
+            .checked_add_signed(delta)
+            .and_then(|pos| <usize as std::convert::TryFrom<u64>>::try_from(pos).ok())
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        self.pos = pos;
+        Ok(pos as u64)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos as u64)
+    }
+}
+
+impl<'s> Read for SliceBuf<'s> {
+    fn read(&mut self, into: &mut [u8]) -> std::io::Result<usize> {
+        let avail = self.buf[..self.len].get(self.pos..).unwrap_or(&[]);
+        let n = into.len().min(avail.len());
+        into[..n].copy_from_slice(&avail[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A `SliceBuf` can also be used as [`Storage`](crate::storage::Storage),
+/// for backends that are written in terms of that interface, such as
+/// when writing into a memory-mapped file.
+impl<'s> crate::storage::Storage for SliceBuf<'s> {
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(pos))?;
+        self.write_all(data)
+    }
+
+    fn len(&mut self) -> std::io::Result<u64> {
+        Ok(self.len as u64)
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.seek(SeekFrom::Start(pos))?;
+        self.read(buf)
+    }
+}
@@ -0,0 +1,253 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A minimal interface to positioned storage, for adding backends that
+/// aren't naturally streams, such as object storage or a custom virtual
+/// file system.
+///
+/// A [`Writer`](crate::Writer) writes to any stream that implements
+/// [`Write`](Write) and [`Seek`](Seek), and also [`Read`](Read) for
+/// derived values. Implementing `Storage` instead and wrapping the storage
+/// in a [`StorageStream`](StorageStream) provides all three, so a backend
+/// need only support writing and reading at a given position.
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// use binbin::storage::StorageStream;
+///
+/// let mut out = StorageStream::new(Vec::<u8>::new());
+/// binbin::write_le(&mut out, |w| {
+///     let len = w.write_deferred(0_u8)?;
+///     w.write(&b"abc"[..])?;
+///     w.resolve(len, 3)?;
+///     Ok(())
+/// })?;
+/// assert_eq!(out.into_inner(), b"\x03abc");
+/// # Ok(())
+/// # }
+/// ```
+pub trait Storage {
+    /// Writes all of the given data starting at the given position,
+    /// extending the storage if necessary. If the position is beyond the
+    /// current length then the gap must read as zeros.
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> std::io::Result<()>;
+
+    /// Writes all of the given data at the end of the storage.
+    ///
+    /// The default implementation uses [`len`](Self::len) and
+    /// [`write_at`](Self::write_at), but backends that have a cheaper way
+    /// to append should override it.
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let len = self.len()?;
+        self.write_at(len, data)
+    }
+
+    /// Returns the current length of the storage.
+    fn len(&mut self) -> std::io::Result<u64>;
+
+    /// Returns true if the storage is empty.
+    fn is_empty(&mut self) -> std::io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Reads data starting at the given position into the given buffer,
+    /// returning the number of bytes read, which is zero only at or beyond
+    /// the end of the storage.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize>;
+
+    /// Ensures that all data written so far has reached its destination.
+    ///
+    /// The default implementation does nothing.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `Vec<u8>` storage grows as needed to accommodate writes.
+impl Storage for Vec<u8> {
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> std::io::Result<()> {
+        let start = usize_pos(pos)?;
+        let end = start
+            .checked_add(data.len())
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        if end > Vec::len(self) {
+            self.resize(end, 0);
+        }
+        self[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn len(&mut self) -> std::io::Result<u64> {
+        Ok(Vec::len(self) as u64)
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let avail = usize_pos(pos)
+            .ok()
+            .and_then(|pos| self.get(pos..))
+            .unwrap_or(&[]);
+        let n = buf.len().min(avail.len());
+        buf[..n].copy_from_slice(&avail[..n]);
+        Ok(n)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &mut S {
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> std::io::Result<()> {
+        (**self).write_at(pos, data)
+    }
+
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        (**self).append(data)
+    }
+
+    fn len(&mut self) -> std::io::Result<u64> {
+        (**self).len()
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        (**self).read_at(pos, buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        (**self).flush()
+    }
+}
+
+fn usize_pos(pos: u64) -> std::io::Result<usize> {
+    <usize as std::convert::TryFrom<u64>>::try_from(pos)
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))
+}
+
+/// Adapts a standard stream, such as a [`File`](std::fs::File), to the
+/// [`Storage`](Storage) interface, by seeking before each operation.
+///
+/// This is mainly for backends that are written in terms of `Storage` but
+/// that should also work with ordinary files.
+#[derive(Debug)]
+pub struct StdStorage<T> {
+    inner: T,
+}
+
+impl<T: Read + Write + Seek> StdStorage<T> {
+    /// Wraps the given stream.
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Discards the adapter, returning the underlying stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read + Write + Seek> Storage for StdStorage<T> {
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> std::io::Result<()> {
+        self.inner.seek(SeekFrom::Start(pos))?;
+        self.inner.write_all(data)
+    }
+
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.write_all(data)
+    }
+
+    fn len(&mut self) -> std::io::Result<u64> {
+        self.inner.seek(SeekFrom::End(0))
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.seek(SeekFrom::Start(pos))?;
+        self.inner.read(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A stream over any [`Storage`](Storage), implementing [`Read`](Read),
+/// [`Write`](Write), and [`Seek`](Seek) so that it can be used as the
+/// target of a [`Writer`](crate::Writer).
+#[derive(Debug)]
+pub struct StorageStream<S> {
+    storage: S,
+    pos: u64,
+}
+
+impl<S: Storage> StorageStream<S> {
+    /// Creates a stream over the given storage, positioned at its start.
+    pub fn new(storage: S) -> Self {
+        Self { storage, pos: 0 }
+    }
+
+    /// Returns a reference to the underlying storage.
+    pub fn get_ref(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns a mutable reference to the underlying storage.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Discards the stream, returning the underlying storage.
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: Storage> Write for StorageStream<S> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if self.pos == self.storage.len()? {
+            self.storage.append(data)?;
+        } else {
+            self.storage.write_at(self.pos, data)?;
+        }
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.storage.flush()
+    }
+}
+
+impl<S: Storage> Seek for StorageStream<S> {
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match to {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(delta) => (self.storage.len()?, delta),
+            SeekFrom::Current(delta) => (self.pos, delta),
+        };
+        self.pos = base.checked_add_signed(delta).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<S: Storage> Read for StorageStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.storage.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
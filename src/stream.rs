@@ -0,0 +1,414 @@
+use std::borrow::Cow;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::Cancelled;
+use crate::progress::Progress;
+use crate::trace::Tracer;
+
+/// Wraps a [`Writer`](crate::Writer)'s stream to track the stream position
+/// as data is written, read, and sought, so that asking for the current
+/// position doesn't need to query the underlying stream each time, and
+/// optionally to buffer writes.
+///
+/// The position is queried from the underlying stream only the first time
+/// it's needed and after any operation fails. In debug builds each cached
+/// position is checked against the underlying stream whenever the two are
+/// expected to agree.
+///
+/// When buffering, sequential writes are collected in memory until the
+/// buffer reaches its capacity. Writes that land inside the unflushed
+/// buffer, such as updates to placeholders written recently, just update
+/// the buffer. Seeking to an absolute position only changes the logical
+/// position, so that seeking back to patch something inside the buffer and
+/// then returning doesn't touch the underlying stream at all.
+///
+/// When tracing, each write is logged at the time the writer makes it,
+/// regardless of buffering.
+pub(crate) struct Stream<W> {
+    inner: W,
+
+    /// The logical position, if known.
+    pos: Option<u64>,
+
+    /// The position of the underlying stream, if known. This differs from
+    /// `pos` only while buffering.
+    inner_pos: Option<u64>,
+
+    buf: Vec<u8>,
+    buf_start: u64,
+    capacity: usize,
+
+    /// Seeks the underlying stream, for flushing the buffer in methods that
+    /// don't otherwise require [`Seek`](Seek). This is always set when
+    /// `capacity` is nonzero.
+    seeker: Option<SeekFn<W>>,
+
+    trace: Option<Tracer>,
+
+    /// Describes the next write, for the trace.
+    label: Option<Cow<'static, str>>,
+
+    /// The position that no write may extend beyond, if any.
+    max_len: Option<u64>,
+
+    progress: Option<Progress>,
+
+    /// Causes writes to fail once set.
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+type SeekFn<W> = fn(&mut W, SeekFrom) -> std::io::Result<u64>;
+
+impl<W> Stream<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            pos: None,
+            inner_pos: None,
+            buf: Vec::new(),
+            buf_start: 0,
+            capacity: 0,
+            seeker: None,
+            trace: None,
+            label: None,
+            max_len: None,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Selects the position that no write may extend beyond, if any.
+    pub(crate) fn set_max_len(&mut self, max_len: Option<u64>) {
+        self.max_len = max_len;
+    }
+
+    /// Returns the position that no write may extend beyond, if any.
+    pub(crate) fn max_len(&self) -> Option<u64> {
+        self.max_len
+    }
+
+    /// Selects where to report the number of bytes written, if anywhere.
+    pub(crate) fn set_progress(&mut self, progress: Option<Progress>) {
+        self.progress = progress;
+    }
+
+    /// Returns the observer for the number of bytes written, if any.
+    pub(crate) fn progress(&self) -> Option<&Progress> {
+        self.progress.as_ref()
+    }
+
+    /// Selects a token that causes all future writes to fail once set.
+    pub(crate) fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.cancel = cancel;
+    }
+
+    /// Returns the token selected using [`set_cancel`](Self::set_cancel),
+    /// if any.
+    pub(crate) fn cancel(&self) -> Option<&Arc<AtomicBool>> {
+        self.cancel.as_ref()
+    }
+
+    /// Returns an error if the cancellation token is set.
+    pub(crate) fn check_cancel(&self) -> std::io::Result<()> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Selects where to log writes and events, if anywhere.
+    pub(crate) fn set_trace(&mut self, trace: Option<Tracer>) {
+        self.trace = trace;
+    }
+
+    /// Returns the destination for the trace, if tracing.
+    pub(crate) fn trace(&self) -> Option<&Tracer> {
+        self.trace.as_ref()
+    }
+
+    /// Describes the next write in the trace, if tracing. The description
+    /// is only generated when needed.
+    pub(crate) fn set_label<F: FnOnce() -> Cow<'static, str>>(&mut self, f: F) {
+        if self.trace.is_some() {
+            self.label = Some(f());
+        }
+    }
+
+    /// Discards the description set using [`set_label`](Self::set_label)
+    /// if it wasn't used by a write.
+    pub(crate) fn clear_label(&mut self) {
+        self.label = None;
+    }
+
+    /// Logs an event in the trace, if tracing.
+    pub(crate) fn trace_event<F: FnOnce() -> String>(&mut self, f: F) -> std::io::Result<()> {
+        match &self.trace {
+            Some(trace) => trace.event(Cow::Owned(f())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the underlying stream, which must have been flushed using
+    /// [`flush_buffer`](Self::flush_buffer) if buffering.
+    pub(crate) fn into_inner(self) -> W {
+        debug_assert!(self.buf.is_empty(), "discarding unflushed buffer");
+        self.inner
+    }
+
+    /// Records that the underlying stream is at the given position, for a
+    /// stream over another stream's underlying stream, which can't query
+    /// the position itself.
+    pub(crate) fn assume_pos(&mut self, pos: Option<u64>) {
+        self.pos = pos;
+        self.inner_pos = pos;
+    }
+
+    fn forget(&mut self) {
+        self.pos = None;
+        self.inner_pos = None;
+    }
+}
+
+impl<W: Write + Seek> Stream<W> {
+    /// Changes how many bytes of writes to buffer, flushing any data that's
+    /// already buffered. A capacity of zero disables buffering.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) -> std::io::Result<()> {
+        self.flush_buffer()?;
+        self.capacity = capacity;
+        self.seeker = Some(|w, to| w.seek(to));
+        Ok(())
+    }
+}
+
+impl<W: Write> Stream<W> {
+    /// Returns the underlying stream for direct use, positioned at the
+    /// logical position, and forgets the cached position because the
+    /// caller might move it.
+    pub(crate) fn get_mut(&mut self) -> std::io::Result<&mut W> {
+        self.sync()?;
+        self.forget();
+        Ok(&mut self.inner)
+    }
+
+    /// Flushes the buffer and moves the underlying stream to the logical
+    /// position, for operations that use the underlying stream directly.
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.flush_buffer()?;
+        // Without a seeker, the underlying stream is always at the logical
+        // position already.
+        if let (Some(pos), Some(seeker)) = (self.pos, self.seeker) {
+            if self.inner_pos != Some(pos) {
+                self.inner_pos = None;
+                seeker(&mut self.inner, SeekFrom::Start(pos))?;
+                self.inner_pos = Some(pos);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered data to the underlying stream.
+    pub(crate) fn flush_buffer(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let result = self.write_buffer();
+        self.buf.clear();
+        if result.is_err() {
+            self.forget();
+        }
+        result
+    }
+
+    fn write_buffer(&mut self) -> std::io::Result<()> {
+        if self.inner_pos != Some(self.buf_start) {
+            self.inner_pos = None;
+            // Buffering is only enabled with a seeker.
+            (self.seeker.unwrap())(&mut self.inner, SeekFrom::Start(self.buf_start))?;
+        }
+        self.inner.write_all(&self.buf[..])?;
+        self.inner_pos = Some(self.buf_start + self.buf.len() as u64);
+        Ok(())
+    }
+
+    fn advance(&mut self, result: std::io::Result<usize>) -> std::io::Result<usize> {
+        match result {
+            Ok(n) => {
+                self.pos = self.pos.map(|pos| pos + n as u64);
+                self.inner_pos = self.pos;
+                Ok(n)
+            }
+            Err(err) => {
+                self.forget();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<W: Write> Stream<W> {
+    /// Returns the logical position, querying the underlying stream for it
+    /// if necessary and possible.
+    pub(crate) fn known_pos(&mut self) -> std::io::Result<Option<u64>> {
+        if self.pos.is_none() {
+            if let Some(seeker) = self.seeker {
+                self.flush_buffer()?;
+                let pos = seeker(&mut self.inner, SeekFrom::Current(0))?;
+                self.pos = Some(pos);
+                self.inner_pos = Some(pos);
+            }
+        }
+        Ok(self.pos)
+    }
+
+    fn check_max_len(&mut self, max_len: u64, len: usize) -> std::io::Result<()> {
+        let pos = match self.known_pos()? {
+            Some(pos) => pos,
+            // A limit we can't check is treated as exceeded, so that a
+            // stream that lost track of its position can't silently write
+            // beyond it.
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "cannot enforce the maximum output length because the position is unknown",
+                ))
+            }
+        };
+        if pos.saturating_add(len as u64) > max_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "write of {} bytes at {:#x} exceeds the maximum output length of {:#x} bytes",
+                    len, pos, max_len
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn write_traced(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let pos = self.known_pos()?;
+        let n = self.write_untraced(data)?;
+        let label = self.label.take();
+        // Tracing is only enabled when there's a tracer.
+        self.trace
+            .as_ref()
+            .unwrap()
+            .write(pos, &data[..n], label.as_deref())?;
+        Ok(n)
+    }
+
+    fn write_untraced(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        // Without buffering, the underlying stream is always at the
+        // logical position.
+        let seeker = match self.seeker {
+            Some(seeker) if self.capacity != 0 => seeker,
+            _ => {
+                let result = self.inner.write(data);
+                return self.advance(result);
+            }
+        };
+        let pos = match self.pos {
+            Some(pos) => pos,
+            None => {
+                self.flush_buffer()?;
+                let pos = seeker(&mut self.inner, SeekFrom::Current(0))?;
+                self.pos = Some(pos);
+                self.inner_pos = Some(pos);
+                pos
+            }
+        };
+        let buf_end = self.buf_start + self.buf.len() as u64;
+        if self.buf.is_empty() || pos < self.buf_start || pos > buf_end {
+            self.flush_buffer()?;
+            self.buf_start = pos;
+        }
+        let ofs = (pos - self.buf_start) as usize;
+        let overlap = data.len().min(self.buf.len() - ofs);
+        self.buf[ofs..ofs + overlap].copy_from_slice(&data[..overlap]);
+        self.buf.extend_from_slice(&data[overlap..]);
+        self.pos = Some(pos + data.len() as u64);
+        if self.buf.len() >= self.capacity {
+            self.flush_buffer()?;
+        }
+        Ok(data.len())
+    }
+}
+
+impl<W: Write> Write for Stream<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.check_cancel()?;
+        if let Some(max_len) = self.max_len {
+            self.check_max_len(max_len, data.len())?;
+        }
+        let n = if self.trace.is_some() {
+            self.write_traced(data)?
+        } else {
+            self.write_untraced(data)?
+        };
+        if let Some(progress) = &self.progress {
+            progress.wrote(n);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Read + Write + Seek> Read for Stream<W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.sync()?;
+        let result = self.inner.read(buf);
+        self.advance(result)
+    }
+}
+
+impl<W: Write + Seek> Seek for Stream<W> {
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let to = match (to, self.pos) {
+            (SeekFrom::Current(delta), Some(pos)) => match pos.checked_add_signed(delta) {
+                Some(to) => SeekFrom::Start(to),
+                None => return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
+            },
+            _ => to,
+        };
+        match to {
+            SeekFrom::Start(to) if self.capacity != 0 || self.pos == Some(to) => {
+                self.pos = Some(to);
+                Ok(to)
+            }
+            _ => {
+                self.flush_buffer()?;
+                self.forget();
+                let pos = self.inner.seek(to)?;
+                self.pos = Some(pos);
+                self.inner_pos = Some(pos);
+                Ok(pos)
+            }
+        }
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        match self.pos {
+            Some(pos) => {
+                if cfg!(debug_assertions) && self.buf.is_empty() && self.inner_pos == Some(pos) {
+                    let actual = self.inner.stream_position()?;
+                    debug_assert_eq!(pos, actual, "cached stream position is stale");
+                }
+                Ok(pos)
+            }
+            None => {
+                self.flush_buffer()?;
+                let pos = self.inner.stream_position()?;
+                self.pos = Some(pos);
+                self.inner_pos = Some(pos);
+                Ok(pos)
+            }
+        }
+    }
+}
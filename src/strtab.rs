@@ -0,0 +1,54 @@
+use crate::deferred::Deferred;
+
+/// A deduplicated pool of null-terminated strings, as used in formats like
+/// ELF where other parts of the file refer to strings by their offset
+/// within the pool.
+///
+/// Add strings to the table using [`Writer::intern`](crate::Writer::intern),
+/// which returns a deferred slot for the string's offset, and then write
+/// the table with
+/// [`Writer::emit_string_table`](crate::Writer::emit_string_table) to
+/// resolve all of those slots.
+///
+/// Offsets are relative to the start of the table. Many formats expect the
+/// empty string at offset zero, which you can achieve by interning `""`
+/// before any other string.
+pub struct StringTable<'a> {
+    pub(crate) strings: Vec<(Vec<u8>, Deferred<'a, u32>)>,
+    index: std::collections::HashMap<Vec<u8>, usize>,
+}
+
+impl<'a> StringTable<'a> {
+    /// Creates a new, empty string table.
+    pub fn new() -> Self {
+        Self {
+            strings: Vec::new(),
+            index: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Returns the number of distinct strings in the table.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns true if no strings have been added to the table.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub(crate) fn get(&self, s: &[u8]) -> Option<Deferred<'a, u32>> {
+        self.index.get(s).map(|idx| self.strings[*idx].1)
+    }
+
+    pub(crate) fn insert(&mut self, s: &[u8], deferred: Deferred<'a, u32>) {
+        self.index.insert(s.to_vec(), self.strings.len());
+        self.strings.push((s.to_vec(), deferred));
+    }
+}
+
+impl<'a> Default for StringTable<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{Seek, SeekFrom, Write};
+
+/// A stream adapter that passes all bytes written through it to an observer
+/// function, such as one that updates a cryptographic hash.
+///
+/// The observer sees the bytes in stream order, so any write that would
+/// change bytes the observer has already seen fails with
+/// [`InvalidInput`](std::io::ErrorKind::InvalidInput). Writers that update
+/// deferred placeholders seek back to do so, so a `Tee` is best used as the
+/// target of [`write_buffered_le`](crate::write_buffered_le) or
+/// [`write_buffered_be`](crate::write_buffered_be), which write the final
+/// output in a single sequential pass:
+///
+/// ```
+/// # use std::io::Result;
+/// # fn main() -> Result<()> {
+/// let mut out = Vec::<u8>::new();
+/// let mut sum = 0_u32;
+/// let mut tee = binbin::tee::Tee::new(&mut out, |b: &[u8]| {
+///     sum += b.iter().map(|b| *b as u32).sum::<u32>()
+/// });
+/// binbin::write_buffered_le(&mut tee, |w| {
+///     let len = w.write_deferred(0_u8)?;
+///     w.write(2_u8)?;
+///     w.resolve(len, 2)?;
+///     Ok(())
+/// })?;
+/// tee.into_inner();
+/// assert_eq!(out, vec![2, 2]);
+/// assert_eq!(sum, 4);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Tee<W, F>
+where
+    W: Write,
+    F: FnMut(&[u8]),
+{
+    w: W,
+    observer: F,
+    pos: u64,
+    observed: u64,
+}
+
+impl<W, F> Tee<W, F>
+where
+    W: Write,
+    F: FnMut(&[u8]),
+{
+    /// Wraps the given stream so that all bytes written to it will also be
+    /// passed to `observer`.
+    pub fn new(w: W, observer: F) -> Self {
+        Self {
+            w,
+            observer,
+            pos: 0,
+            observed: 0,
+        }
+    }
+
+    /// Returns the number of bytes passed to the observer so far.
+    pub fn observed_len(&self) -> u64 {
+        self.observed
+    }
+
+    /// Discards the adapter, returning the underlying stream and the
+    /// observer function.
+    pub fn into_inner(self) -> (W, F) {
+        (self.w, self.observer)
+    }
+}
+
+impl<W, F> Write for Tee<W, F>
+where
+    W: Write,
+    F: FnMut(&[u8]),
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.pos < self.observed {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "write would change data already passed to the observer",
+            ));
+        }
+        let n = self.w.write(buf)?;
+        // If the stream seeked beyond the end of the data, the gap reads
+        // as zeros and so the observer must see zeros too.
+        while self.observed < self.pos {
+            let gap = std::cmp::min(self.pos - self.observed, 512) as usize;
+            (self.observer)(&[0_u8; 512][..gap]);
+            self.observed += gap as u64;
+        }
+        (self.observer)(&buf[..n]);
+        self.pos += n as u64;
+        self.observed = self.pos;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<W, F> Seek for Tee<W, F>
+where
+    W: Write + Seek,
+    F: FnMut(&[u8]),
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = self.w.seek(pos)?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,148 @@
+//! Helpers for testing code that writes binary formats.
+//!
+//! These are available when the `testing` feature is enabled, which is
+//! intended for use in `dev-dependencies`. They panic on failure, with
+//! messages describing the difference as a hexdump.
+
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use crate::endian::{BigEndian, ByteOrder, LittleEndian};
+use crate::layout::LayoutMap;
+use crate::pack::{IntoPack, Pack};
+use crate::{Builder, Result, Writer};
+
+/// Returns the bytes written by the given function, writing little-endian
+/// by default, panicking if writing fails.
+#[track_caller]
+pub fn capture_vec_le<F>(f: F) -> Vec<u8>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut Cursor<&mut Vec<u8>>, LittleEndian>) -> Result<()>,
+{
+    capture_vec::<LittleEndian, F>(f)
+}
+
+/// Returns the bytes written by the given function, writing big-endian by
+/// default, panicking if writing fails.
+#[track_caller]
+pub fn capture_vec_be<F>(f: F) -> Vec<u8>
+where
+    for<'w> F: FnOnce(&mut Writer<'w, &mut Cursor<&mut Vec<u8>>, BigEndian>) -> Result<()>,
+{
+    capture_vec::<BigEndian, F>(f)
+}
+
+/// Generic equivalent of [`capture_vec_le`](capture_vec_le) and
+/// [`capture_vec_be`](capture_vec_be), with endianness selected by a type
+/// parameter.
+#[track_caller]
+pub fn capture_vec<E, F>(f: F) -> Vec<u8>
+where
+    E: ByteOrder,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut Cursor<&mut Vec<u8>>, E>) -> Result<()>,
+{
+    capture_with_layout::<E, F>(f).0
+}
+
+/// Like [`capture_vec`](capture_vec), but also returns the layout of the
+/// output, for use with [`assert_slot`](assert_slot).
+#[track_caller]
+pub fn capture_with_layout<E, F>(f: F) -> (Vec<u8>, LayoutMap)
+where
+    E: ByteOrder,
+    for<'w> F: FnOnce(&mut Writer<'w, &mut Cursor<&mut Vec<u8>>, E>) -> Result<()>,
+{
+    let mut buf = Vec::new();
+    let mut cursor = Cursor::new(&mut buf);
+    let result = Builder::<E>::new().write_with_layout(&mut cursor, f);
+    match result {
+        Ok(((), layout)) => (buf, layout),
+        Err(err) => panic!("writing failed: {}", err),
+    }
+}
+
+/// Returns a description of the differences between the expected and actual
+/// bytes, or `None` if they're equal.
+///
+/// The description is a hexdump of each sixteen-byte line that differs,
+/// showing the expected line prefixed with `-` and the actual line prefixed
+/// with `+`, along with the lengths if they differ.
+pub fn hex_diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let mut ret = String::new();
+    if expected.len() != actual.len() {
+        // Writing to a String never fails.
+        writeln!(
+            ret,
+            "expected {} bytes, but got {} bytes",
+            expected.len(),
+            actual.len()
+        )
+        .unwrap();
+    }
+    let lines = expected.len().max(actual.len()).div_ceil(16);
+    for line in 0..lines {
+        let rng = line * 16..(line + 1) * 16;
+        let want = line_of(expected, rng.clone());
+        let got = line_of(actual, rng.clone());
+        if want != got {
+            writeln!(ret, "-{:08x} {}", rng.start, hex_line(want)).unwrap();
+            writeln!(ret, "+{:08x} {}", rng.start, hex_line(got)).unwrap();
+        }
+    }
+    Some(ret)
+}
+
+fn line_of(data: &[u8], rng: std::ops::Range<usize>) -> &[u8] {
+    &data[rng.start.min(data.len())..rng.end.min(data.len())]
+}
+
+fn hex_line(data: &[u8]) -> String {
+    data.iter().map(|b| format!(" {:02x}", b)).collect()
+}
+
+/// Panics with a hexdump of the differences if the actual bytes don't match
+/// the expected bytes.
+#[track_caller]
+pub fn assert_bytes_eq(actual: impl AsRef<[u8]>, expected: impl AsRef<[u8]>) {
+    if let Some(diff) = hex_diff(expected.as_ref(), actual.as_ref()) {
+        panic!("output does not match expected bytes:\n{}", diff);
+    }
+}
+
+/// Panics if any placeholder of the deferred slot with the given index, as
+/// recorded in the layout, doesn't contain the given value packed in that
+/// placeholder's byte order.
+///
+/// Slots are numbered in the order they were created, starting at zero.
+#[track_caller]
+pub fn assert_slot<T: IntoPack>(output: &[u8], layout: &LayoutMap, slot: usize, expected: T) {
+    let slot_layout = match layout.slots().get(slot) {
+        Some(s) => s,
+        None => panic!("there is no deferred slot {}", slot),
+    };
+    if !slot_layout.resolved() {
+        panic!("deferred slot {} was never resolved", slot);
+    }
+    let v = expected.into_pack();
+    let mut want = vec![0_u8; v.pack_len()];
+    for (rng, endianness) in slot_layout
+        .placeholders()
+        .iter()
+        .zip(slot_layout.endianness())
+    {
+        endianness.pack_into_slice(&v, &mut want[..]);
+        let got = line_of(output, rng.start as usize..rng.end as usize);
+        if got != &want[..] {
+            panic!(
+                "deferred slot {} placeholder at {:#x} is{}, but expected{}",
+                slot,
+                rng.start,
+                hex_line(got),
+                hex_line(&want),
+            );
+        }
+    }
+}
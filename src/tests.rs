@@ -167,3 +167,3089 @@ fn derive() {
     assert_eq_hex!(buf, vec![0x01, 0x02, 0x03]);
     assert_eq!(ret_sum, 3);
 }
+
+#[test]
+fn read_little_endian() {
+    let buf = vec![
+        0xef, 0xbe, 0xad, 0xde, 0xce, 0xfa, 0xed, 0xfe, // u64
+        0xad, 0xbe, 0xed, 0xde, // u32
+        0x34, 0x12, // u16
+        0xff, // u8
+        b'h', b'o', b'w', b'd', b'y', 0x00, // &std::ffi::CStr
+    ];
+    let got = read_slice_le(&buf[..], |r| {
+        let a: u64 = r.read()?;
+        let b: u32 = r.read()?;
+        let c: (u16, i8) = r.read()?;
+        let d = r.read_cstr()?;
+        Ok((a, b, c, d))
+    })
+    .unwrap();
+    assert_eq_hex!(got.0, 0xfeedfacedeadbeef);
+    assert_eq_hex!(got.1, 0xdeedbead);
+    assert_eq_hex!(got.2, (0x1234, -1));
+    assert_eq!(got.3.as_bytes(), b"howdy");
+}
+
+#[test]
+fn read_big_endian_offsets() {
+    let buf = vec![
+        0x00, 0x00, 0x00, 0x08, // offset of table
+        0x00, 0x02, // table length
+        0xff, 0xff, // padding
+        0x12, 0x34, 0x56, 0x78, // table
+        0x9a, // trailing byte
+    ];
+    let got = read_slice_be(&buf[..], |r| {
+        let ofs: u32 = r.read()?;
+        let len: u16 = r.read()?;
+        let table = r.subregion(ofs as u64..(ofs as u64 + len as u64 * 2), |r| {
+            let table: [u16; 2] = r.read()?;
+            // The subregion ends here, so further reads must fail.
+            let err = r.read::<u8>().unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+            Ok(table)
+        })?;
+        let pos = r.position()?;
+        Ok((table, pos))
+    })
+    .unwrap();
+    assert_eq_hex!(got.0, [0x1234, 0x5678]);
+    assert_eq!(got.1, 6);
+}
+
+#[test]
+fn read_align() {
+    let buf = [0x01_u8, 0xff, 0xff, 0xff, 0x02];
+    let got = read_slice_le(&buf[..], |r| {
+        let a: u8 = r.read()?;
+        let skipped = r.align(4)?;
+        let b: u8 = r.read()?;
+        let err = r.align(0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok((a, skipped, b))
+    })
+    .unwrap();
+    assert_eq!(got, (0x01, 3, 0x02));
+}
+
+#[test]
+fn labels() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let early = w.label();
+        let late = w.label();
+        w.place(early)?;
+        w.write_offset_of::<u16>(late)?;
+        w.write_offset_of::<u32>(late)?;
+        w.write_offset_of::<u8>(early)?;
+        w.place(late)?;
+        w.write(0xff_u8)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x07, 0x00, // late as u16
+            0x07, 0x00, 0x00, 0x00, // late as u32
+            0x00, // early as u8
+            0xff, // late is placed here
+        ]
+    );
+}
+
+#[test]
+fn labels_wide() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let label = w.label();
+        w.write_offset_of::<[u8; 20]>(label)?;
+        w.with_endian::<BigEndian, _, _>(|w| w.write_offset_of::<[u8; 18]>(label))?;
+        w.place(label)?;
+        Ok(())
+    })
+    .unwrap();
+    let mut want = vec![0_u8; 38];
+    want[0] = 38;
+    want[37] = 38;
+    assert_eq_hex!(buf, want);
+
+    let mut buf = Vec::<u8>::new();
+    write_vec::<_, MiddleEndian, _>(&mut buf, |w| {
+        let label = w.label();
+        w.write_offset_of::<[u8; 18]>(label)?;
+        w.write(0x0102_u16)?;
+        w.place(label)?;
+        Ok(())
+    })
+    .unwrap();
+    let mut want = vec![0_u8; 20];
+    want[16] = 20;
+    want[18] = 0x02;
+    want[19] = 0x01;
+    assert_eq_hex!(buf, want);
+}
+
+#[test]
+fn labels_unplaced() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let label = w.label();
+        w.write_offset_of::<u32>(label)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn write_sized_subregion() {
+    let mut buf = Vec::<u8>::new();
+    let (rng, ret) = write_vec_be(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        w.write_sized_subregion::<u16, _, _>(|w| {
+            w.write(0x12345678_u32)?;
+            w.write(0x9a_u8)?;
+            Ok("done")
+        })
+    })
+    .unwrap();
+    assert_eq!(rng, 3..8);
+    assert_eq!(ret, "done");
+    assert_eq_hex!(
+        buf,
+        vec![
+            0xff, // leading byte
+            0x00, 0x05, // size prefix
+            0x12, 0x34, 0x56, 0x78, 0x9a, // content
+        ]
+    );
+}
+
+#[test]
+fn deferred_combinators() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let start = w.deferred(0_u16);
+        let end = w.deferred(0_u16);
+        let size = w.deferred_sub(end, start)?;
+        let end_plus = w.deferred_offset_by(end, 0x100)?;
+        let doubled = w.deferred_map(size, |v| (v as u32) * 2)?;
+        w.write_placeholder(size)?;
+        w.write_placeholder(end_plus)?;
+        w.write_placeholder(doubled)?;
+        let sum = w.deferred_add(start, end)?;
+        w.write_placeholder(sum)?;
+        w.resolve(start, 2)?;
+        w.resolve(end, 8)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x06, 0x00, // size
+            0x08, 0x01, // end_plus
+            0x0c, 0x00, 0x00, 0x00, // doubled
+            0x0a, 0x00, // sum
+        ]
+    );
+}
+
+#[test]
+fn deferred_combinators_overflow() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let start = w.deferred(0_u32);
+        let end = w.deferred(0_u32);
+        let size = w.deferred_sub(end, start)?;
+        w.write_placeholder(size)?;
+        w.resolve(end, 1)?;
+        w.resolve(start, 5)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let a = w.deferred(0_u8);
+        let sum = w.deferred_offset_by(a, 0x80)?;
+        w.write_placeholder(sum)?;
+        w.resolve(a, 0x80)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn deferred_unresolved() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        w.write_deferred(0_u32)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.to_string(),
+        "deferred slot 0 was never resolved (placeholders at offsets [1])"
+    );
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let d = w.write_deferred(0xaa_u8)?;
+        w.allow_unresolved(d);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0xaa]);
+}
+
+#[test]
+fn write_buffered() {
+    // A writer that cannot seek, like a socket or a pipe.
+    struct Stream(Vec<u8>);
+    impl std::io::Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut stream = Stream(Vec::new());
+    write_buffered_be(&mut stream, |w| {
+        let len = w.write_deferred(0_u16)?;
+        w.write(0xfefefefe_u32)?;
+        w.resolve(len, 4)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(stream.0, vec![0x00, 0x04, 0xfe, 0xfe, 0xfe, 0xfe]);
+}
+
+#[test]
+fn short_writes() {
+    // A stream that accepts at most one byte per call to write, as some
+    // streams may, so that every value must be written using write_all.
+    struct Stream(std::io::Cursor<Vec<u8>>);
+    impl std::io::Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(&buf[..buf.len().min(1)])
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl std::io::Seek for Stream {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    let mut stream = Stream(std::io::Cursor::new(Vec::new()));
+    write_be(&mut stream, |w| {
+        let len = w.write_deferred(0_u16)?;
+        w.write(0xfefefefe_u32)?;
+        w.set_padding(0x3d);
+        w.skip(3)?;
+        w.resolve(len, 7)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        stream.0.into_inner(),
+        vec![0x00, 0x07, 0xfe, 0xfe, 0xfe, 0xfe, 0x3d, 0x3d, 0x3d]
+    );
+}
+
+#[test]
+fn string_table() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let mut table = strtab::StringTable::new();
+        let empty = w.intern(&mut table, "");
+        let foo = w.intern(&mut table, "foo");
+        let bar = w.intern(&mut table, b"bar");
+        let foo_again = w.intern(&mut table, "foo");
+        w.write_placeholder(bar)?;
+        w.write_placeholder(foo)?;
+        w.write_placeholder(foo_again)?;
+        w.write_placeholder(empty)?;
+        let rng = w.emit_string_table(&table)?;
+        assert_eq!(rng, 16..25);
+        assert_eq!(table.len(), 3);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x05, 0x00, 0x00, 0x00, // offset of "bar"
+            0x01, 0x00, 0x00, 0x00, // offset of "foo"
+            0x01, 0x00, 0x00, 0x00, // offset of "foo" again
+            0x00, 0x00, 0x00, 0x00, // offset of ""
+            0x00, // ""
+            b'f', b'o', b'o', 0x00, // "foo"
+            b'b', b'a', b'r', 0x00, // "bar"
+        ]
+    );
+}
+
+#[test]
+fn varints() {
+    use varint::*;
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(Uleb128(0))?;
+        w.write(Uleb128(624485))?;
+        w.write(Sleb128(-123456))?;
+        w.write(Sleb128(63))?;
+        w.write(Sleb128(64))?;
+        w.write(ZigZag(-1))?;
+        w.write(ZigZag(150))?;
+        w.write(Vlq(0x7f))?;
+        w.write(Vlq(0x3fff))?;
+        w.write_sized_subregion::<PaddedUleb128<5>, _, _>(|w| {
+            w.write(0xff_u8)?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x00, // Uleb128(0)
+            0xe5, 0x8e, 0x26, // Uleb128(624485)
+            0xc0, 0xbb, 0x78, // Sleb128(-123456)
+            0x3f, // Sleb128(63)
+            0xc0, 0x00, // Sleb128(64)
+            0x01, // ZigZag(-1)
+            0xac, 0x02, // ZigZag(150)
+            0x7f, // Vlq(0x7f)
+            0xff, 0x7f, // Vlq(0x3fff)
+            0x81, 0x80, 0x80, 0x80, 0x00, // PaddedUleb128<5>(1)
+            0xff, // sized content
+        ]
+    );
+
+    let got = read_slice_le(&buf[buf.len() - 6..], |r| r.read::<PaddedUleb128<5>>()).unwrap();
+    assert_eq!(got, PaddedUleb128(1));
+
+    // A zero-width padded field has no bytes at all.
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| w.write(PaddedUleb128::<0>(0))).unwrap();
+    assert_eq_hex!(buf, Vec::<u8>::new());
+    let mut empty = [0_u8; 0];
+    pack::Pack::pack_into_slice::<LittleEndian>(&Sleb128(0), &mut empty[..]);
+}
+
+#[test]
+fn u128_round_trip() {
+    let v = 0x00112233445566778899aabbccddeeff_u128;
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(v)?;
+        w.write(pack::as_big_endian(-2_i128))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0xff, 0xee, 0xdd, 0xcc, 0xbb, 0xaa, 0x99, 0x88, //
+            0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00, // u128
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, //
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, // i128
+        ]
+    );
+    let got = read_slice_le(&buf[..16], |r| r.read::<u128>()).unwrap();
+    assert_eq_hex!(got, v);
+    let got = read_slice_be(&buf[16..], |r| r.read::<i128>()).unwrap();
+    assert_eq!(got, -2);
+}
+
+#[test]
+fn odd_width_integers() {
+    use pack::{U24, U48};
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(U24(0x123456))?;
+        let d = w.write_deferred(U48(0))?;
+        w.resolve(d, U48(0xaabbccddeeff))?;
+        w.write_sized_subregion::<U24, _, _>(|w| w.write(0xff_u8))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x12, 0x34, 0x56, // U24
+            0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, // U48
+            0x00, 0x00, 0x01, // U24 size
+            0xff, // sized content
+        ]
+    );
+    let got = read_slice_be(&buf[..], |r| r.read::<(U24, U48)>()).unwrap();
+    assert_eq!(got, (U24(0x123456), U48(0xaabbccddeeff)));
+    assert!(<U24 as std::convert::TryFrom<u64>>::try_from(0x1000000).is_err());
+}
+
+#[test]
+fn runtime_width_integers() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write_uint(0x123456, 3)?;
+        w.write_uint(0xff, 1)?;
+        w.write_int(-2, 3)?;
+        w.write_int(-128, 1)?;
+        w.write_int(127, 1)?;
+        assert!(w.write_uint(0x100, 1).is_err());
+        assert!(w.write_int(128, 1).is_err());
+        assert!(w.write_int(-129, 1).is_err());
+        assert!(w.write_uint(0, 9).is_err());
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x56, 0x34, 0x12, // 0x123456 in three bytes
+            0xff, // 0xff in one byte
+            0xfe, 0xff, 0xff, // -2 in three bytes
+            0x80, // -128 in one byte
+            0x7f, // 127 in one byte
+        ]
+    );
+}
+
+#[test]
+fn strings() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write("MAGIC")?;
+        w.write(String::from("héllo"))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            b'M', b'A', b'G', b'I', b'C', // &str
+            b'h', 0xc3, 0xa9, b'l', b'l', b'o', // String
+        ]
+    );
+}
+
+#[test]
+fn len_prefixed() {
+    use pack::len_prefixed;
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(len_prefixed::<u16, _>("hello")?)?;
+        w.write(len_prefixed::<u8, _>("hi")?.with_terminator(true)?)?;
+        w.write(len_prefixed::<varint::Uleb128, _>(&[0x1234_u16, 0x5678][..])?.counting_units(2)?)?;
+
+        let long = [b'x'; 300];
+        let err = len_prefixed::<u8, _>(&long[..]).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = len_prefixed::<u8, _>(&long[..255])?
+            .with_terminator(true)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x00, 0x05, b'h', b'e', b'l', b'l', b'o', // u16 prefix
+            0x03, b'h', b'i', 0x00, // u8 prefix with counted terminator
+            0x02, 0x12, 0x34, 0x56, 0x78, // varint prefix counting u16s
+        ]
+    );
+}
+
+#[test]
+fn fixed_str() {
+    use pack::{fixed_str, fixed_str_truncated, FixedStr};
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(fixed_str::<8>("README")?)?;
+        w.write(fixed_str_truncated::<3>("TEXT").with_padding(b' '))?;
+        w.write(fixed_str::<3>("A")?.with_padding(b' '))?;
+        assert!(fixed_str::<3>("TEXT").is_err());
+        let name = w.write_deferred(fixed_str::<4>("")?)?;
+        w.resolve(name, fixed_str("abc")?)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            b'R', b'E', b'A', b'D', b'M', b'E', 0x00, 0x00, // fixed_str
+            b'T', b'E', b'X', // truncated
+            b'A', b' ', b' ', // space-padded
+            b'a', b'b', b'c', 0x00, // deferred
+        ]
+    );
+    let got = read_slice_le(&buf[..], |r| r.read::<FixedStr<8>>()).unwrap();
+    assert_eq!(got.as_bytes(), b"README");
+}
+
+#[test]
+fn utf16() {
+    use pack::{as_big_endian, utf16};
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(utf16("hé"))?;
+        w.write(utf16("A").with_terminator())?;
+        w.write(as_big_endian(utf16("B").with_bom()))?;
+        w.write(utf16("xyz").with_terminator().with_fixed_len(3))?;
+        w.write(utf16("").with_fixed_len(1))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            b'h', 0x00, 0xe9, 0x00, // "hé"
+            b'A', 0x00, 0x00, 0x00, // "A" with terminator
+            0xfe, 0xff, 0x00, b'B', // "B" big-endian with BOM
+            b'x', 0x00, b'y', 0x00, 0x00, 0x00, // truncated with terminator
+            0x00, 0x00, // empty padded
+        ]
+    );
+}
+
+#[test]
+fn arrays_and_slices() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write([0x1234_u16, 0x5678])?;
+        w.write(&[0x9abcdef0_u32][..])?;
+        w.write(vec![varint::Uleb128(1), varint::Uleb128(300)])?;
+        w.write(*b"ok")?;
+        let d = w.write_deferred([0_u8; 2])?;
+        w.resolve(d, [0xaa, 0xbb])?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x12, 0x34, 0x56, 0x78, // [u16; 2]
+            0x9a, 0xbc, 0xde, 0xf0, // &[u32]
+            0x01, 0xac, 0x02, // Vec<Uleb128>
+            b'o', b'k', // [u8; 2]
+            0xaa, 0xbb, // deferred [u8; 2]
+        ]
+    );
+}
+
+#[test]
+fn tuples() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write((0x7f454c46_u32, 1_u16, 0xff_u8))?;
+        w.write(("ab", varint::Uleb128(300), [1_u8, 2], 3_i8))?;
+        let d = w.write_deferred((0_u8, 0_u16))?;
+        w.resolve(d, (0x12, 0x3456))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x46, 0x4c, 0x45, 0x7f, 0x01, 0x00, 0xff, // 3-tuple
+            b'a', b'b', 0xac, 0x02, 0x01, 0x02, 0x03, // 4-tuple
+            0x12, 0x56, 0x34, // deferred 2-tuple
+        ]
+    );
+    let got = read_slice_le(&buf[..7], |r| r.read::<(u32, u16, u8)>()).unwrap();
+    assert_eq_hex!(got, (0x7f454c46, 1, 0xff));
+}
+
+#[test]
+fn runtime_endianness() {
+    use endian::Endianness;
+    for (endianness, want) in [
+        (Endianness::Little, [0x34, 0x12]),
+        (Endianness::Big, [0x12, 0x34]),
+    ] {
+        let mut buf = Vec::<u8>::new();
+        write_vec_dyn(&mut buf, endianness, |w| {
+            assert_eq!(w.endianness(), endianness);
+            let d = w.write_deferred(0_u16)?;
+            w.resolve(d, 0x1234)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq_hex!(buf, want.to_vec());
+        let got = read_slice_dyn(&buf[..], endianness, |r| r.read::<u16>()).unwrap();
+        assert_eq_hex!(got, 0x1234);
+    }
+}
+
+#[test]
+fn native_endian() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_ne(&mut buf, |w| {
+        w.write(0x12345678_u32)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, 0x12345678_u32.to_ne_bytes().to_vec());
+}
+
+#[test]
+fn with_endian() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let outer = w.write_deferred(0_u16)?;
+        let inner = w.with_endian::<BigEndian, _, _>(|w| {
+            w.write(0x0102_u16)?;
+            let inner = w.write_deferred(0_u16)?;
+            w.resolve(outer, 0x0304)?;
+            Ok(inner)
+        })?;
+        w.write(0x0506_u16)?;
+        w.resolve(inner, 0x0708)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x04_u8, 0x03, // outer, little-endian
+            0x01, 0x02, // big-endian
+            0x07, 0x08, // inner, big-endian
+            0x06, 0x05, // little-endian
+        ]
+    );
+}
+
+#[test]
+fn middle_endian() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(pack::as_middle_endian(0x0a0b0c0d_u32))?;
+        w.with_endian::<MiddleEndian, _, _>(|w| {
+            w.write(0x0102_u16)?;
+            w.write(0x0102030405060708_u64)?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x0b_u8, 0x0a, 0x0d, 0x0c, // u32
+            0x02, 0x01, // u16
+            0x02, 0x01, 0x04, 0x03, 0x06, 0x05, 0x08, 0x07, // u64
+        ]
+    );
+
+    let v: u32 = read_slice_dyn(&buf[..], Endianness::Middle, |r| r.read()).unwrap();
+    assert_eq!(v, 0x0a0b0c0d);
+}
+
+#[test]
+fn derive_deferred() {
+    use std::io::Read;
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let sum = w.write_deferred(0_u8)?;
+        let len = w.write_deferred(0_u8)?;
+        w.derive_deferred(1..4, sum, |r| {
+            let mut bytes = Vec::new();
+            r.read_to_end(&mut bytes)?;
+            Ok(bytes.iter().fold(0_u8, |a, b| a.wrapping_add(*b)))
+        })?;
+        w.write(0x10_u8)?;
+        w.write(0x20_u8)?;
+        w.resolve(len, 4)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x34_u8, 0x04, 0x10, 0x20]);
+}
+
+#[test]
+fn derive_deferred_nested() {
+    use std::io::Read;
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let len = w.write_deferred(0_u8)?;
+        w.with_endian::<BigEndian, _, _>(|w| {
+            let sum = w.write_deferred(0_u16)?;
+            w.derive_deferred(0..1, sum, |r| {
+                let mut bytes = Vec::new();
+                r.read_to_end(&mut bytes)?;
+                Ok(bytes[0] as u16 * 0x101)
+            })
+        })?;
+        w.resolve(len, 3)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x03_u8, 0x03, 0x03]);
+
+    // A writer over a stream not known to be readable can't run a
+    // derivation postponed inside a nested writer.
+    let mut c = std::io::Cursor::new(Vec::<u8>::new());
+    let err = write_le(&mut c, |w| {
+        w.with_endian::<BigEndian, _, _>(|w| {
+            let sum = w.write_deferred(0_u8)?;
+            w.derive_deferred(0..1, sum, |_| Ok(1))
+        })
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn crc() {
+    use checksum::{Crc, CrcParams};
+
+    let check = b"123456789";
+    let cases = [
+        (CrcParams::CRC_8, 0xf4),
+        (CrcParams::CRC_16_ARC, 0xbb3d),
+        (CrcParams::CRC_16_IBM_3740, 0x29b1),
+        (CrcParams::CRC_32, 0xcbf43926),
+        (CrcParams::CRC_32C, 0xe3069283),
+        (CrcParams::CRC_64_XZ, 0x995dc9bbdf1939fa),
+        (CrcParams::CRC_64_ECMA_182, 0x6c40df5f0b497347),
+    ];
+    for (params, want) in cases.iter() {
+        assert_eq!(Crc::checksum(*params, &check[..]), *want, "{:?}", params);
+    }
+
+    let mut buf = Vec::<u8>::new();
+    let got = write_vec_le(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        w.write(&check[..])?;
+        w.crc32(1..10)
+    })
+    .unwrap();
+    assert_eq!(got, 0xcbf43926);
+}
+
+#[test]
+fn tee() {
+    let mut out = Vec::<u8>::new();
+    let mut seen = Vec::<u8>::new();
+    let mut tee = tee::Tee::new(&mut out, |b: &[u8]| seen.extend_from_slice(b));
+    write_buffered_be(&mut tee, |w| {
+        let len = w.write_deferred(0_u16)?;
+        w.write(0xaa_u8)?;
+        w.resolve(len, 1)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(tee.observed_len(), 3);
+    assert_eq_hex!(seen, vec![0x00_u8, 0x01, 0xaa]);
+    assert_eq_hex!(out, seen);
+
+    // Seekable streams can't be patched once observed.
+    let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
+    let mut tee = tee::Tee::new(&mut cursor, |_: &[u8]| {});
+    let err = write_le(&mut tee, |w| {
+        let len = w.write_deferred(0_u16)?;
+        w.write(0xaa_u8)?;
+        w.resolve(len, 1)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn simple_checksums() {
+    use checksum::{Adler32, Fletcher16, Fletcher32, InternetChecksum};
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        let sum = w.write_deferred(0_u16)?;
+        w.derive_deferred(0..6, sum, |r| {
+            let mut sum = InternetChecksum::new();
+            std::io::copy(r, &mut sum)?;
+            Ok(sum.finish())
+        })?;
+        w.write(0x4500_u16)?;
+        w.write(0x0073_u16)?;
+        Ok(())
+    })
+    .unwrap();
+    // Re-summing a region that includes a correct checksum gives zero.
+    let mut sum = InternetChecksum::new();
+    sum.update(&buf[..]);
+    assert_eq!(sum.finish(), 0);
+
+    // The sums of odd-length chunks match the sums of the whole.
+    let data = b"abcdefghi";
+    let mut split = Fletcher32::new();
+    let mut whole = Fletcher32::new();
+    for chunk in data.chunks(3) {
+        split.update(chunk);
+    }
+    whole.update(&data[..]);
+    assert_eq!(split.finish(), whole.finish());
+
+    let mut sum = Fletcher32::new();
+    sum.update(b"abcdef");
+    assert_eq!(sum.finish(), 0x56502d2a);
+
+    let mut sum = Fletcher16::new();
+    sum.update(b"abcdef");
+    assert_eq!(sum.finish(), 0x2057);
+
+    let mut sum = Adler32::new();
+    sum.update(b"Wikipedia");
+    assert_eq!(sum.finish(), 0x11e60398);
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn compressed_subregion() {
+    // A trivial run-length encoding, to stand in for a real compressor.
+    let rle = |data: &[u8], out: &mut dyn std::io::Write| {
+        for run in data.chunk_by(|a, b| a == b) {
+            out.write_all(&[run.len() as u8, run[0]])?;
+        }
+        Ok(())
+    };
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        let clen = w.write_deferred(0_u16)?;
+        let ulen = w.write_deferred(0_u16)?;
+        let (compressed, ret) = w.compressed_subregion(rle, |w| {
+            let n = w.write_deferred(0_u16)?;
+            w.write(&[0xaa_u8; 4][..])?;
+            w.resolve(n, 4)?;
+            Ok("done")
+        })?;
+        assert_eq!(ret, "done");
+        assert_eq!(compressed.range, 4..10);
+        w.resolve(clen, compressed.compressed_len() as u16)?;
+        w.resolve(ulen, compressed.uncompressed_len as u16)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x00_u8, 0x06, // compressed length
+            0x00, 0x06, // uncompressed length
+            0x01, 0x00, 0x01, 0x04, 0x04, 0xaa, // compressed data
+        ]
+    );
+}
+
+#[test]
+fn copy_from() {
+    let src = vec![0x55_u8; 10000];
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let (rng, n) = w.write_sized_subregion::<u32, _, _>(|w| w.copy_from(&src[..], None))?;
+        assert_eq!(n, 10000);
+        assert_eq!(rng, 4..10004);
+        w.copy_from(&b"abcdef"[..], Some(2))?;
+        let err = w.copy_from(&b"ab"[..], Some(3)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(buf.len(), 10008);
+    assert_eq_hex!(buf[..4].to_vec(), vec![0x10_u8, 0x27, 0x00, 0x00]);
+    assert_eq_hex!(buf[10004..].to_vec(), b"abab".to_vec());
+}
+
+#[test]
+fn seek_forward() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.set_padding(0xff);
+        let start = w.write_deferred(0_u8)?;
+        w.write(0x11_u8)?;
+        assert_eq!(w.seek_forward(3)?, 5);
+        w.resolve(start, 5)?;
+        assert_eq!(w.seek_forward(0)?, 5);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x05_u8, 0x11, 0x00, 0x00, 0x00]);
+
+    // Seeking over existing data leaves it unchanged.
+    let mut cursor = std::io::Cursor::new(vec![0xaa_u8; 4]);
+    write_le(&mut cursor, |w| {
+        w.seek_forward(2)?;
+        w.write(0xbb_u8)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(cursor.into_inner(), vec![0xaa_u8, 0xaa, 0xbb, 0xaa]);
+}
+
+#[test]
+fn pad_to_and_fill() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.fill(0xaa, 3)?;
+        w.set_padding(0xff);
+        assert_eq!(w.pad_to(1030)?, 1027);
+        assert_eq!(w.pad_to(1030)?, 0);
+        let err = w.pad_to(1029).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(buf.len(), 1030);
+    assert_eq_hex!(buf[..4].to_vec(), vec![0xaa_u8, 0xaa, 0xaa, 0xff]);
+    assert!(buf[3..].iter().all(|b| *b == 0xff));
+}
+
+#[test]
+fn padding_pattern() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.set_padding_pattern(&[0xa0, 0xa1, 0xa2, 0xa3], 2);
+        w.write(0x01_u8)?;
+        w.align(4)?;
+        w.write(0x02_u8)?;
+        w.skip(6)?;
+        w.set_padding(0xff);
+        w.align(4)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x01_u8, 0xa3, 0xa0, 0xa1, // aligned
+            0x02, 0xa3, 0xa0, 0xa1, 0xa2, 0xa3, 0xa0, // skipped
+            0xff, // aligned
+        ]
+    );
+}
+
+#[test]
+fn align_from() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(0x01_u8)?;
+        w.subregion(|w| {
+            let base = w.position()?;
+            w.write(0x02_u8)?;
+            assert_eq!(w.align_from(base, 4)?, 3);
+            w.write(0x03_u8)?;
+            assert!(w.align_from(base + 100, 4).is_err());
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x02, 0x00, 0x00, 0x00, 0x03]);
+}
+
+#[test]
+fn subregion_with() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(0x01_u8)?;
+        let (rng, count) = w.subregion_with(|w| {
+            let count = w.write_deferred(0_u8)?;
+            w.write(0x02_u8)?;
+            Ok(count)
+        })?;
+        assert_eq!(rng, 1..3);
+        w.resolve(count, 1)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x01, 0x02]);
+}
+
+#[test]
+fn buffered() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        let payload_count = w.write_deferred(0_u8)?;
+
+        // The payload is built first, so we know how many entries the
+        // directory needs, but the directory is emitted first.
+        let entry = w.label();
+        let (payload, (count, size)) = w.buffered(|w| {
+            w.write(0xaa_u8)?;
+            w.place(entry)?;
+            let size = w.write_deferred(0_u8)?;
+            w.write(0xbb_u8)?;
+            w.resolve(payload_count, 1)?;
+            Ok((1_u8, size))
+        })?;
+        w.write(count)?;
+        w.write_offset_of::<u8>(entry)?;
+        let rng = w.emit(payload)?;
+        assert_eq!(rng, 4..7);
+        w.resolve(size, 9)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0xff_u8, 0x01, // header
+            0x01, 0x05, // directory
+            0xaa, 0x09, 0xbb, // payload
+        ]
+    );
+
+    // Labels placed in regions that are never emitted cause an error.
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let label = w.label();
+        w.buffered(|w| w.place(label))?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn sections() {
+    let (a, b) = std::thread::scope(|s| {
+        let a = s.spawn(|| {
+            build_section_le(|w| {
+                w.write(0xaa_u8)?;
+                let start = w.symbol("a");
+                w.place(start)?;
+                w.write(0xab_u8)?;
+                let b = w.symbol("b");
+                w.write_offset_of::<u16>(b)?;
+                Ok(())
+            })
+        });
+        let b = s.spawn(|| {
+            build_section_be(|w| {
+                let local = w.label();
+                w.write_offset_of::<u16>(local)?;
+                let start = w.symbol("b");
+                w.place(start)?;
+                w.place(local)?;
+                w.write(0xbb_u8)?;
+                Ok(())
+            })
+        });
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    let (a, ()) = a.unwrap();
+    let (b, ()) = b.unwrap();
+    assert_eq!(a.len(), 4);
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let a_ptr = w.symbol("a");
+        w.write_offset_of::<u8>(a_ptr)?;
+        assert_eq!(w.emit_section(a)?, 1..5);
+        assert_eq!(w.emit_section(b)?, 5..8);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x02_u8, // offset of "a"
+            0xaa, 0xab, 0x07, 0x00, // section a
+            0x00, 0x07, 0xbb, // section b
+        ]
+    );
+}
+
+#[test]
+// The closures are needed to make the function generic over the writer's
+// lifetime, despite what clippy thinks.
+#[allow(clippy::redundant_closure)]
+fn measure() {
+    fn body<W: std::io::Write + std::io::Seek>(w: &mut Writer<W, BigEndian>) -> Result<u64> {
+        let len = w.write_deferred(0_u32)?;
+        w.write(&b"hello"[..])?;
+        w.align(8)?;
+        let pos = w.position()?;
+        w.resolve(len, pos as u32)?;
+        w.write(0_u16)?;
+        Ok(pos)
+    }
+
+    let (len, pos) = measure_be(|w| body(w)).unwrap();
+    assert_eq!((len, pos), (18, 16));
+
+    let mut buf = Vec::<u8>::new();
+    let pos = write_vec_be(&mut buf, |w| body(w)).unwrap();
+    assert_eq!(pos, 16);
+    assert_eq!(buf.len(), 18);
+}
+
+#[test]
+#[allow(clippy::redundant_closure)]
+fn digest() {
+    fn body<W: std::io::Write + std::io::Seek>(w: &mut Writer<W, LittleEndian>) -> Result<()> {
+        let (body, len) = w.buffered(|w| {
+            let len = w.write_deferred(0_u16)?;
+            w.write(&b"hello"[..])?;
+            Ok(len)
+        })?;
+        w.resolve(len, 5)?;
+        w.emit(body)?;
+        w.align(4)?;
+        Ok(())
+    }
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| body(w)).unwrap();
+    assert_eq_hex!(buf, [0x05, 0x00, b'h', b'e', b'l', b'l', b'o', 0x00]);
+
+    let mut seen = Vec::<u8>::new();
+    let (len, ()) = digest_le(|b| seen.extend_from_slice(b), |w| body(w)).unwrap();
+    assert_eq!(len, 8);
+    assert_eq!(seen, buf);
+
+    // Updating a placeholder the observer has already seen fails.
+    let err = digest_be(
+        |_| {},
+        |w| {
+            let len = w.write_deferred(0_u8)?;
+            w.write(1_u8)?;
+            w.resolve(len, 1)?;
+            Ok(())
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn planner() {
+    let mut planner = plan::Planner::<LittleEndian>::new();
+    planner
+        .section("header", |w, plan| {
+            w.write(varint::Uleb128(plan.offset("table")?))?;
+            w.write(varint::Uleb128(plan.size("data")?))?;
+            Ok(())
+        })
+        .section("table", |w, plan| {
+            w.write(plan.offset("data")? as u8)?;
+            Ok(())
+        })
+        .section("data", |w, _| {
+            w.fill(0xaa, 200)?;
+            Ok(())
+        })
+        .after("table", "data");
+
+    let mut buf = Vec::<u8>::new();
+    let plan = write_vec_le(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        w.write_planned(&mut planner)
+    })
+    .unwrap();
+    assert_eq!(plan.range("header").unwrap(), 1..5);
+    assert_eq!(plan.range("data").unwrap(), 5..205);
+    assert_eq!(plan.range("table").unwrap(), 205..206);
+    assert_eq_hex!(buf[..5].to_vec(), vec![0xff_u8, 0xcd, 0x01, 0xc8, 0x01]);
+    assert_eq!(buf[205], 5);
+
+    let mut planner = plan::Planner::<LittleEndian>::new();
+    planner
+        .section("a", |_, _| Ok(()))
+        .section("b", |_, _| Ok(()))
+        .after("a", "b")
+        .after("b", "a");
+    let mut buf = Vec::<u8>::new();
+    assert!(write_vec_le(&mut buf, |w| w.write_planned(&mut planner)).is_err());
+}
+
+#[test]
+fn write_auto_width() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let end = w.label();
+        w.write_auto_width::<u8, u32, _, _>(|w, size| {
+            w.write(0xaa_u8)?;
+            w.resolve(size, 1)?;
+            w.place(end)?;
+            Ok(())
+        })?;
+        w.write_auto_width::<u8, u32, _, _>(|w, size| {
+            w.write_offset_of::<u8>(end)?;
+            w.resolve(size, 0x1234)?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x01_u8, 0xaa, // narrow
+            0x34, 0x12, 0x00, 0x00, 0x02, // wide
+        ]
+    );
+
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.write_auto_width::<u8, u32, _, _>(|_, _| Ok(()))
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn chunk() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.chunk(b"RIFF", |w| {
+            w.write(&b"WAVE"[..])?;
+            let (rng, ()) = w.chunk(b"fmt ", |w| {
+                w.write(0x01_u8)?;
+                Ok(())
+            })?;
+            assert_eq!(rng, 20..21);
+            w.chunk_padded(b"data", 4, |w| {
+                w.write(0x02_u8)?;
+                Ok(())
+            })?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            b'R', b'I', b'F', b'F', 0x1a, 0x00, 0x00, 0x00, // RIFF header
+            b'W', b'A', b'V', b'E', // form type
+            b'f', b'm', b't', b' ', 0x01, 0x00, 0x00, 0x00, // fmt header
+            0x01, 0x00, // fmt payload and padding
+            b'd', b'a', b't', b'a', 0x01, 0x00, 0x00, 0x00, // data header
+            0x02, 0x00, 0x00, 0x00, // data payload and padding
+        ]
+    );
+}
+
+#[test]
+fn tlv() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        let (rng, ()) = w.tlv::<_, u16, _, _>(0x30_u8, |w| {
+            w.tlv::<_, u8, _, _>(0x02_u8, |w| {
+                w.write(0x05_u8)?;
+                Ok(())
+            })?;
+            Ok(())
+        })?;
+        assert_eq!(rng, 3..6);
+        w.tlv_inclusive::<_, varint::Uleb128, _, _>(0x01_u8, |w| {
+            w.fill(0xaa, 126)?;
+            Ok(())
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf[..9].to_vec(),
+        vec![
+            0x30_u8, 0x00, 0x03, // outer header
+            0x02, 0x01, 0x05, // inner record
+            0x01, 0x81, 0x01, // inclusive header, with two-byte length
+        ]
+    );
+    assert_eq!(buf.len(), 6 + 129);
+}
+
+#[test]
+fn offset_table() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let mut table = offtab::OffsetTable::new();
+        let a = w.table_entry(&mut table);
+        let b = w.table_entry(&mut table);
+        w.write(table.len() as u8)?;
+        w.emit_table(&table, |w, entry| {
+            let offset = w.deferred_map(entry.offset, |v| v as u16)?;
+            let size = w.deferred_map(entry.size, |v| v as u8)?;
+            w.write_placeholder(offset)?;
+            w.write_placeholder(size)?;
+            Ok(())
+        })?;
+        w.write_table_blob(b, |w| w.write(&b"bb"[..]))?;
+        w.write_table_blob(a, |w| w.write(&b"a"[..]))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x02_u8, // count
+            0x09, 0x00, 0x01, // a
+            0x07, 0x00, 0x02, // b
+            b'b', b'b', b'a', // blobs
+        ]
+    );
+}
+
+#[test]
+fn counter() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let count = w.counter::<u16>()?;
+        w.write_placeholder(count.deferred())?;
+        for v in 1..=3_u8 {
+            w.write(v)?;
+            count.increment();
+        }
+        assert_eq!(count.get(), 3);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x03_u8, 0x00, 0x01, 0x02, 0x03]);
+
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let count = w.counter::<u8>()?;
+        count.add(256);
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn on_finalize() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let seq = std::rc::Rc::new(std::cell::Cell::new(0_u8));
+        let total = w.write_deferred(0_u8)?;
+        let second = w.write_deferred(0_u8)?;
+        let third = w.write_deferred(0_u8)?;
+        let outer_seq = seq.clone();
+        w.on_finalize(move |w| {
+            outer_seq.set(outer_seq.get() + 1);
+            let end = w.position()?;
+            w.resolve(total, end as u8)?;
+            w.on_finalize(move |w| {
+                outer_seq.set(outer_seq.get() + 1);
+                w.resolve(third, outer_seq.get()).map(|_| ())
+            });
+            Ok(())
+        });
+        w.on_finalize(move |w| {
+            seq.set(seq.get() + 1);
+            w.resolve(second, seq.get()).map(|_| ())
+        });
+        w.write(&b"xyz"[..])?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x06_u8, 0x02, 0x03, b'x', b'y', b'z']);
+}
+
+#[test]
+fn at() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(0_u16)?;
+        w.write(&b"abc"[..])?;
+        let written = w.at(1, |w| {
+            w.write(0xff_u8)?;
+            w.position()
+        })?;
+        assert_eq!(written, 2);
+        assert_eq!(w.position()?, 5);
+        let err = w
+            .at(0, |w| -> Result<()> {
+                w.write(0xee_u8)?;
+                Err(std::io::Error::from(std::io::ErrorKind::Other))
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+        assert_eq!(w.position()?, 5);
+        w.write(b'd')?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0xee_u8, 0xff, b'a', b'b', b'c', b'd']);
+}
+
+#[test]
+fn patch() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(&[0_u8; 6][..])?;
+        let end = w.position()?;
+        assert_eq!(w.patch(1_u64, 0x0102_u16)?, 1..3);
+        assert_eq!(w.patch(3..4, 0xaa_u8)?, 3..4);
+        assert_eq!(w.position()?, end);
+        let err = w.patch(2..4, 0xbb_u8).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = w.patch(5_u64, 0xcccc_u16).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(w.position()?, end);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x00_u8, 0x01, 0x02, 0xaa, 0x00, 0x00]);
+}
+
+#[test]
+fn patch_before_end() {
+    let mut cursor = std::io::Cursor::new(vec![0_u8; 8]);
+    edit_le(&mut cursor, |w| {
+        assert_eq!(w.position()?, 0);
+        assert_eq!(w.patch(4_u64, 7_u32)?, 4..8);
+        assert_eq!(w.position()?, 0);
+        w.write(0xaa_u8)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        cursor.into_inner(),
+        vec![0xaa_u8, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn insert_at() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let body = w.label();
+        w.write_offset_of::<u8>(body)?;
+        w.place(body)?;
+        let later = w.write_deferred(0_u16)?;
+        let end = w.position()?;
+        let (inserted, ret) = w.insert_at(1, |w| {
+            let ext = w.write_deferred(0_u8)?;
+            w.write(b'x')?;
+            w.resolve(ext, 0xee)?;
+            Ok(7)
+        })?;
+        assert_eq!((inserted, ret), (1..3, 7));
+        assert_eq!(w.position()?, end + 2);
+        w.resolve(later, 0x0201)?;
+        w.write(b'z')?;
+
+        let err = w.insert_at(100, |_| Ok(())).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x03_u8, 0xee, b'x', 0x01, 0x02, b'z']);
+}
+
+#[test]
+fn insert_at_resolve_later() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let inner = w.label();
+        w.write([0xaa_u8; 8])?;
+        w.write([0xbb_u8; 8])?;
+        let (inserted, slot) = w.insert_at(4, |w| {
+            w.write(0xcc_u8)?;
+            w.place(inner)?;
+            w.write_deferred(0_u32)
+        })?;
+        assert_eq!(inserted, 4..9);
+        w.write_offset_of::<u8>(inner)?;
+        w.resolve(slot, 0x11111111)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0xaa, 0xaa, 0xaa, 0xaa, // before the insertion point
+            0xcc, 0x11, 0x11, 0x11, 0x11, // inserted
+            0xaa, 0xaa, 0xaa, 0xaa, // moved
+            0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, // moved
+            0x05, // offset of inner label
+        ]
+    );
+}
+
+#[test]
+fn error_context() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.write(0_u16)?;
+        w.context("header", |w| {
+            w.write(0_u8)?;
+            w.context("magic", |w| w.write_uint(0, 9))
+        })
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let ctx = Error::from_io(&err).unwrap();
+    assert_eq!(ctx.context(), &["header", "magic"][..]);
+    assert_eq!(ctx.position(), Some(3));
+    assert_eq!(ctx.slot(), None);
+    assert_eq!(
+        err.to_string(),
+        "in header > magic at 0x3: invalid input parameter"
+    );
+}
+
+#[test]
+fn resolve_checked() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let a = w.write_deferred(0_u16)?;
+        let b = w.write_deferred(0_u8)?;
+        assert_eq!(w.resolve_checked(a, 0x0102_u64)?, 0x0102);
+        let err = b.resolve_from_u64(w, 0x100).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Error::from_io(&err).unwrap().slot(), Some(b.idx));
+        b.resolve_from_u64(w, 0xff)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x02_u8, 0x01, 0xff]);
+}
+
+#[test]
+fn subregion_limited() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let size = w.write_deferred(0_u8)?;
+        let (rng, ()) = w.subregion_limited(4, |w| {
+            w.write(0x0201_u16)?;
+            assert_eq!(w.w.get_mut()?.remaining(), 2);
+            w.write(0x0403_u16)?;
+            w.resolve(size, 4)?;
+            Ok(())
+        })?;
+        assert_eq!(rng, 1..5);
+
+        let err = w
+            .subregion_limited(2, |w| {
+                w.write(0xaa_u8)?;
+                w.write(0xbbbb_u16)?;
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x04_u8, 0x01, 0x02, 0x03, 0x04, 0xaa]);
+}
+
+#[test]
+fn strict_alignment() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(1_u8)?;
+        assert_eq!(
+            w.assert_aligned(4).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        w.write_aligned(4, 0x0302_u16)?;
+        w.align(3)?;
+
+        w.set_strict(true);
+        assert_eq!(
+            w.align(3).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        w.assert_aligned(2)?;
+        w.write_aligned(2, 4_u8)?;
+        assert_eq!(
+            w.write_aligned(2, 5_u8).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x00, 0x00, 0x00, 0x02, 0x03, 0x04]);
+}
+
+#[test]
+fn builder() {
+    let mut buf = Vec::<u8>::new();
+    Builder::<BigEndian>::new()
+        .padding(0xee)
+        .default_alignment(4)
+        .unresolved(builder::UnresolvedPolicy::KeepInitial)
+        .write_vec(&mut buf, |w| {
+            w.write_deferred(0x0102_u16)?;
+            w.align_default()?;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x02, 0xee, 0xee]);
+
+    let mut buf = Vec::<u8>::new();
+    let err = Builder::<DynEndian>::new()
+        .endianness(Endianness::Little)
+        .strict(true)
+        .write_vec(&mut buf, |w| {
+            w.write(0x0102_u16)?;
+            w.align(3)
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq_hex!(buf, vec![0x02_u8, 0x01]);
+}
+
+#[test]
+fn pack_to_writer() {
+    use pack::Pack;
+    let mut buf = Vec::<u8>::new();
+    let n = 0x01020304_u32
+        .pack_to_writer::<BigEndian, _>(&mut buf)
+        .unwrap();
+    assert_eq!(n, 4);
+    let n = "hi".pack_to_writer::<BigEndian, _>(&mut buf).unwrap();
+    assert_eq!(n, 2);
+    let big = [0x0102_u16; 40];
+    let n = Endianness::Little.pack_to_writer(&big, &mut buf).unwrap();
+    assert_eq!(n, 80);
+    let n = pack::as_little_endian(0x0506_u16)
+        .pack_to_writer::<BigEndian, _>(&mut buf)
+        .unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(buf.len(), 88);
+    assert_eq_hex!(buf[..6], [0x01_u8, 0x02, 0x03, 0x04, b'h', b'i']);
+    assert_eq_hex!(buf[6..8], [0x02_u8, 0x01]);
+    assert_eq_hex!(buf[86..], [0x06_u8, 0x05]);
+}
+
+#[test]
+fn position_cache() {
+    use std::io::SeekFrom;
+    let mut c = stream::Stream::new(std::io::Cursor::new(Vec::<u8>::new()));
+    c.write_all(b"hello").unwrap();
+    assert_eq!(c.stream_position().unwrap(), 5);
+    c.write_all(b" world").unwrap();
+    assert_eq!(c.stream_position().unwrap(), 11);
+    assert_eq!(c.seek(SeekFrom::End(-5)).unwrap(), 6);
+    let mut buf = [0_u8; 2];
+    c.read_exact(&mut buf).unwrap();
+    assert_eq!(c.stream_position().unwrap(), 8);
+    c.get_mut().unwrap().set_position(1);
+    assert_eq!(c.stream_position().unwrap(), 1);
+    assert_eq!(c.seek(SeekFrom::Start(1)).unwrap(), 1);
+    assert_eq!(c.into_inner().into_inner(), b"hello world");
+}
+
+#[test]
+fn batch_patches() {
+    // Records the position of each write, so we can see the order in
+    // which placeholders are patched.
+    struct Log {
+        inner: std::io::Cursor<Vec<u8>>,
+        writes: Vec<u64>,
+    }
+    impl Write for Log {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.writes.push(self.inner.position());
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+    impl Seek for Log {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let mut log = Log {
+        inner: std::io::Cursor::new(Vec::new()),
+        writes: Vec::new(),
+    };
+    Builder::<LittleEndian>::new()
+        .batch_patches(true)
+        .write(&mut log, |w| {
+            let a = w.write_deferred(0_u8)?;
+            let b = w.write_deferred(0_u8)?;
+            let c = w.write_deferred(0_u8)?;
+            w.resolve(c, 3)?;
+            w.resolve(a, 1)?;
+            w.resolve(b, 2)?;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq_hex!(log.inner.into_inner(), vec![0x01_u8, 0x02, 0x03]);
+    assert_eq!(log.writes, vec![0, 1, 2, 0, 1, 2]);
+}
+
+#[test]
+fn write_slice() {
+    let vs: Vec<u32> = (0..3000).collect();
+    let fs = [1.5_f32, -2.0];
+    let mut le = Vec::<u8>::new();
+    write_vec_le(&mut le, |w| {
+        assert_eq!(w.write_slice(&vs[..])?, 12000);
+        w.write_slice(&fs[..])?;
+        w.write_slice(&b"ab"[..])?;
+        Ok(())
+    })
+    .unwrap();
+    let mut be = Vec::<u8>::new();
+    write_vec_be(&mut be, |w| {
+        w.write_slice(&vs[..])?;
+        w.write_slice(&fs[..])?;
+        w.write_slice(&b"ab"[..])?;
+        Ok(())
+    })
+    .unwrap();
+
+    let mut want_le = Vec::<u8>::new();
+    let mut want_be = Vec::<u8>::new();
+    for v in &vs {
+        want_le.extend_from_slice(&v.to_le_bytes());
+        want_be.extend_from_slice(&v.to_be_bytes());
+    }
+    for f in &fs {
+        want_le.extend_from_slice(&f.to_le_bytes());
+        want_be.extend_from_slice(&f.to_be_bytes());
+    }
+    want_le.extend_from_slice(b"ab");
+    want_be.extend_from_slice(b"ab");
+    assert!(le == want_le);
+    assert!(be == want_be);
+}
+
+#[test]
+fn write_buffer() {
+    // Records the position and length of each write, to see which writes
+    // reach the underlying stream.
+    struct Log {
+        inner: std::io::Cursor<Vec<u8>>,
+        writes: Vec<(u64, usize)>,
+    }
+    impl Write for Log {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.writes.push((self.inner.position(), buf.len()));
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+    impl Seek for Log {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let mut log = Log {
+        inner: std::io::Cursor::new(Vec::new()),
+        writes: Vec::new(),
+    };
+    Builder::<BigEndian>::new()
+        .write_buffer(8)
+        .write(&mut log, |w| {
+            let a = w.write_deferred(0_u16)?;
+            w.write(&b"ab"[..])?;
+            w.resolve(a, 2)?;
+            let b = w.write_deferred(0_u16)?;
+            w.write(&b"cdef"[..])?;
+            w.resolve(b, 4)?;
+            w.write(&b"gh"[..])?;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(&log.inner.get_ref()[..], b"\x00\x02ab\x00\x04cdefgh");
+    assert_eq!(log.writes, vec![(0, 10), (4, 2), (10, 2)]);
+}
+
+#[test]
+fn append_vec() {
+    let mut buf = b"hdr:".to_vec();
+    let ret = append_vec_be(&mut buf, |w| {
+        let start = w.position()?;
+        let len = w.write_deferred(0_u16)?;
+        w.write(&b"body"[..])?;
+        let end = w.position()?;
+        w.resolve(len, (end - start) as u16)?;
+        w.seek_forward(2)?;
+        w.derive(start..end, |r| {
+            let mut v = Vec::new();
+            r.read_to_end(&mut v)?;
+            Ok(v)
+        })
+    })
+    .unwrap();
+    assert_eq!(ret, b"\x00\x06body");
+    assert_eq_hex!(buf, b"hdr:\x00\x06body\x00\x00".to_vec());
+}
+
+#[test]
+fn slice_buf() {
+    let mut mem = [0xff_u8; 6];
+    let mut out = slicebuf::SliceBuf::new(&mut mem[..]);
+    let err = write_be(&mut out, |w| {
+        let count = w.write_deferred(0_u16)?;
+        w.write(0xaa_u8)?;
+        w.resolve(count, 1)?;
+        w.seek_forward(1)?;
+        w.write(0xbb_u8)?;
+        assert_eq!(w.position()?, 5);
+        w.write(0xcccc_u16)
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+    assert_eq!(out.len(), 5);
+    assert_eq_hex!(mem, [0x00_u8, 0x01, 0xaa, 0x00, 0xbb, 0xff]);
+}
+
+#[test]
+fn deferred_non_copy() {
+    use endian::Endian;
+    use pack::{FixedLenPack, Pack};
+
+    // A fixed-length type that owns heap data, and so can't be Copy.
+    #[derive(Clone)]
+    struct Tag(Box<[u8; 3]>);
+
+    impl Pack for Tag {
+        fn pack_len(&self) -> usize {
+            <Self as FixedLenPack>::PACK_LEN
+        }
+
+        fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0[..]);
+        }
+    }
+
+    impl FixedLenPack for Tag {
+        const PACK_LEN: usize = 3;
+    }
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let tag = w.write_deferred(Tag(Box::new(*b"???")))?;
+        w.write_placeholder(tag)?;
+        let got = w.resolve(tag, Tag(Box::new(*b"abc")))?;
+        assert_eq!(*got.0, *b"abc");
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, b"abcabc".to_vec());
+}
+
+#[test]
+fn resolve_early() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        let len = w.deferred(0_u8);
+        w.write_placeholder(len)?;
+        w.resolve(len, 3)?;
+        w.write_placeholder(len)?;
+        w.write(&b"abc"[..])?;
+        w.resolve(len, 3)?;
+        w.resolve(len, 4).map(|_| ())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq_hex!(buf, vec![0x03_u8, 0x03, b'a', b'b', b'c']);
+}
+
+#[test]
+fn resolve_conflict() {
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_be(&mut buf, |w| {
+        w.context("header", |w| {
+            let size = w.write_deferred(0_u16)?;
+            w.resolve(size, 0x102)?;
+            w.resolve(size, 0x103).map(|_| ())
+        })
+    })
+    .unwrap_err();
+    let err = error::Error::from_io(&err).unwrap();
+    assert_eq!(err.slot(), Some(0));
+    assert_eq!(err.context(), &["header".to_string()][..]);
+}
+
+#[test]
+fn write_iter() {
+    let mut buf = Vec::<u8>::new();
+    let sizes = write_vec_be(&mut buf, |w| {
+        let plain = w.write_iter((1..=3_u16).map(|v| v * 0x101))?;
+        let separated = w.write_iter_separated(vec![&b"ab"[..], &b"cd"[..], &b"ef"[..]], b',')?;
+        let aligned = w.write_iter_aligned(4, vec![0xaa_u8, 0xbb])?;
+        Ok((plain, separated, aligned))
+    })
+    .unwrap();
+    assert_eq!(sizes, (6, 8, 7));
+    assert_eq_hex!(
+        buf,
+        b"\x01\x01\x02\x02\x03\x03ab,cd,ef\x00\x00\xaa\x00\x00\x00\xbb".to_vec()
+    );
+}
+
+#[test]
+fn write_repeated() {
+    let mut buf = Vec::<u8>::new();
+    let n = write_vec_le(&mut buf, |w| {
+        w.write(0x01_u8)?;
+        let n = w.write_repeated(0xfff8_u16, 3000)?;
+        w.write_repeated(0_u32, 0)?;
+        Ok(n)
+    })
+    .unwrap();
+    assert_eq!(n, 6000);
+    assert_eq!(buf.len(), 6001);
+    assert_eq_hex!(&buf[..5], &[0x01_u8, 0xf8, 0xff, 0xf8, 0xff][..]);
+    assert!(buf[1..].chunks(2).all(|c| c == [0xf8, 0xff]));
+}
+
+#[test]
+fn deferred_byte_array() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let digest = w.write_deferred([0_u8; 16])?;
+        let id = w.write_deferred(*b"????")?;
+        let start = w.position()?;
+        w.write(&b"payload"[..])?;
+        let end = w.position()?;
+        w.resolve(id, *b"ID01")?;
+        w.write_placeholder(id)?;
+        w.derive_deferred(start..end, digest, |r| {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)?;
+            let mut sum = [0_u8; 16];
+            for (i, b) in data.iter().enumerate() {
+                sum[i % 16] ^= *b;
+            }
+            Ok(sum)
+        })
+    })
+    .unwrap();
+    let mut want = b"payload".to_vec();
+    want.resize(16, 0);
+    want.extend_from_slice(b"ID01payloadID01");
+    assert_eq_hex!(buf, want);
+}
+
+#[test]
+fn reserve() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let sig = w.reserve(5)?;
+        w.write(0xaa_u8)?;
+        let err = w.fill_reservation(sig, b"abc").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        w.fill_reservation(sig, b"abcde")
+    })
+    .unwrap();
+    assert_eq_hex!(buf, b"abcde\xaa".to_vec());
+
+    let err = write_vec_le(&mut buf, |w| w.reserve(2).map(|_| ())).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn layout_map() {
+    let mut buf = std::io::Cursor::new(Vec::<u8>::new());
+    let ((), layout) = Builder::<LittleEndian>::new()
+        .write_with_layout(&mut buf, |w| {
+            let size = w.annotate("header", |w| {
+                w.write(&b"HD"[..])?;
+                w.annotate("size", |w| w.write_deferred(0_u16))
+            })?;
+            w.align(4)?;
+            let start = w.position()?;
+            w.annotate("body", |w| w.write(&b"hello"[..]))?;
+            let end = w.position()?;
+            w.resolve(size, (end - start) as u16)?;
+            let spare = w.write_deferred(0_u8)?;
+            w.allow_unresolved(spare);
+            Ok(())
+        })
+        .unwrap();
+    let regions: Vec<_> = layout
+        .regions()
+        .iter()
+        .map(|r| (r.path().join("/"), r.range(), r.alignment()))
+        .collect();
+    assert_eq!(
+        regions,
+        vec![
+            ("header".to_string(), 0..4, 1 << 63),
+            ("header/size".to_string(), 2..4, 2),
+            ("body".to_string(), 4..9, 4),
+        ]
+    );
+    let slots: Vec<_> = layout
+        .slots()
+        .iter()
+        .map(|s| {
+            let ranges: Vec<_> = s.placeholders().iter().map(|r| (r.start, r.end)).collect();
+            (s.index(), ranges, s.resolved())
+        })
+        .collect();
+    assert_eq!(
+        slots,
+        vec![(0, vec![(2, 4)], true), (1, vec![(9, 10)], false)]
+    );
+    assert_eq!(
+        layout.to_string(),
+        "0x00000000..0x00000004        4 header\n\
+         0x00000002..0x00000004        2   size\n\
+         0x00000004..0x00000009        5 body\n\
+         slot 0: 0x2\n\
+         slot 1 (unresolved): 0x9\n"
+    );
+}
+
+#[test]
+fn trace() {
+    #[derive(Clone, Default)]
+    struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    let log = Shared::default();
+    let mut buf = Vec::<u8>::new();
+    Builder::<BigEndian>::new()
+        .trace(log.clone())
+        .write_vec(&mut buf, |w| {
+            let len = w.write_deferred(0_u16)?;
+            w.write(&b"Hello, world!\x00\x01\x02\x03"[..])?;
+            w.resolve(len, 17)?;
+            Ok(())
+        })
+        .unwrap();
+    let log = String::from_utf8(log.0.borrow().clone()).unwrap();
+    assert_eq!(
+        log,
+        "00000000  00 00                                            |..| placeholder for slot 0 (u16)\n\
+         00000002  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00 01 02  |Hello, world!...| &[u8]\n\
+         00000012  03                                               |.|\n\
+         ; resolve slot 0\n\
+         00000000  00 11                                            |..| slot 0\n"
+    );
+}
+
+#[test]
+fn layout_json() {
+    let mut buf = std::io::Cursor::new(Vec::<u8>::new());
+    let ((), layout) = Builder::<BigEndian>::new()
+        .write_with_layout(&mut buf, |w| {
+            w.annotate("file \"a\"", |w| {
+                let len = w.write_deferred(0_u16)?;
+                w.with_endian::<LittleEndian, _, _>(|w| {
+                    w.annotate("body", |w| w.write(1_u32).map(|_| ()))
+                })?;
+                w.resolve(len, 4).map(|_| ())
+            })
+        })
+        .unwrap();
+    let mut json = Vec::new();
+    layout.write_json(&mut json).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        r#"{
+  "regions": [
+    {"name": "file \"a\"", "path": ["file \"a\""], "offset": 0, "size": 6, "endianness": "big"},
+    {"name": "body", "path": ["file \"a\"", "body"], "offset": 2, "size": 4, "endianness": "little"}
+  ],
+  "slots": [
+    {"index": 0, "resolved": true, "placeholders": [{"offset": 0, "size": 2, "endianness": "big"}]}
+  ]
+}
+"#
+    );
+}
+
+#[test]
+fn vaddr() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let entry = w.label();
+        w.write_offset_of::<u32>(entry)?;
+        w.write_vaddr_of::<u32>(entry)?;
+        w.set_vaddr_base(0, 0x400000);
+        w.set_vaddr_base(0x10, 0x601000);
+        assert_eq!(w.vaddr()?, 0x400008);
+        w.align(16)?;
+        w.write(0xaa_u8)?;
+        w.place(entry)?;
+        assert_eq!(w.vaddr()?, 0x601001);
+        w.write(0xbb_u8)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(&buf[..8], &[0x11_u8, 0, 0, 0, 0x01, 0x10, 0x60, 0x00][..]);
+
+    let err = write_vec_le(&mut buf, |w| {
+        let entry = w.label();
+        w.place(entry)?;
+        w.write_vaddr_of::<u32>(entry)?;
+        w.set_vaddr_base(4, 0x1000);
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn relocations() {
+    let mut buf = Vec::<u8>::new();
+    let relocs = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let found = relocs.clone();
+    write_vec_le(&mut buf, |w| {
+        let printf = w.symbol("printf");
+        let local = w.label();
+        w.write(0xe8_u8)?;
+        w.write_reloc::<u32>(printf, 4, -4)?;
+        w.write_reloc::<u16>(local, 1, 2)?;
+        w.place(local)?;
+        let unnamed = w.label();
+        w.write_reloc::<u32>(unnamed, 2, 0)?;
+        w.on_finalize(move |w| {
+            for r in w.relocations() {
+                w.write(r.offset() as u8)?;
+                w.write(r.kind() as u8)?;
+            }
+            *found.borrow_mut() = w.relocations();
+            Ok(())
+        });
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0xe8_u8, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 1, 4, 7, 2]);
+    let relocs: Vec<_> = relocs
+        .borrow()
+        .iter()
+        .map(|r| {
+            (
+                r.offset(),
+                r.len(),
+                r.symbol().map(String::from),
+                r.addend(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        relocs,
+        vec![(1, 4, Some("printf".to_string()), -4), (7, 4, None, 0)]
+    );
+}
+
+#[test]
+fn volumes() {
+    use std::io::Cursor;
+    use volume::Volumes;
+
+    let mut out = Volumes::new(4, |_| Ok(Cursor::new(Vec::<u8>::new())));
+    let sum = write_be(&mut out, |w| {
+        let len = w.write_deferred(0_u16)?;
+        w.write(&b"abcdefg"[..])?;
+        w.resolve(len, 7)?;
+        w.seek_forward(4)?;
+        w.write(0xff_u8)?;
+        w.derive(0..9, |r| {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)?;
+            Ok(data.iter().map(|b| *b as u32).sum::<u32>())
+        })
+    })
+    .unwrap();
+    assert_eq!(sum, 7 + (b'a'..=b'g').map(u32::from).sum::<u32>());
+    assert_eq!(out.len(), 14);
+    assert_eq!(out.position(), (3, 2));
+    let parts: Vec<_> = out
+        .into_volumes()
+        .into_iter()
+        .map(Cursor::into_inner)
+        .collect();
+    assert_eq!(
+        parts,
+        vec![
+            b"\x00\x07ab".to_vec(),
+            b"cdef".to_vec(),
+            b"g\x00\x00\x00".to_vec(),
+            b"\x00\xff".to_vec(),
+        ]
+    );
+}
+
+#[test]
+fn edit() {
+    let mut file = std::io::Cursor::new(b"HDR\x00\x00\x00\x00\x00payload".to_vec());
+    file.set_position(5);
+    edit_be(&mut file, |w| {
+        assert_eq!(w.position()?, 0);
+        assert_eq!(w.read_at::<u8>(2)?, b'R');
+        let sum = w.derive(8..15, |r| {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)?;
+            Ok(data.iter().map(|b| *b as u16).sum::<u16>())
+        })?;
+        w.at(4, |w| {
+            w.write(sum)?;
+            w.write(7_u8)
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        file.into_inner(),
+        b"HDR\x00\x02\xea\x07\x00payload".to_vec()
+    );
+}
+
+#[test]
+fn append_registry() {
+    use registry::Registry;
+
+    let mut file = std::io::Cursor::new(b"MAGIC".to_vec());
+    let mut saved = Vec::new();
+    append_be(&mut file, |w| {
+        let rec = w.symbol("rec0");
+        w.place(rec)?;
+        w.write(&b"first"[..])?;
+        let unplaced = w.symbol("later");
+        w.write_reloc::<u16>(unplaced, 0, 0)?;
+        w.export_symbols().save(&mut saved)
+    })
+    .unwrap();
+
+    let registry = Registry::load(&saved[..]).unwrap();
+    assert_eq!(registry.iter().collect::<Vec<_>>(), vec![("rec0", 5)]);
+    append_be(&mut file, |w| {
+        w.import_symbols(&registry)?;
+        let prev = w.symbol("rec0");
+        w.write_offset_of::<u16>(prev)?;
+        let err = w.place(prev).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(file.into_inner(), b"MAGICfirst\x00\x00\x00\x05".to_vec());
+
+    let err = Registry::load(&saved[..saved.len() - 1]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn max_len() {
+    let mut buf = Vec::<u8>::new();
+    let err = Builder::<LittleEndian>::new()
+        .max_len(6)
+        .write_vec(&mut buf, |w| {
+            let size = w.write_deferred(0_u16)?;
+            w.write(1_u32)?;
+            w.resolve(size, 4)?;
+            w.write(0_u8)
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq_hex!(buf, vec![0x04_u8, 0x00, 0x01, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn max_len_nested() {
+    let mut buf = Vec::<u8>::new();
+    let err = Builder::<LittleEndian>::new()
+        .max_len(4)
+        .write_vec(&mut buf, |w| {
+            w.with_endian::<BigEndian, _, _>(|w| {
+                w.write(0_u64)?;
+                Ok(())
+            })
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(buf.is_empty());
+
+    let mut buf = Vec::<u8>::new();
+    let err = Builder::<LittleEndian>::new()
+        .max_len(4)
+        .write_vec(&mut buf, |w| {
+            w.write(0_u16)?;
+            w.subregion_limited(8, |w| w.write(0_u32))?;
+            Ok(())
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(buf.len(), 2);
+}
+
+#[test]
+fn bool_and_char() {
+    use pack::{bool_as, CodePoint};
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(true)?;
+        w.write(false)?;
+        w.write(bool_as(true, 0xffff_u16, 0))?;
+        w.write(bool_as(false, 0xffff_u16, 0))?;
+        w.write('a')?;
+        w.write('é')?;
+        w.write(CodePoint('é'))?;
+        let flag = w.write_deferred(false)?;
+        w.resolve(flag, true)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x01_u8, 0x00, 0xff, 0xff, 0x00, 0x00, b'a', 0xc3, 0xa9, 0x00, 0x00, 0x00, 0xe9, 0x01,
+        ]
+    );
+
+    let mut r = Reader::<_, LittleEndian>::new(std::io::Cursor::new(vec![0x00_u8, 0x02]));
+    assert!(!r.read::<bool>().unwrap());
+    assert!(r.read::<bool>().unwrap());
+}
+
+#[test]
+fn net_addrs() {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+    let v4 = Ipv4Addr::new(192, 168, 0, 1);
+    let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(v4)?;
+        w.write(SocketAddrV4::new(v4, 0x1234))?;
+        w.write(pack::as_big_endian(SocketAddrV4::new(v4, 0x1234)))?;
+        w.write(SocketAddrV6::new(v6, 80, 0, 0))?;
+        Ok(())
+    })
+    .unwrap();
+    let mut want = vec![0xc0_u8, 0xa8, 0x00, 0x01];
+    want.extend_from_slice(&[0xc0, 0xa8, 0x00, 0x01, 0x34, 0x12]);
+    want.extend_from_slice(&[0xc0, 0xa8, 0x00, 0x01, 0x12, 0x34]);
+    want.extend_from_slice(&v6.octets());
+    want.extend_from_slice(&[80, 0]);
+    assert_eq_hex!(buf, want);
+
+    let mut r = Reader::<_, LittleEndian>::new(std::io::Cursor::new(buf));
+    assert_eq!(r.read::<Ipv4Addr>().unwrap(), v4);
+    r.skip(12).unwrap();
+    assert_eq!(r.read::<Ipv6Addr>().unwrap(), v6);
+}
+
+#[test]
+fn timestamps() {
+    use std::convert::TryFrom;
+    use std::time::{Duration, UNIX_EPOCH};
+    use timestamp::*;
+
+    // 2023-06-15 12:34:56.5 UTC
+    let t = UNIX_EPOCH + Duration::from_millis(1_686_832_496_500);
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(UnixTime32::try_from(t).unwrap())?;
+        w.write(UnixTime64::try_from(UNIX_EPOCH - Duration::from_millis(500)).unwrap())?;
+        let mtime = w.write_deferred(DosDateTime::default())?;
+        w.write(FileTime::try_from(t).unwrap())?;
+        w.resolve(mtime, DosDateTime::try_from(t).unwrap())?;
+        Ok(())
+    })
+    .unwrap();
+    let mut want = 1_686_832_496_u32.to_le_bytes().to_vec();
+    want.extend_from_slice(&(-1_i64).to_le_bytes());
+    want.extend_from_slice(&[0x5c, 0x64, 0xcf, 0x56]);
+    want.extend_from_slice(&(0x1d99f85ca70d800_u64 + 5_000_000).to_le_bytes());
+    assert_eq_hex!(buf, want);
+
+    assert!(UnixTime32::try_from(UNIX_EPOCH - Duration::from_secs(1)).is_err());
+    assert!(DosDateTime::try_from(UNIX_EPOCH).is_err());
+    assert_eq!(
+        FileTime::try_from(UNIX_EPOCH).unwrap(),
+        FileTime(116_444_736_000_000_000)
+    );
+}
+
+#[test]
+fn uuids() {
+    use pack::{Guid, Uuid};
+    let id = 0x00112233_4455_6677_8899_aabbccddeeff_u128;
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(Uuid(id))?;
+        w.write(Guid(id))?;
+        Ok(())
+    })
+    .unwrap();
+    let mut be = Vec::<u8>::new();
+    write_vec_be(&mut be, |w| {
+        w.write(Uuid(id))?;
+        w.write(Guid(id))?;
+        Ok(())
+    })
+    .unwrap();
+    let mut want = id.to_be_bytes().to_vec();
+    want.extend_from_slice(&[
+        0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ]);
+    assert_eq_hex!(buf, want);
+    assert_eq_hex!(be, want);
+
+    let mut r = Reader::<_, BigEndian>::new(std::io::Cursor::new(buf));
+    assert_eq!(r.read::<Uuid>().unwrap(), Uuid(id));
+    assert_eq!(r.read::<Guid>().unwrap(), Guid(id));
+}
+
+#[test]
+fn fixed_point() {
+    use fixed::{F2Dot14, Fixed, Fixed16Dot16};
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(F2Dot14::from_f64(1.5))?;
+        w.write(F2Dot14::from_f64(-0.5))?;
+        w.write(Fixed16Dot16::from_f32(1.25))?;
+        w.write(Fixed::<u8, 4>::from_f64(100.0))?;
+        w.write(Fixed::<u8, 4>::from_f64(-1.0))?;
+        let scale = w.write_deferred(Fixed16Dot16::default())?;
+        w.resolve(scale, 0.5.into())?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0x60_u8, 0x00, 0xe0, 0x00, 0x00, 0x01, 0x40, 0x00, 0xff, 0x00, 0x00, 0x00, 0x80, 0x00,
+        ]
+    );
+    assert_eq!(Fixed::<i16, 14>(0x7000).to_f64(), 1.75);
+    assert_eq!(Fixed16Dot16::from_f64(1.0 / 65536.0 / 3.0), Fixed(0));
+}
+
+#[test]
+fn half_floats() {
+    use float::{Bf16, F16};
+    let cases = [
+        (1.0_f32, 0x3c00_u16),
+        (-2.0, 0xc000),
+        (0.1, 0x2e66),
+        (65504.0, 0x7bff),
+        (65520.0, 0x7c00),
+        (f32::NEG_INFINITY, 0xfc00),
+        (2_f32.powi(-24), 0x0001),
+        (2_f32.powi(-26), 0x0000),
+        (6.1e-5, 0x03ff),
+        (6.104e-5, 0x0400),
+    ];
+    for (v, want) in cases.iter() {
+        assert_eq!(F16::from_f32(*v), F16(*want), "converting {}", v);
+    }
+    assert_eq!(F16(0x2e66).to_f32(), 0.099975586);
+    assert_eq!(F16(0x0001).to_f32(), 2_f32.powi(-24));
+    assert!(F16::from_f32(f32::NAN).to_f32().is_nan());
+    assert_eq!(Bf16::from_f32(std::f32::consts::PI), Bf16(0x4049));
+    assert_eq!(Bf16::from_f32(1.0).to_f32(), 1.0);
+    assert!(Bf16::from_f32(f32::NAN).to_f32().is_nan());
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(F16::from_f32(1.0))?;
+        w.write(Bf16::from_f32(1.0))?;
+        w.write_slice(&[F16(0x1234)][..])?;
+        w.write_slice_as::<F16, _>(&[-2.0_f32, 0.1][..])?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![0x3c_u8, 0x00, 0x3f, 0x80, 0x12, 0x34, 0xc0, 0x00, 0x2e, 0x66]
+    );
+}
+
+#[test]
+fn bcd() {
+    use bcd::{Bcd, UnpackedBcd};
+    use std::convert::TryFrom;
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(Bcd::<3>::try_from(12345).unwrap())?;
+        w.write(UnpackedBcd::<4>::try_from(907).unwrap())?;
+        let count = w.write_deferred(Bcd::<1>::default())?;
+        w.resolve(count, Bcd::try_from(99).unwrap())?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x23, 0x45, 0x00, 0x09, 0x00, 0x07, 0x99]);
+    assert!(Bcd::<1>::try_from(100).is_err());
+    assert!(UnpackedBcd::<2>::try_from(100).is_err());
+    assert!(Bcd::<10>::try_from(u64::MAX).is_ok());
+
+    let mut r = Reader::<_, BigEndian>::new(std::io::Cursor::new(buf));
+    assert_eq!(r.read::<Bcd<3>>().unwrap().value(), 12345);
+    assert_eq!(r.read::<UnpackedBcd<4>>().unwrap().value(), 907);
+}
+
+#[test]
+fn bitfields() {
+    use bitfield::Bitfield;
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        let flags = Bitfield::<u32>::new()
+            .set(0..4, 0xa)?
+            .set_flag(4, true)?
+            .set(28..32, 0xf)?;
+        assert_eq!(flags.get(0..5), Some(0x1a));
+        w.write(flags)?;
+        w.write(flags.set_flag(4, false)?.set(0..4, 1)?)?;
+        w.write(Bitfield::<u8>::new().set(0..8, 0xff)?)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![0x1a_u8, 0x00, 0x00, 0xf0, 0x01, 0x00, 0x00, 0xf0, 0xff]
+    );
+
+    let f = Bitfield::<u16>::new();
+    assert!(f.set(0..4, 16).is_err());
+    assert!(f.set(8..17, 0).is_err());
+    assert!(f.set(4..4, 0).is_err());
+    assert!(f.set_flag(16, true).is_err());
+    assert_eq!(f.get(0..17), None);
+}
+
+#[test]
+fn pack_enum() {
+    use pack::PackEnum;
+    crate::pack_enum! {
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        enum RecordType: u16 {
+            Header = 1,
+            /// Doc comments on variants are allowed.
+            Data = 0x0100,
+        }
+    }
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(RecordType::Data)?;
+        w.write(RecordType::Header)?;
+        let kind = w.write_deferred(RecordType::Header)?;
+        w.resolve(kind, RecordType::Data)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(buf, vec![0x01_u8, 0x00, 0x00, 0x01, 0x01, 0x00]);
+    assert_eq!(RecordType::Data.to_repr(), 0x100);
+    assert_eq!(RecordType::from_repr(1), Some(RecordType::Header));
+    assert_eq!(RecordType::from_repr(2), None);
+}
+
+#[test]
+fn write_cstr_padded() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(1_u8)?;
+        assert_eq!(w.write_cstr_padded("GNU", 4)?, 4);
+        assert_eq!(w.write_cstr_padded(b"abcd", 4)?, 8);
+        assert_eq!(w.write_cstr_padded("", 8)?, 8);
+        assert!(w.write_cstr_padded("a\0b", 4).is_err());
+        Ok(())
+    })
+    .unwrap();
+    let mut want = vec![0x01_u8];
+    want.extend_from_slice(b"GNU\0abcd\0\0\0\0");
+    want.extend_from_slice(&[0; 8]);
+    assert_eq_hex!(buf, want);
+}
+
+#[test]
+fn write_struct() {
+    let mut buf = Vec::<u8>::new();
+    let name = "abc";
+    let count = 2;
+    write_vec_be(&mut buf, |w| {
+        crate::write_struct!(w, {
+            magic: u16 = 0xfeed,
+            count: u8 = count,
+            size: u32 = defer,
+            name: cstr = name,
+            _pad: align(4),
+            checksum: u16 = defer,
+        });
+        w.resolve(size, 14)?;
+        w.resolve(checksum, 0x1111)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![
+            0xfe_u8, 0xed, 0x02, 0x00, 0x00, 0x00, 0x0e, b'a', b'b', b'c', 0x00, 0x00, 0x11, 0x11,
+        ]
+    );
+}
+
+#[test]
+fn derive_seek_bufread() {
+    use std::io::{BufRead, Seek, SeekFrom};
+    let mut buf = std::io::Cursor::new(Vec::<u8>::new());
+    write_le(&mut buf, |w| {
+        w.write(&b"xxline one\nline two\nyy"[..])?;
+        let lines = w.derive(2..20, |r| {
+            assert_eq!(r.len(), 18);
+            let mut first = [0_u8; 4];
+            r.read_exact(&mut first)?;
+            assert_eq!(&first, b"line");
+            assert_eq!(r.fill_buf()?, b" one\nline two\n");
+            r.consume(1);
+            assert_eq!(r.stream_position()?, 5);
+            assert_eq!(r.seek(SeekFrom::End(-4))?, 14);
+            let mut tail = Vec::new();
+            r.read_to_end(&mut tail)?;
+            assert_eq!(tail, b"two\n");
+            assert_eq!(r.seek(SeekFrom::Current(100))?, 18);
+            assert!(r.seek(SeekFrom::Current(-19)).is_err());
+            r.seek(SeekFrom::Start(0))?;
+            r.lines().collect::<Result<Vec<_>>>()
+        })?;
+        assert_eq!(lines, vec!["line one", "line two"]);
+        assert_eq!(w.position()?, 22);
+
+        let tail = w.write_deferred(0_u8)?;
+        w.derive_deferred(0..4, tail, |r| {
+            r.seek(SeekFrom::Start(3))?;
+            let mut b = [0_u8];
+            r.read_exact(&mut b)?;
+            Ok(b[0])
+        })?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(buf.into_inner(), b"xxline one\nline two\nyyi");
+}
+
+#[test]
+fn writer_seek() {
+    use std::io::{Seek, SeekFrom};
+
+    // A stand-in for a library function that needs Write + Seek.
+    fn encode<S: Write + Seek>(s: &mut S) -> Result<()> {
+        let start = s.stream_position()?;
+        s.write_all(b"\0\0data")?;
+        let end = s.stream_position()?;
+        s.seek(SeekFrom::Start(start))?;
+        s.write_all(&[(end - start) as u8])?;
+        s.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    let mut buf = std::io::Cursor::new(b"old".to_vec());
+    append_le(&mut buf, |w| {
+        let len = w.write_deferred(0_u8)?;
+        w.subregion_limited(8, |w| {
+            encode(w)?;
+            assert!(w.seek(SeekFrom::Current(-7)).is_err());
+            Ok(())
+        })?;
+        encode(w)?;
+        assert_eq!(w.seek(SeekFrom::Current(-1))?, 15);
+        assert!(w.seek(SeekFrom::Start(2)).is_err());
+        w.seek(SeekFrom::Start(3))?;
+        w.write(0xff_u8)?;
+        let end = w.seek(SeekFrom::End(0))?;
+        w.resolve(len, end as u8)
+    })
+    .unwrap();
+    assert_eq!(buf.into_inner(), b"old\x10\x06\0data\x06\0data");
+}
+
+#[test]
+fn new_and_finish() {
+    use std::io::Cursor;
+    let mut w = Writer::new_be(Cursor::new(Vec::<u8>::new())).unwrap();
+    let len = w.write_deferred(0_u16).unwrap();
+    w.write(&b"abc"[..]).unwrap();
+    assert_eq!(w.get_mut().unwrap().get_ref().len(), 5);
+    let pos = w.position().unwrap();
+    w.resolve(len, pos as u16).unwrap();
+    assert_eq!(w.finish().unwrap().into_inner(), b"\x00\x05abc");
+
+    let mut w = Builder::<LittleEndian>::new()
+        .write_buffer(64)
+        .build(Cursor::new(Vec::<u8>::new()))
+        .unwrap();
+    w.write(1_u16).unwrap();
+    w.write_deferred(0_u8).unwrap();
+    let err = w.finish().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let w = Writer::new_le(Cursor::new(Vec::<u8>::new())).unwrap();
+    assert_eq!(w.finish().unwrap().into_inner(), b"");
+}
+
+#[test]
+fn write_range() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        assert_eq!(w.write_range(1_u8)?, 0..1);
+        assert_eq!(w.align_range(4)?, 1..4);
+        assert_eq!(w.align_range(4)?, 4..4);
+        let size = w.deferred(0_u16);
+        assert_eq!(w.write_placeholder_range(size)?, 4..6);
+        let data = w.write_range(&b"abc"[..])?;
+        assert_eq!(data, 6..9);
+        w.resolve(size, data.end as u16)?;
+        let (_, rng) = w.buffered(|w| w.write_range(2_u32))?;
+        assert_eq!(rng, 0..4);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        vec![0x01_u8, 0x00, 0x00, 0x00, 0x09, 0x00, b'a', b'b', b'c']
+    );
+}
+
+#[test]
+fn trailers() {
+    let mut buf = std::io::Cursor::new(Vec::<u8>::new());
+    write_be(&mut buf, |w| {
+        let ((), len) = w.with_trailer(
+            |w| {
+                w.write(&b"abc"[..])?;
+                Ok(())
+            },
+            |w, rng| {
+                let sum = w.derive(rng.clone(), |r| {
+                    Ok(r.bytes().map(|b| b.unwrap()).fold(0, u8::wrapping_add))
+                })?;
+                w.write(sum)?;
+                Ok(rng.end - rng.start)
+            },
+        )?;
+        assert_eq!(len, 3);
+
+        let (size, _) = w.with_derived_trailer(
+            0_u16,
+            |w| {
+                let size = w.write_deferred(0_u8)?;
+                w.write(&b"xy"[..])?;
+                Ok(size)
+            },
+            |r| {
+                let mut sum = 0_u16;
+                for b in r.bytes() {
+                    sum += b? as u16;
+                }
+                Ok(sum)
+            },
+        )?;
+        w.resolve(size, 2)?;
+        Ok(())
+    })
+    .unwrap();
+    let sum = (b'x' as u16 + b'y' as u16 + 2).to_be_bytes();
+    assert_eq_hex!(
+        buf.into_inner(),
+        vec![b'a', b'b', b'c', 0x26, 0x02, b'x', b'y', sum[0], sum[1]]
+    );
+}
+
+#[test]
+fn progress() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let reports = Rc::new(RefCell::new(Vec::<(u64, Option<String>)>::new()));
+    let log = reports.clone();
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.set_progress(4, move |n, name| {
+            log.borrow_mut().push((n, name.map(str::to_string)));
+        });
+        let size = w.write_deferred(0_u32)?;
+        w.annotate("body", |w| {
+            w.copy_from(&[1_u8; 6][..], None)?;
+            Ok(())
+        })?;
+        w.with_endian::<BigEndian, _, _>(|w| w.write(2_u16))?;
+        w.resolve(size, 12)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        *reports.borrow(),
+        vec![(4, None), (10, Some("body".to_string())), (16, None),]
+    );
+}
+
+#[test]
+fn cancel() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let token = Arc::new(AtomicBool::new(false));
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.set_cancel(token.clone());
+        w.write(1_u8)?;
+        w.context("body", |w| {
+            w.write(2_u8)?;
+            token.store(true, Ordering::Relaxed);
+            w.copy_from(&[3_u8; 10][..], None)?;
+            Ok(())
+        })
+    })
+    .unwrap_err();
+    assert!(Error::is_cancelled(&err));
+    assert_eq!(Error::from_io(&err).unwrap().context(), ["body"]);
+    assert_eq!(buf, vec![1, 2]);
+    assert!(!Error::is_cancelled(&std::io::Error::from(
+        std::io::ErrorKind::Other
+    )));
+
+    // Cancelling also stops finalization from updating placeholders.
+    let token = Arc::new(AtomicBool::new(false));
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.set_cancel(token.clone());
+        let size = w.write_deferred(0_u8)?;
+        w.on_finalize(move |w| {
+            w.resolve(size, 1_u8)?;
+            Ok(())
+        });
+        token.store(true, Ordering::Relaxed);
+        Ok(())
+    })
+    .unwrap_err();
+    assert!(Error::is_cancelled(&err));
+    assert_eq!(buf, vec![0]);
+}
+
+#[test]
+fn testing_helpers() {
+    use crate::testing::*;
+
+    let buf = capture_vec_be(|w| {
+        w.write(&b"HD"[..])?;
+        w.write(0x0102_u16)?;
+        Ok(())
+    });
+    assert_bytes_eq(&buf, [b'H', b'D', 0x01, 0x02]);
+
+    assert_eq!(hex_diff(&[1, 2], &[1, 2]), None);
+    assert_eq!(
+        hex_diff(&[1, 2, 3], &[1, 4]).unwrap(),
+        "expected 3 bytes, but got 2 bytes\n-00000000  01 02 03\n+00000000  01 04\n"
+    );
+    let result = std::panic::catch_unwind(|| assert_bytes_eq([1_u8], [2_u8]));
+    assert!(result.is_err());
+
+    let (buf, layout) = capture_with_layout::<LittleEndian, _>(|w| {
+        let len = w.write_deferred(0_u32)?;
+        w.with_endian::<BigEndian, _, _>(|w| w.write_placeholder(len))?;
+        w.write(&b"body"[..])?;
+        w.resolve(len, 4_u32)?;
+        Ok(())
+    });
+    assert_eq_hex!(
+        buf,
+        [0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, b'b', b'o', b'd', b'y']
+    );
+    assert_slot(&buf, &layout, 0, 4_u32);
+    let result = std::panic::catch_unwind(|| assert_slot(&buf, &layout, 0, 5_u32));
+    assert!(result.is_err());
+}
+
+#[test]
+fn packed() {
+    use crate::pack::Packed;
+
+    fn header(kind: u8) -> std::io::Result<Packed> {
+        Packed::build_le(|w| {
+            let len = w.write_deferred(0_u16)?;
+            w.write(kind)?;
+            w.align(4)?;
+            let pos = w.position()?;
+            w.resolve(len, pos as u16)?;
+            Ok(())
+        })
+    }
+
+    let hdr = header(7).unwrap();
+    assert_eq!(hdr.len(), 4);
+    assert_eq_hex!(hdr.as_bytes(), [0x04, 0x00, 0x07, 0x00]);
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_be(&mut buf, |w| {
+        w.write(0xff_u8)?;
+        w.write(&hdr)?;
+        w.write(header(9)?)?;
+        w.write(1_u16)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        [0xff, 0x04, 0x00, 0x07, 0x00, 0x04, 0x00, 0x09, 0x00, 0x00, 0x01]
+    );
+
+    assert!(Packed::build_be(|w| w.write_deferred(0_u8).map(|_| ())).is_err());
+    assert!(Packed::default().is_empty());
+}
+
+#[test]
+fn c_struct() {
+    use crate::abi::Abi;
+
+    fn body<W: std::io::Write + std::io::Seek>(
+        w: &mut Writer<W, LittleEndian>,
+        abi: Abi,
+    ) -> Result<()> {
+        // struct { char a; double b; uint16_t c[3]; }
+        w.write(0xff_u8)?;
+        w.c_struct(abi, |s| {
+            s.field(1_u8)?;
+            let b = s.field_deferred(0_u64)?;
+            s.field([3_u16, 4, 5])?;
+            assert_eq!(s.alignment(), abi.scalar_align(8));
+            s.writer().resolve(b, 2)?;
+            Ok(())
+        })?;
+        w.write(0xee_u8)?;
+        Ok(())
+    }
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| body(w, Abi::SystemV64)).unwrap();
+    assert_eq_hex!(
+        buf,
+        [
+            0xff, // before the struct
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // a, padding
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // b
+            0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00, // c, padding
+            0xee, // after the struct
+        ]
+    );
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| body(w, Abi::SystemVI386)).unwrap();
+    assert_eq_hex!(
+        buf,
+        [
+            0xff, // before the struct
+            0x01, 0x00, 0x00, 0x00, // a, padding
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // b
+            0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x00, 0x00, // c, padding
+            0xee, // after the struct
+        ]
+    );
+
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| body(w, Abi::Packed)).unwrap();
+    assert_eq!(buf.len(), 1 + 1 + 8 + 6 + 1);
+}
+
+#[test]
+#[allow(clippy::redundant_closure)]
+fn storage() {
+    use crate::storage::{StdStorage, Storage, StorageStream};
+
+    fn body<W: std::io::Read + std::io::Write + std::io::Seek>(
+        w: &mut Writer<W, BigEndian>,
+    ) -> Result<()> {
+        let len = w.write_deferred(0_u16)?;
+        w.write(&b"abc"[..])?;
+        w.align(8)?;
+        w.write(1_u8)?;
+        w.resolve(len, 3)?;
+        let sum = w.derive(0..5, |r| {
+            let mut buf = Vec::new();
+            std::io::Read::read_to_end(r, &mut buf)?;
+            Ok(buf.iter().map(|b| *b as u32).sum::<u32>() as u8)
+        })?;
+        w.write(sum)?;
+        Ok(())
+    }
+    let want = [0x00, 0x03, b'a', b'b', b'c', 0x00, 0x00, 0x00, 0x01, 0x29];
+
+    let mut out = StorageStream::new(Vec::<u8>::new());
+    write_be(&mut out, |w| body(w)).unwrap();
+    assert_eq_hex!(out.into_inner(), want);
+
+    let mut out = StorageStream::new(StdStorage::new(std::io::Cursor::new(Vec::new())));
+    write_be(&mut out, |w| body(w)).unwrap();
+    assert_eq_hex!(out.into_inner().into_inner().into_inner(), want);
+
+    let mut mem = [0xff_u8; 12];
+    let mut slice = slicebuf::SliceBuf::new(&mut mem[..]);
+    write_be(&mut StorageStream::new(&mut slice), |w| body(w)).unwrap();
+    assert_eq!(slice.len(), 10);
+    assert_eq_hex!(slice.written(), want);
+
+    let mut v = vec![1_u8];
+    v.write_at(3, &[2]).unwrap();
+    assert_eq!(v, [1, 0, 0, 2]);
+    let mut buf = [0_u8; 4];
+    assert_eq!(v.read_at(2, &mut buf).unwrap(), 2);
+    assert_eq!(v.read_at(9, &mut buf).unwrap(), 0);
+}
+
+#[test]
+fn relative_offsets() {
+    let mut buf = Vec::<u8>::new();
+    write_vec_le(&mut buf, |w| {
+        w.write(&b"FILE"[..])?;
+        let chunk = w.position()?;
+        w.write(&b"CHNK"[..])?;
+        let fwd = w.write_deferred_offset::<u16>(chunk)?;
+        let back = w.write_deferred_offset::<i16>(chunk + 12)?;
+        let plain = w.write_deferred(0_u8)?;
+        assert_eq!(fwd.base(), 4);
+        w.resolve_offset(back)?;
+        w.write(0xee_u8)?;
+        assert_eq!(w.resolve_offset(fwd)?, 10);
+        w.resolve_offset_from(plain, 0)?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq_hex!(
+        buf,
+        [
+            b'F', b'I', b'L', b'E', b'C', b'H', b'N', b'K', // file and chunk magic
+            0x0a, 0x00, // fwd: 14 - 4
+            0xfd, 0xff, // back: 13 - 16
+            0x0e, // plain: 14 - 0
+            0xee,
+        ]
+    );
+
+    // An offset before the base doesn't fit in an unsigned type.
+    let mut buf = Vec::<u8>::new();
+    let err = write_vec_le(&mut buf, |w| {
+        w.write(0_u32)?;
+        let off = w.write_deferred_offset::<u8>(8)?;
+        w.resolve_offset(off)?;
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
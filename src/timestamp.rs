@@ -0,0 +1,221 @@
+use std::convert::TryFrom;
+use std::num::TryFromIntError;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::endian::Endian;
+use crate::pack::{out_of_range_error, FixedLenPack, Pack};
+use crate::unpack::Unpack;
+
+/// Packs a time as a 32-bit unsigned number of seconds since the Unix
+/// epoch, as in classic `time_t` fields, tar headers, and many others.
+///
+/// Conversion from [`SystemTime`](std::time::SystemTime) discards any
+/// fraction of a second, and fails for times before 1970 or after early
+/// 2106.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTime32(pub u32);
+
+/// Packs a time as a 64-bit signed number of seconds since the Unix epoch,
+/// where negative numbers represent times before 1970.
+///
+/// Conversion from [`SystemTime`](std::time::SystemTime) rounds down to
+/// the nearest whole second.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTime64(pub i64);
+
+/// Packs a time as a Windows `FILETIME`, which is a 64-bit unsigned number
+/// of 100-nanosecond intervals since the start of 1601.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTime(pub u64);
+
+/// Packs a time as an MS-DOS date and time, as used in FAT directory entries
+/// and ZIP archives.
+///
+/// The two 16-bit parts pack together as a 32-bit integer with the date in
+/// the most significant half, so a little-endian writer produces the time
+/// followed by the date, matching both of those formats.
+///
+/// DOS times have no time zone, and are usually in local time. Conversion
+/// from [`SystemTime`](std::time::SystemTime) uses UTC, rounds down to an
+/// even number of seconds, and fails for times outside of the years 1980
+/// through 2107.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DosDateTime {
+    /// The date, with the year since 1980 in bits 9 through 15, the month in
+    /// bits 5 through 8, and the day of the month in bits 0 through 4.
+    pub date: u16,
+
+    /// The time, with the hour in bits 11 through 15, the minute in bits 5
+    /// through 10, and the number of seconds divided by two in bits 0
+    /// through 4.
+    pub time: u16,
+}
+
+/// The number of seconds between the start of 1601 and the Unix epoch.
+const FILETIME_EPOCH_OFFSET: i128 = 11_644_473_600;
+
+/// Returns the number of whole nanoseconds between the Unix epoch and the
+/// given time, which is negative for times before the epoch.
+fn unix_nanos(t: SystemTime) -> i128 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i128,
+        Err(err) => -(err.duration().as_nanos() as i128),
+    }
+}
+
+/// Returns the number of whole seconds between the Unix epoch and the given
+/// time, rounding down.
+fn unix_secs(t: SystemTime) -> i128 {
+    unix_nanos(t).div_euclid(1_000_000_000)
+}
+
+/// Converts a number of days since the Unix epoch into a year, month, and
+/// day in the proleptic Gregorian calendar.
+fn civil_from_days(days: i128) -> (i128, u32, u32) {
+    // This is Howard Hinnant's "civil_from_days" algorithm, which works in
+    // 400-year eras beginning on the first of March.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl TryFrom<SystemTime> for UnixTime32 {
+    type Error = TryFromIntError;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        Ok(Self(u32::try_from(unix_secs(t))?))
+    }
+}
+
+impl TryFrom<SystemTime> for UnixTime64 {
+    type Error = TryFromIntError;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        Ok(Self(i64::try_from(unix_secs(t))?))
+    }
+}
+
+impl TryFrom<SystemTime> for FileTime {
+    type Error = TryFromIntError;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        let ticks = unix_nanos(t).div_euclid(100) + FILETIME_EPOCH_OFFSET * 10_000_000;
+        Ok(Self(u64::try_from(ticks)?))
+    }
+}
+
+impl TryFrom<SystemTime> for DosDateTime {
+    type Error = TryFromIntError;
+
+    fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+        let secs = unix_secs(t);
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        let secs = secs.rem_euclid(86400) as u16;
+        if !(1980..=2107).contains(&year) {
+            return Err(out_of_range_error());
+        }
+        Ok(Self {
+            date: ((year - 1980) as u16) << 9 | (month as u16) << 5 | day as u16,
+            time: (secs / 3600) << 11 | (secs / 60 % 60) << 5 | (secs % 60 / 2),
+        })
+    }
+}
+
+impl Pack for UnixTime32 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for UnixTime32 {
+    const PACK_LEN: usize = <u32 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for UnixTime32 {
+    const UNPACK_LEN: usize = <u32 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(u32::unpack_from_slice::<E>(buf))
+    }
+}
+
+impl Pack for UnixTime64 {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for UnixTime64 {
+    const PACK_LEN: usize = <i64 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for UnixTime64 {
+    const UNPACK_LEN: usize = <i64 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(i64::unpack_from_slice::<E>(buf))
+    }
+}
+
+impl Pack for FileTime {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.0.pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for FileTime {
+    const PACK_LEN: usize = <u64 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for FileTime {
+    const UNPACK_LEN: usize = <u64 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(u64::unpack_from_slice::<E>(buf))
+    }
+}
+
+impl Pack for DosDateTime {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        ((self.date as u32) << 16 | self.time as u32).pack_into_slice::<E>(buf)
+    }
+}
+
+impl FixedLenPack for DosDateTime {
+    const PACK_LEN: usize = <u32 as FixedLenPack>::PACK_LEN;
+}
+
+impl Unpack for DosDateTime {
+    const UNPACK_LEN: usize = <u32 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let v = u32::unpack_from_slice::<E>(buf);
+        Self {
+            date: (v >> 16) as u16,
+            time: v as u16,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// A shared destination for the trace selected using
+/// [`Builder::trace`](crate::Builder::trace), which can be handed to each
+/// of the streams of a writer and its nested writers.
+#[derive(Clone)]
+pub(crate) struct Tracer {
+    sink: Rc<RefCell<dyn Write>>,
+}
+
+impl Tracer {
+    pub(crate) fn new<T: Write + 'static>(sink: T) -> Self {
+        Self {
+            sink: Rc::new(RefCell::new(sink)),
+        }
+    }
+
+    /// Logs a write of the given bytes as a hexdump, with up to sixteen
+    /// bytes per line. The label, if any, describes what was written and
+    /// appears on the first line only.
+    pub(crate) fn write(
+        &self,
+        pos: Option<u64>,
+        data: &[u8],
+        label: Option<&str>,
+    ) -> std::io::Result<()> {
+        let mut sink = self.sink.borrow_mut();
+        let mut label = label;
+        for (i, line) in data.chunks(16).enumerate() {
+            match pos {
+                Some(pos) => write!(sink, "{:08x} ", pos + (i * 16) as u64)?,
+                None => write!(sink, "???????? ")?,
+            }
+            for b in line {
+                write!(sink, " {:02x}", b)?;
+            }
+            write!(sink, "{:width$}  |", "", width = (16 - line.len()) * 3)?;
+            for b in line {
+                let c = if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                };
+                write!(sink, "{}", c)?;
+            }
+            write!(sink, "|")?;
+            match label.take() {
+                Some(label) => writeln!(sink, " {}", label)?,
+                None => writeln!(sink)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Logs an event that doesn't directly write anything, such as
+    /// resolving a deferred slot.
+    pub(crate) fn event(&self, msg: Cow<'_, str>) -> std::io::Result<()> {
+        writeln!(self.sink.borrow_mut(), "; {}", msg)
+    }
+}
+
+impl std::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Tracer")
+    }
+}
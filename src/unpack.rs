@@ -0,0 +1,225 @@
+use crate::Endian;
+
+/// Trait implemented by types that can be decoded from a fixed-length
+/// sequence of bytes read from a file.
+///
+/// This is the reading counterpart of [`FixedLenPack`](crate::pack::FixedLenPack),
+/// and so it's implemented for the same primitive types so that a format
+/// can be both written and read using the same field types.
+pub trait Unpack: Sized {
+    /// The number of bytes that must be read to produce a value of this type.
+    const UNPACK_LEN: usize;
+
+    /// Decodes a value from the given slice, whose length must match
+    /// [`Unpack::UNPACK_LEN`](Self::UNPACK_LEN).
+    ///
+    /// If given a slice of the wrong length, the behavior is undefined,
+    /// including possible panics.
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self;
+}
+
+impl Unpack for u8 {
+    const UNPACK_LEN: usize = std::mem::size_of::<Self>();
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        // Endian doesn't matter for only one byte!
+        buf[0]
+    }
+}
+
+impl Unpack for i8 {
+    const UNPACK_LEN: usize = <u8 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        u8::unpack_from_slice::<E>(buf) as i8
+    }
+}
+
+impl Unpack for u16 {
+    const UNPACK_LEN: usize = std::mem::size_of::<Self>();
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        E::read_integer(&buf[0..2]) as u16
+    }
+}
+
+impl Unpack for i16 {
+    const UNPACK_LEN: usize = <u16 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        u16::unpack_from_slice::<E>(buf) as i16
+    }
+}
+
+impl Unpack for u32 {
+    const UNPACK_LEN: usize = std::mem::size_of::<Self>();
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        E::read_integer(&buf[0..4]) as u32
+    }
+}
+
+impl Unpack for i32 {
+    const UNPACK_LEN: usize = <u32 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        u32::unpack_from_slice::<E>(buf) as i32
+    }
+}
+
+impl Unpack for u64 {
+    const UNPACK_LEN: usize = std::mem::size_of::<Self>();
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        E::read_integer(&buf[0..8]) as u64
+    }
+}
+
+impl Unpack for i64 {
+    const UNPACK_LEN: usize = <u64 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        u64::unpack_from_slice::<E>(buf) as i64
+    }
+}
+
+impl Unpack for u128 {
+    const UNPACK_LEN: usize = std::mem::size_of::<Self>();
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        E::read_integer(&buf[0..16])
+    }
+}
+
+impl Unpack for i128 {
+    const UNPACK_LEN: usize = <u128 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        u128::unpack_from_slice::<E>(buf) as i128
+    }
+}
+
+impl Unpack for f32 {
+    const UNPACK_LEN: usize = <u32 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        f32::from_bits(u32::unpack_from_slice::<E>(buf))
+    }
+}
+
+impl Unpack for f64 {
+    const UNPACK_LEN: usize = <u64 as Unpack>::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        f64::from_bits(u64::unpack_from_slice::<E>(buf))
+    }
+}
+
+/// `bool` values unpack from a single byte, where any nonzero value is
+/// `true`.
+impl Unpack for bool {
+    const UNPACK_LEN: usize = 1;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        buf[0] != 0
+    }
+}
+
+/// IPv4 addresses unpack from four octets in network order, regardless of
+/// the reader's endianness.
+impl Unpack for std::net::Ipv4Addr {
+    const UNPACK_LEN: usize = 4;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self::new(buf[0], buf[1], buf[2], buf[3])
+    }
+}
+
+/// IPv6 addresses unpack from sixteen octets in network order, regardless
+/// of the reader's endianness.
+impl Unpack for std::net::Ipv6Addr {
+    const UNPACK_LEN: usize = 16;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let mut octets = [0_u8; 16];
+        octets.copy_from_slice(&buf[..16]);
+        Self::from(octets)
+    }
+}
+
+/// UUIDs unpack from the RFC 4122 layout, regardless of the reader's
+/// endianness.
+impl Unpack for crate::pack::Uuid {
+    const UNPACK_LEN: usize = 16;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        Self(u128::unpack_from_slice::<crate::endian::BigEndian>(buf))
+    }
+}
+
+/// GUIDs unpack from the Microsoft GUID layout, regardless of the reader's
+/// endianness.
+impl Unpack for crate::pack::Guid {
+    const UNPACK_LEN: usize = 16;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let mut bytes = [0_u8; 16];
+        bytes.copy_from_slice(&buf[..16]);
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        Self(u128::from_be_bytes(bytes))
+    }
+}
+
+/// `[T; N]` values unpack by unpacking each element of the array in sequence.
+impl<T, const N: usize> Unpack for [T; N]
+where
+    T: Unpack,
+{
+    const UNPACK_LEN: usize = N * T::UNPACK_LEN;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let elem_len = T::UNPACK_LEN;
+        std::array::from_fn(|i| {
+            let idx = elem_len * i;
+            T::unpack_from_slice::<E>(&buf[idx..idx + elem_len])
+        })
+    }
+}
+
+/// Implements [`Unpack`](Unpack) for a tuple type, unpacking each element
+/// in order.
+macro_rules! tuple_unpack {
+    ($($t:ident),+) => {
+        impl<$($t),+> Unpack for ($($t,)+)
+        where
+            $($t: Unpack,)+
+        {
+            const UNPACK_LEN: usize = 0 $(+ $t::UNPACK_LEN)+;
+
+            #[allow(unused_assignments)]
+            fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+                let mut buf = buf;
+                ($({
+                    let (elem, rest) = buf.split_at($t::UNPACK_LEN);
+                    buf = rest;
+                    $t::unpack_from_slice::<E>(elem)
+                },)+)
+            }
+        }
+    };
+}
+
+tuple_unpack!(T1);
+tuple_unpack!(T1, T2);
+tuple_unpack!(T1, T2, T3);
+tuple_unpack!(T1, T2, T3, T4);
+tuple_unpack!(T1, T2, T3, T4, T5);
+tuple_unpack!(T1, T2, T3, T4, T5, T6);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7, T8);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+tuple_unpack!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
@@ -0,0 +1,176 @@
+use crate::endian::Endian;
+use crate::pack::{FixedLenPack, Pack};
+use crate::unpack::Unpack;
+
+/// Packs an unsigned integer using the variable-length LEB128 encoding, as
+/// used in DWARF, WebAssembly, and elsewhere.
+///
+/// Varint encodings are independent of the writer's endianness.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Uleb128(pub u64);
+
+/// Packs a signed integer using the variable-length signed LEB128 encoding,
+/// as used in DWARF, WebAssembly, and elsewhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sleb128(pub i64);
+
+/// Packs a signed integer by first "zigzag" encoding it, so that values of
+/// small magnitude have small encodings regardless of sign, and then using
+/// the unsigned LEB128 encoding, as used by Protocol Buffers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZigZag(pub i64);
+
+/// Packs an unsigned integer using the MIDI-style variable-length quantity
+/// encoding, which is like [`Uleb128`](Uleb128) except that the most
+/// significant group of seven bits comes first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vlq(pub u64);
+
+/// Packs an unsigned integer using the LEB128 encoding padded to always
+/// occupy exactly `N` bytes, using redundant continuation bytes.
+///
+/// Because its length is fixed, `PaddedUleb128` can be used for deferred
+/// slots, as WebAssembly encoders do for section sizes that are updated once
+/// the section content is known. It can also be used as the size type
+/// for [`Writer::write_sized_subregion`](crate::Writer::write_sized_subregion).
+///
+/// Values that don't fit in `N * 7` bits will be truncated when packed.
+/// The [`TryFrom<u64>`](std::convert::TryFrom) implementation for this type
+/// returns an error for such values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PaddedUleb128<const N: usize>(pub u64);
+
+impl Pack for Uleb128 {
+    fn pack_len(&self) -> usize {
+        let mut v = self.0 >> 7;
+        let mut len = 1;
+        while v != 0 {
+            v >>= 7;
+            len += 1;
+        }
+        len
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let mut v = self.0;
+        for b in buf.iter_mut() {
+            *b = (v & 0x7f) as u8;
+            v >>= 7;
+            if v != 0 {
+                *b |= 0x80;
+            }
+        }
+    }
+}
+
+impl From<u64> for Uleb128 {
+    fn from(v: u64) -> Self {
+        Self(v)
+    }
+}
+
+impl Pack for Sleb128 {
+    fn pack_len(&self) -> usize {
+        let mut v = self.0;
+        let mut len = 0;
+        loop {
+            let b = v & 0x7f;
+            v >>= 7;
+            len += 1;
+            if (v == 0 && b & 0x40 == 0) || (v == -1 && b & 0x40 != 0) {
+                return len;
+            }
+        }
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let mut v = self.0;
+        let last = buf.len().saturating_sub(1);
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (v & 0x7f) as u8;
+            v >>= 7;
+            if i != last {
+                *b |= 0x80;
+            }
+        }
+    }
+}
+
+impl Pack for ZigZag {
+    fn pack_len(&self) -> usize {
+        self.as_uleb128().pack_len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        self.as_uleb128().pack_into_slice::<E>(buf)
+    }
+}
+
+impl ZigZag {
+    fn as_uleb128(&self) -> Uleb128 {
+        Uleb128(((self.0 << 1) ^ (self.0 >> 63)) as u64)
+    }
+}
+
+impl Pack for Vlq {
+    fn pack_len(&self) -> usize {
+        Uleb128(self.0).pack_len()
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        // We fill the buffer backwards here, so the first byte we write is
+        // the final byte, which is the only one without a continuation bit.
+        let mut v = self.0;
+        for (i, b) in buf.iter_mut().rev().enumerate() {
+            *b = (v & 0x7f) as u8;
+            v >>= 7;
+            if i != 0 {
+                *b |= 0x80;
+            }
+        }
+    }
+}
+
+impl<const N: usize> Pack for PaddedUleb128<N> {
+    fn pack_len(&self) -> usize {
+        <Self as FixedLenPack>::PACK_LEN
+    }
+
+    fn pack_into_slice<E: Endian>(&self, buf: &mut [u8]) {
+        let mut v = self.0;
+        let last = buf.len().saturating_sub(1);
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (v & 0x7f) as u8;
+            v >>= 7;
+            if i != last {
+                *b |= 0x80;
+            }
+        }
+    }
+}
+
+impl<const N: usize> FixedLenPack for PaddedUleb128<N> {
+    const PACK_LEN: usize = N;
+}
+
+impl<const N: usize> Unpack for PaddedUleb128<N> {
+    const UNPACK_LEN: usize = N;
+
+    fn unpack_from_slice<E: Endian>(buf: &[u8]) -> Self {
+        let v = buf.iter().enumerate().fold(0, |v, (i, b)| {
+            v | ((b & 0x7f) as u64).checked_shl(7 * i as u32).unwrap_or(0)
+        });
+        Self(v)
+    }
+}
+
+impl<const N: usize> std::convert::TryFrom<u64> for PaddedUleb128<N> {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        if N * 7 < 64 && (v >> (N * 7)) != 0 {
+            return Err(crate::pack::out_of_range_error());
+        }
+        Ok(Self(v))
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A stream over a borrowed byte vector, where seeking is just moving an
+/// index into the vector and writing is a slice copy, possibly extending
+/// the vector.
+///
+/// This is similar to [`Cursor`](std::io::Cursor) over a `&mut Vec<u8>`,
+/// but starts at the end of any existing content so that new data is
+/// appended, and implements the whole [`Write`](Write) interface with
+/// direct slice operations. Use [`append_vec_le`](crate::append_vec_le)
+/// or similar to write into a vector this way.
+///
+/// This crate doesn't depend on the `bytes` crate, so there's no
+/// equivalent for `BytesMut` or other `BufMut` targets. Write into a
+/// vector and then convert it, such as using `BytesMut::from`.
+#[derive(Debug)]
+pub struct VecBuf<'v> {
+    buf: &'v mut Vec<u8>,
+    pos: usize,
+}
+
+impl<'v> VecBuf<'v> {
+    /// Creates a stream over the given vector, positioned at the end of its
+    /// existing content.
+    pub fn new(buf: &'v mut Vec<u8>) -> Self {
+        let pos = buf.len();
+        Self { buf, pos }
+    }
+
+    /// Returns the current position as an index into the vector.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the vector's content.
+    pub fn get_ref(&self) -> &[u8] {
+        &self.buf[..]
+    }
+
+    fn put(&mut self, data: &[u8]) {
+        if self.pos > self.buf.len() {
+            self.buf.resize(self.pos, 0);
+        }
+        let overlap = data.len().min(self.buf.len() - self.pos);
+        self.buf[self.pos..self.pos + overlap].copy_from_slice(&data[..overlap]);
+        self.buf.extend_from_slice(&data[overlap..]);
+        self.pos += data.len();
+    }
+}
+
+impl<'v> Write for VecBuf<'v> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.put(data);
+        Ok(data.len())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.put(data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'v> Seek for VecBuf<'v> {
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match to {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(delta) => (self.buf.len() as u64, delta),
+            SeekFrom::Current(delta) => (self.pos as u64, delta),
+        };
+        let pos = base
+            .checked_add_signed(delta)
+            .and_then(|pos| <usize as std::convert::TryFrom<u64>>::try_from(pos).ok())
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        self.pos = pos;
+        Ok(pos as u64)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos as u64)
+    }
+}
+
+impl<'v> Read for VecBuf<'v> {
+    fn read(&mut self, into: &mut [u8]) -> std::io::Result<usize> {
+        let avail = self.buf.get(self.pos..).unwrap_or(&[]);
+        let n = into.len().min(avail.len());
+        into[..n].copy_from_slice(&avail[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
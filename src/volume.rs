@@ -0,0 +1,223 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// A stream split across a sequence of volumes, such as the parts of a
+/// multi-volume archive, each holding at most a fixed number of bytes.
+///
+/// Positions in a `Volumes` stream are global, counting from the start of
+/// the first volume, so a [`Writer`](crate::Writer) over it behaves as if
+/// it were writing a single stream. Writes that reach the end of a volume
+/// continue in the next one, which is opened on demand by calling the
+/// given function with its index. Use [`split`](split) to convert a global
+/// position into a volume number and an offset within that volume.
+///
+/// ```
+/// # use std::io::{Cursor, Result};
+/// # fn main() -> Result<()> {
+/// use binbin::volume::{self, Volumes};
+///
+/// let mut out = Volumes::new(4, |_| Ok(Cursor::new(Vec::<u8>::new())));
+/// binbin::write_le(&mut out, |w| {
+///     let next = w.write_deferred(0_u8)?;
+///     w.write(&b"abcdef"[..])?;
+///     let (vol, ofs) = volume::split(w.position()?, 4);
+///     assert_eq!((vol, ofs), (1, 3));
+///     w.resolve(next, ofs as u8)?;
+///     Ok(())
+/// })?;
+/// let parts: Vec<_> = out.into_volumes().into_iter().map(Cursor::into_inner).collect();
+/// assert_eq!(parts, vec![b"\x03abc".to_vec(), b"def".to_vec()]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Volumes<S, F> {
+    volumes: Vec<S>,
+
+    /// The length of the data written to each volume.
+    lens: Vec<u64>,
+
+    /// The position of each volume's own stream.
+    at: Vec<u64>,
+
+    cap: u64,
+    open: F,
+    pos: u64,
+}
+
+/// Returns the index of the volume containing the given global position,
+/// and the offset of that position within the volume, for volumes of
+/// `cap` bytes each.
+pub fn split(pos: u64, cap: u64) -> (usize, u64) {
+    ((pos / cap) as usize, pos % cap)
+}
+
+impl<S, F> Volumes<S, F>
+where
+    S: Write + Seek,
+    F: FnMut(usize) -> std::io::Result<S>,
+{
+    /// Creates a stream whose volumes each hold at most `cap` bytes, using
+    /// `open` to create each volume as it's needed.
+    ///
+    /// Panics if `cap` is zero.
+    pub fn new(cap: u64, open: F) -> Self {
+        assert!(cap > 0, "volume size must not be zero");
+        Self {
+            volumes: Vec::new(),
+            lens: Vec::new(),
+            at: Vec::new(),
+            cap,
+            open,
+            pos: 0,
+        }
+    }
+
+    /// Returns the maximum size of each volume.
+    pub fn cap(&self) -> u64 {
+        self.cap
+    }
+
+    /// Returns the number of volumes opened so far.
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+
+    /// Returns the total length of the data written across all volumes.
+    pub fn len(&self) -> u64 {
+        match self.lens.last() {
+            Some(len) => (self.lens.len() as u64 - 1) * self.cap + len,
+            None => 0,
+        }
+    }
+
+    /// Returns true if nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the current position as a volume index and an offset within
+    /// that volume.
+    pub fn position(&self) -> (usize, u64) {
+        split(self.pos, self.cap)
+    }
+
+    /// Returns the volumes, in order.
+    pub fn into_volumes(self) -> Vec<S> {
+        self.volumes
+    }
+
+    /// Returns the volume with the given index positioned at the given
+    /// offset, opening it and any volumes before it if necessary.
+    fn volume_at(&mut self, idx: usize, ofs: u64) -> std::io::Result<&mut S> {
+        while self.volumes.len() <= idx {
+            // Each volume before the last must be full, so that positions
+            // in later volumes are where they're expected to be.
+            if let Some(last) = self.volumes.len().checked_sub(1) {
+                let len = self.lens[last];
+                if len < self.cap {
+                    let zeros = [0_u8; 4096];
+                    let v = &mut self.volumes[last];
+                    v.seek(SeekFrom::Start(len))?;
+                    let mut remain = self.cap - len;
+                    while remain > 0 {
+                        let n = remain.min(zeros.len() as u64) as usize;
+                        v.write_all(&zeros[..n])?;
+                        remain -= n as u64;
+                    }
+                    self.lens[last] = self.cap;
+                    self.at[last] = self.cap;
+                }
+            }
+            let v = (self.open)(self.volumes.len())?;
+            self.volumes.push(v);
+            self.lens.push(0);
+            self.at.push(0);
+        }
+        if self.at[idx] != ofs {
+            self.volumes[idx].seek(SeekFrom::Start(ofs))?;
+            self.at[idx] = ofs;
+        }
+        Ok(&mut self.volumes[idx])
+    }
+}
+
+impl<S, F> Write for Volumes<S, F>
+where
+    S: Write + Seek,
+    F: FnMut(usize) -> std::io::Result<S>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (idx, ofs) = split(self.pos, self.cap);
+        let max = (self.cap - ofs).min(buf.len() as u64) as usize;
+        let v = self.volume_at(idx, ofs)?;
+        let result = v.write(&buf[..max]);
+        let n = match result {
+            Ok(n) => n,
+            Err(err) => {
+                // The volume's position is unknown after a failure.
+                self.at[idx] = u64::MAX;
+                return Err(err);
+            }
+        };
+        self.at[idx] = ofs + n as u64;
+        self.lens[idx] = self.lens[idx].max(ofs + n as u64);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for v in self.volumes.iter_mut() {
+            v.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<S, F> Seek for Volumes<S, F>
+where
+    S: Write + Seek,
+    F: FnMut(usize) -> std::io::Result<S>,
+{
+    /// Moves the current position. Seeking relative to the end is relative
+    /// to the end of the data written across all volumes.
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match to {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(delta) => (self.len(), delta),
+            SeekFrom::Current(delta) => (self.pos, delta),
+        };
+        self.pos = base
+            .checked_add_signed(delta)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<S, F> Read for Volumes<S, F>
+where
+    S: Read + Write + Seek,
+    F: FnMut(usize) -> std::io::Result<S>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (idx, ofs) = split(self.pos, self.cap);
+        let len = match self.lens.get(idx) {
+            Some(len) if *len > ofs => *len,
+            _ => return Ok(0),
+        };
+        let max = (len - ofs).min(buf.len() as u64) as usize;
+        let v = self.volume_at(idx, ofs)?;
+        let n = match v.read(&mut buf[..max]) {
+            Ok(n) => n,
+            Err(err) => {
+                self.at[idx] = u64::MAX;
+                return Err(err);
+            }
+        };
+        self.at[idx] = ofs + n as u64;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}